@@ -2,31 +2,43 @@
 //!
 //! Contains the entry point for the egui APP.
 
+mod history;
 mod preview;
 pub use preview::{render_task, RenderRequest};
 use resvg::usvg;
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs,
     hash::{self, DefaultHasher, Hash, Hasher},
     path::PathBuf,
     sync::{Arc, Mutex, RwLock},
-    time::Duration,
+    time::{Duration, Instant, SystemTime},
 };
 
 use egui::{
-    Align, Color32, Frame, Key, Label, Layout, Margin, Pos2, Rect, RichText, ScrollArea, Sense,
-    Slider, Stroke, TextEdit, Vec2, Visuals, WidgetText,
+    Align, Button, Color32, Frame, Key, Label, Layout, Margin, Pos2, Rect, RichText, ScrollArea,
+    Sense, Slider, Stroke, TextEdit, Vec2, Visuals, WidgetText,
 };
 use egui_dnd::{dnd, DragDropConfig};
 use egui_extras::{Size, StripBuilder};
-use preview::{DesignPreview, MAX_ZOOM_LEVEL, MIN_ZOOM_LEVEL};
+use history::UndoHistory;
+use preview::{
+    DesignPreview, DEFAULT_GRID_SPACING_MM, GRID_SPACING_OPTIONS_MM, MAX_ZOOM_LEVEL, MIN_ZOOM_LEVEL,
+};
 
 use seance::{
     cut_file, default_passes,
-    svg::{parse_svg, SVG_UNITS_PER_MM},
-    DesignFile, PrintDevice, SendToDeviceError, ToolPass, BED_HEIGHT_MM, BED_WIDTH_MM,
+    dxf::{parse_dxf_to_svg, DxfColourTable, DxfError},
+    frame_file,
+    svg::{
+        design_size_mm, get_paths_grouped_by_colour, GroupingKey, LayerVisibility, ParseWarnings,
+        StrokeWidth,
+    },
+    Colour, DesignFile, DeviceProfile, HpglError, JobMetadata, JobOptions, Machine,
+    MaterialLibrary, MaterialPreset, PathColour, PrintBed, PrintDevice, PrintDeviceCandidate,
+    SendToDeviceError, SerialFlowControl, SettingsFile, ToolPass, WriteRetry, DEFAULT_SERIAL_BAUD,
+    DEFAULT_TCP_PORT, MAX_TOOL_PASSES, SERIAL_BAUD_RATES,
 };
 
 /// `DesignFile` with a hash and original path attached.
@@ -38,9 +50,16 @@ const MINIMUM_DEFAULT_DESIGN_MOVE_STEP_MM: f32 = 0.1;
 const DEFAULT_DESIGN_MOVE_STEP_MM: f32 = 10.0;
 /// The maximum amount that designs can be moved by.
 const MAXIMUM_DESIGN_MOVE_STEP_MM: f32 = 500.0;
-
-#[cfg(target_os = "windows")]
-use crate::USBPort;
+/// The number of recently opened designs to remember.
+const MAX_RECENT_DESIGNS: usize = 10;
+/// The number of changes to the tool passes that can be undone.
+const MAX_UNDO_STATES: usize = 50;
+/// The most characters that [`all_capitalisations_of`] will capitalise, there are 2^n capitalisations of n characters.
+const MAX_CAPITALISED_LENGTH: u32 = 8;
+/// How often to check that the print device can be used. Network devices are checked by connecting to them.
+const PRINT_DEVICE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+/// The widest stroke that a tool pass can be set to cut, in mm.
+const MAX_STROKE_WIDTH_MM: f32 = 10.0;
 
 /// Data that is saved between uses of Seance.
 #[derive(serde::Deserialize, serde::Serialize)]
@@ -53,6 +72,46 @@ struct PersistentStorage {
     print_device: PrintDevice,
     /// How much to move the design by each time a movement button is pressed.
     design_move_step_mm: f32,
+    /// The saved tool passes for each material.
+    #[serde(default)]
+    material_presets: Vec<MaterialPreset>,
+    /// The designs that have been opened recently, most recent first.
+    #[serde(default)]
+    recent_designs: Vec<PathBuf>,
+    /// The design that was open when Seance was closed, if any.
+    #[serde(default)]
+    last_design: Option<PathBuf>,
+    /// Where the design was on the bed when Seance was closed, in mm.
+    #[serde(default)]
+    design_offset_mm: (f32, f32),
+    /// The zoom level of the design preview when Seance was closed.
+    #[serde(default = "default_preview_zoom_level")]
+    preview_zoom_level: f32,
+    /// The distance between the markers drawn across the bed in the design preview in mm, `None` if they are hidden.
+    #[serde(default = "default_preview_grid_spacing_mm")]
+    preview_grid_spacing_mm: Option<f32>,
+    /// What paths are grouped by to choose the tool pass that cuts them.
+    #[serde(default)]
+    grouping: GroupingKey,
+    /// The bed of the laser cutter that jobs are cut on.
+    #[serde(default)]
+    bed: PrintBed,
+}
+
+/// The zoom level to use when there isn't one saved.
+///
+/// # Returns
+/// The zoom level.
+fn default_preview_zoom_level() -> f32 {
+    MIN_ZOOM_LEVEL
+}
+
+/// The grid spacing to use when there isn't one saved.
+///
+/// # Returns
+/// The grid spacing, in mm.
+fn default_preview_grid_spacing_mm() -> Option<f32> {
+    Some(DEFAULT_GRID_SPACING_MM)
 }
 
 /// The Seance UI app.
@@ -61,11 +120,17 @@ pub struct Seance {
     dark_mode: bool,
     /// The tool passes to run on the machine.
     passes: Vec<ToolPass>,
+    /// What paths are grouped by to choose the tool pass that cuts them.
+    grouping: GroupingKey,
     /// The print device configuration.
     print_device: PrintDevice,
+    /// The laser cutter that jobs are cut on.
+    machine: Machine,
 
     /// The currently open design file, if any.
     design_file: Arc<RwLock<Option<DesignWithMeta>>>,
+    /// The colours of the paths in the currently open design, empty if there isn't one.
+    design_colours: HashSet<PathColour>,
     /// The message channel that will receive UI events.
     ui_message_tx: UIMessageTx,
     /// The message channel that UI events will be sent into.
@@ -76,6 +141,15 @@ pub struct Seance {
     hasher: Box<dyn Hasher>,
     /// Amount to move the design by when moving.
     design_move_step_mm: f32,
+    /// The saved tool passes for each material.
+    material_presets: Vec<MaterialPreset>,
+    /// The designs that have been opened recently, most recent first.
+    recent_designs: Vec<PathBuf>,
+
+    /// Previous tool passes, so that changes to them can be undone.
+    pass_history: UndoHistory<Vec<ToolPass>>,
+    /// The tool pass whose name was the last change made, so that typing a name is undone all at once.
+    renaming_pass: Option<usize>,
 
     /// The states of all of the tool pass widgets.
     tool_pass_widget_states: Vec<ToolPassWidgetState>,
@@ -84,6 +158,14 @@ pub struct Seance {
     previous_frame_widgets: HashMap<egui::Id, SeanceUIElement>,
     /// The zoom level of the design preview.
     preview_zoom_level: f32,
+    /// Where to put the design once the design preview has been created, used to put it back where it was last time.
+    restored_design_offset_mm: Option<Vec2>,
+    /// Whether the design preview shows all paths, rather than just those that will be cut.
+    preview_show_all_paths: bool,
+    /// The distance between the markers drawn across the bed in the design preview in mm, `None` if they are hidden.
+    preview_grid_spacing_mm: Option<f32>,
+    /// The index of the tool pass whose colour is being picked from the design preview, if any.
+    picking_colour_for: Option<usize>,
 
     /// The file dialog that is currently open, if any.
     /// Used for e.g. opening files/saving files.
@@ -96,24 +178,61 @@ pub struct Seance {
     design_preview_image: Option<DesignPreview>,
     /// The settings dialog, if it is currently open.
     settings_dialog: Option<SettingsDialogState>,
+    /// The name being entered for a new material preset, if the save preset dialog is open.
+    save_preset_dialog: Option<String>,
+    /// How the last job sent to the laser cutter went.
+    send_status: SendStatus,
+    /// Whether the print device could be used when it was last checked.
+    print_device_valid: bool,
+    /// When the print device was last checked, `None` if it should be checked straight away.
+    print_device_checked_at: Option<Instant>,
+    /// Whether the print device is being checked in the background.
+    checking_print_device: bool,
+}
+
+/// How the last job sent to the laser cutter went.
+#[derive(Debug, Default, Clone, PartialEq)]
+enum SendStatus {
+    /// Nothing has been sent yet.
+    #[default]
+    Idle,
+    /// A job is being sent to the laser cutter in the background.
+    Sending,
+    /// The last job was sent.
+    Sent {
+        /// The ID that the device gave the job, if it gives jobs IDs.
+        job_id: Option<String>,
+    },
+    /// The last job could not be sent, the error has been shown to the user.
+    Failed,
 }
 
 /// The state of the settings dialog. Data here is ephemiral and must explicitly be saved when required.
 struct SettingsDialogState {
     /// The device that we will be using to "print" the design.
     print_device: PrintDevice,
+    /// The bed of the laser cutter that jobs are cut on.
+    bed: PrintBed,
+    /// The devices that were found when the dialog was opened or last refreshed, for the user to pick from.
+    /// Finding them can mean asking CUPS, so it isn't done every time the dialog is drawn.
+    found_devices: Vec<PrintDeviceCandidate>,
 }
 
 impl SettingsDialogState {
-    /// Creates a new [`SettingsDialogState`].
+    /// Creates a new [`SettingsDialogState`], finding the devices that can be picked.
     ///
     /// # Arguments
     /// * `print_device`: The device to print to.
+    /// * `bed`: The bed of the laser cutter that jobs are cut on.
     ///
     /// # Returns
     /// A new [`SettingsDialogState`].
-    fn new(print_device: PrintDevice) -> Self {
-        Self { print_device }
+    fn new(print_device: PrintDevice, bed: PrintBed) -> Self {
+        Self {
+            print_device,
+            bed,
+            found_devices: PrintDevice::enumerate(),
+        }
     }
 }
 
@@ -145,6 +264,14 @@ impl Seance {
                     passes: default_pens,
                     print_device: PrintDevice::default(),
                     design_move_step_mm: DEFAULT_DESIGN_MOVE_STEP_MM,
+                    material_presets: MaterialLibrary::built_in().presets,
+                    recent_designs: vec![],
+                    last_design: None,
+                    design_offset_mm: (0.0, 0.0),
+                    preview_zoom_level: MIN_ZOOM_LEVEL,
+                    preview_grid_spacing_mm: default_preview_grid_spacing_mm(),
+                    grouping: GroupingKey::default(),
+                    bed: PrintBed::default(),
                 });
             if seance_storage.dark_mode {
                 cc.egui_ctx.set_visuals(Visuals::dark());
@@ -152,65 +279,202 @@ impl Seance {
                 cc.egui_ctx.set_visuals(Visuals::light());
             }
 
-            let laser_pass_widget_states: Vec<ToolPassWidgetState> = seance_storage
-                .passes
-                .iter()
-                .map(|pass| {
-                    ToolPassWidgetState::new(Default::default(), pass.power(), pass.speed())
-                })
-                .collect::<Vec<_>>();
+            let laser_pass_widget_states = tool_pass_widget_states(&seance_storage.passes);
+
+            // Pick up where we left off, the design is put in place once the first UI messages are handled.
+            let mut hasher: Box<dyn Hasher> = Box::new(DefaultHasher::new());
+            let mut restored_design_offset_mm = None;
+            if let Some(path) = &seance_storage.last_design {
+                match load_design(path, &seance_storage.bed, &mut hasher) {
+                    Ok(file) => {
+                        let (x, y) = seance_storage.design_offset_mm;
+                        restored_design_offset_mm = Some(Vec2::new(x, y));
+                        let _ =
+                            ui_message_tx.send(UIMessage::DesignFileChanged { design_file: file });
+                    }
+                    Err(err) => {
+                        let _ = ui_message_tx.send(UIMessage::ShowError {
+                            error: format!(
+                                "Could not reopen the last design, '{}'",
+                                path.to_string_lossy()
+                            ),
+                            details: Some(err),
+                        });
+                    }
+                }
+            }
 
             return Seance {
                 dark_mode: seance_storage.dark_mode,
                 passes: seance_storage.passes,
+                grouping: seance_storage.grouping,
                 print_device: seance_storage.print_device,
+                machine: Machine::new(seance_storage.bed, DeviceProfile::default()),
 
                 design_file: Default::default(),
+                design_colours: HashSet::new(),
                 ui_message_tx,
                 ui_message_rx,
                 render_request,
-                hasher: Box::new(DefaultHasher::new()),
+                hasher,
                 design_move_step_mm: seance_storage.design_move_step_mm,
+                material_presets: seance_storage.material_presets,
+                recent_designs: seance_storage.recent_designs,
+
+                pass_history: UndoHistory::new(MAX_UNDO_STATES),
+                renaming_pass: None,
 
                 tool_pass_widget_states: laser_pass_widget_states,
                 previous_frame_widgets: Default::default(),
-                preview_zoom_level: MIN_ZOOM_LEVEL,
+                preview_zoom_level: seance_storage
+                    .preview_zoom_level
+                    .clamp(MIN_ZOOM_LEVEL, MAX_ZOOM_LEVEL),
+                restored_design_offset_mm,
+                preview_show_all_paths: false,
+                preview_grid_spacing_mm: seance_storage.preview_grid_spacing_mm,
+                picking_colour_for: None,
                 file_dialog: None,
                 current_error: None,
                 design_preview_image: None,
                 settings_dialog: None,
+                save_preset_dialog: None,
+                send_status: SendStatus::Idle,
+                print_device_valid: false,
+                print_device_checked_at: None,
+                checking_print_device: false,
             };
         }
 
-        let laser_passes_widget_states: Vec<ToolPassWidgetState> = default_pens
-            .iter()
-            .map(|pass| ToolPassWidgetState::new(Default::default(), pass.power(), pass.speed()))
-            .collect::<Vec<_>>();
+        let laser_passes_widget_states = tool_pass_widget_states(&default_pens);
 
         Seance {
             dark_mode: cc.egui_ctx.style().visuals.dark_mode,
             passes: default_pens,
+            grouping: GroupingKey::default(),
             print_device: PrintDevice::default(),
+            machine: Machine::default(),
 
             design_file: Default::default(),
+            design_colours: HashSet::new(),
             ui_message_tx,
             ui_message_rx,
             render_request,
             hasher: Box::new(DefaultHasher::new()),
             design_move_step_mm: DEFAULT_DESIGN_MOVE_STEP_MM,
+            material_presets: MaterialLibrary::built_in().presets,
+            recent_designs: vec![],
+
+            pass_history: UndoHistory::new(MAX_UNDO_STATES),
+            renaming_pass: None,
 
             tool_pass_widget_states: laser_passes_widget_states,
             previous_frame_widgets: Default::default(),
             preview_zoom_level: MIN_ZOOM_LEVEL,
+            restored_design_offset_mm: None,
+            preview_show_all_paths: false,
+            preview_grid_spacing_mm: default_preview_grid_spacing_mm(),
+            picking_colour_for: None,
             file_dialog: None,
             current_error: None,
             design_preview_image: None,
             settings_dialog: None,
+            save_preset_dialog: None,
+            send_status: SendStatus::Idle,
+            print_device_valid: false,
+            print_device_checked_at: None,
+            checking_print_device: false,
+        }
+    }
+
+    /// Sends a job for the current design to the laser cutter on a background thread, so that the UI
+    /// doesn't freeze while the device is busy. Nothing is sent if there is no design or a job is already being sent.
+    ///
+    /// # Arguments
+    /// * `send`: Sends the job, given the design, tool passes, print device and offset of the design.
+    fn send_in_background(
+        &mut self,
+        send: impl FnOnce(
+                &DesignFile,
+                &Vec<ToolPass>,
+                &PrintDevice,
+                (f32, f32),
+            ) -> Result<Option<String>, SendToDeviceError>
+            + Send
+            + 'static,
+    ) {
+        if self.send_status == SendStatus::Sending {
+            return;
+        }
+
+        // The design is copied so that a new design can be loaded while this one is being sent.
+        let design_file = match self.design_file.read() {
+            Ok(design_lock) => design_lock.as_ref().map(|file| file.0.clone()),
+            Err(_) => None,
+        };
+        let Some(design_file) = design_file else {
+            return;
+        };
+        let passes = self.passes.clone();
+        let print_device = self.print_device.clone();
+        let offset = self
+            .design_preview_image
+            .as_ref()
+            .map(|preview| *preview.get_design_offset())
+            .unwrap_or_default();
+        let ui_message_tx = self.ui_message_tx.clone();
+
+        self.send_status = SendStatus::Sending;
+        let _ = std::thread::spawn(move || {
+            let result = send(&design_file, &passes, &print_device, (offset.x, offset.y));
+
+            let (succeeded, job_id) = match result {
+                Ok(job_id) => (true, job_id),
+                Err(err) => {
+                    handle_cut_file_error(err, &ui_message_tx);
+                    (false, None)
+                }
+            };
+            let _ = ui_message_tx.send(UIMessage::SendFinished { succeeded, job_id });
+        });
+    }
+
+    /// Checks whether the print device can be used on a background thread, as checking a network device
+    /// means connecting to it. The device is checked every [`PRINT_DEVICE_CHECK_INTERVAL`].
+    ///
+    /// # Arguments
+    /// * `ctx`: The egui context, which is repainted once the device has been checked.
+    fn check_print_device(&mut self, ctx: &egui::Context) {
+        if self.checking_print_device {
+            return;
+        }
+        if let Some(checked_at) = self.print_device_checked_at {
+            let since_check = checked_at.elapsed();
+            if since_check < PRINT_DEVICE_CHECK_INTERVAL {
+                ctx.request_repaint_after(PRINT_DEVICE_CHECK_INTERVAL - since_check);
+                return;
+            }
         }
+
+        self.checking_print_device = true;
+        let print_device = self.print_device.clone();
+        let ui_message_tx = self.ui_message_tx.clone();
+        let ctx = ctx.clone();
+        let _ = std::thread::spawn(move || {
+            let valid = print_device.is_valid();
+            let _ = ui_message_tx.send(UIMessage::PrintDeviceChecked { valid });
+            ctx.request_repaint();
+        });
     }
 
     fn handle_ui_messages(&mut self, ctx: &egui::Context) {
         while let Ok(msg) = self.ui_message_rx.try_recv() {
+            let passes_before = self.passes.clone();
+            let is_undo_or_redo = matches!(msg, UIMessage::Undo | UIMessage::Redo);
+            let renaming_pass = match msg {
+                UIMessage::ToolPassNameChanged { index, .. } => Some(index),
+                _ => None,
+            };
+
             match msg {
                 UIMessage::ShowOpenFileDialog => {
                     if self.file_dialog.is_none() {
@@ -218,7 +482,11 @@ impl Seance {
                         let _ = std::thread::spawn(|| {
                             let file = rfd::FileDialog::new()
                                 .set_title("Select Design File")
-                                .add_filter("Supported Files", &all_capitalisations_of("svg"))
+                                .add_filter(
+                                    "Supported Files",
+                                    &[all_capitalisations_of("svg"), all_capitalisations_of("dxf")]
+                                        .concat(),
+                                )
                                 .add_filter("All Files", &["*"])
                                 .pick_file();
                             let _ = tx.send(file);
@@ -259,7 +527,9 @@ impl Seance {
                                 path.set_extension("json");
                             }
 
-                            if let Ok(json_string) = serde_json::to_string(&passes) {
+                            if let Ok(json_string) =
+                                serde_json::to_string(&SettingsFile::new(passes))
+                            {
                                 if let Err(err) = fs::write(path, json_string) {
                                     let _ = ui_message_tx.send(UIMessage::ShowError {
                                         error: "Could not open export dialog".to_string(),
@@ -280,22 +550,94 @@ impl Seance {
                     let _ = self.current_error.take();
                 }
                 UIMessage::ShowSettingsDialog => {
-                    self.settings_dialog = Some(SettingsDialogState::new(self.print_device.clone()))
+                    self.settings_dialog = Some(SettingsDialogState::new(
+                        self.print_device.clone(),
+                        self.machine.bed().clone(),
+                    ))
                 }
                 UIMessage::PrinterSettingsChanged { printer } => {
                     if let Some(dialog) = &mut self.settings_dialog {
                         dialog.print_device = printer;
                     }
                 }
+                UIMessage::BedSettingsChanged { bed } => {
+                    if let Some(dialog) = &mut self.settings_dialog {
+                        dialog.bed = bed;
+                    }
+                }
+                UIMessage::RefreshPrintDevices => {
+                    if let Some(dialog) = &mut self.settings_dialog {
+                        dialog.found_devices = PrintDevice::enumerate();
+                    }
+                }
                 UIMessage::SaveSettings => {
                     if let Some(dialog) = &self.settings_dialog {
                         self.print_device = dialog.print_device.clone();
+                        self.print_device_checked_at = None;
+                        self.machine =
+                            Machine::new(dialog.bed.clone(), self.machine.profile().clone());
+                        if let Some(preview) = &mut self.design_preview_image {
+                            preview.set_bed(self.machine.bed(), &self.design_file);
+                        }
                     }
                 }
                 UIMessage::CloseSettingsDialog => {
                     self.settings_dialog = None;
                 }
+                UIMessage::ShowSavePresetDialog => {
+                    self.save_preset_dialog = Some(String::new());
+                }
+                UIMessage::SavePresetNameChanged { name } => {
+                    if let Some(dialog) = &mut self.save_preset_dialog {
+                        *dialog = name;
+                    }
+                }
+                UIMessage::SavePreset => {
+                    let Some(name) = self.save_preset_dialog.take() else {
+                        continue;
+                    };
+                    let name = name.trim().to_string();
+                    if name.is_empty() {
+                        self.save_preset_dialog = Some(name);
+                        continue;
+                    }
+
+                    // Saving with the name of an existing preset replaces it.
+                    let preset = MaterialPreset::new(name, None, self.passes.clone());
+                    if let Some(existing) = self
+                        .material_presets
+                        .iter_mut()
+                        .find(|existing| existing.name == preset.name)
+                    {
+                        *existing = preset;
+                    } else {
+                        self.material_presets.push(preset);
+                    }
+                }
+                UIMessage::CloseSavePresetDialog => {
+                    self.save_preset_dialog = None;
+                }
+                UIMessage::OpenRecentDesign { path } => {
+                    match load_design(&path, self.machine.bed(), &mut self.hasher) {
+                        Ok(file) => {
+                            let _ = self
+                                .ui_message_tx
+                                .send(UIMessage::DesignFileChanged { design_file: file });
+                        }
+                        Err(err) => {
+                            if !path.exists() {
+                                self.recent_designs.retain(|recent| recent != &path);
+                            }
+                            let _ = self.ui_message_tx.send(UIMessage::ShowError {
+                                error: "Failed to load design".to_string(),
+                                details: Some(err),
+                            });
+                        }
+                    }
+                }
                 UIMessage::DesignFileChanged { design_file } => {
+                    add_recent_design(&mut self.recent_designs, design_file.2.clone());
+
                     let Ok(mut design_lock) = self.design_file.write() else {
                         let _ = self.ui_message_tx.send(UIMessage::ShowError {
                             error: "Could not store design file".to_string(),
@@ -304,14 +646,60 @@ impl Seance {
                         continue;
                     };
 
+                    self.design_colours = get_paths_grouped_by_colour(&design_file.0.tree)
+                        .map(|paths| paths.into_keys().collect())
+                        .unwrap_or_default();
+                    // The design is still loaded, so that it can be scaled down or only partly cut.
+                    if let Some(overflow) = describe_bed_overflow(
+                        self.machine.bed(),
+                        design_file.0.width_mm,
+                        design_file.0.height_mm,
+                    ) {
+                        let _ = self.ui_message_tx.send(UIMessage::ShowError {
+                            error: "Design is larger than the bed".to_string(),
+                            details: Some(overflow),
+                        });
+                    }
                     *design_lock = Some(design_file);
                     if let Some(preview) = &mut self.design_preview_image {
                         preview.render(&self.design_file);
                     }
                 }
                 UIMessage::ToolPassesListChanged { passes } => {
+                    self.tool_pass_widget_states = tool_pass_widget_states(&passes);
                     self.passes = passes;
                 }
+                UIMessage::AddToolPass => {
+                    if self.passes.len() < MAX_TOOL_PASSES {
+                        // New passes start disabled, so that nothing extra is cut until they have been set up.
+                        let mut pass = ToolPass::new(
+                            format!("Pass {}", self.passes.len() + 1),
+                            0,
+                            0,
+                            0,
+                            100,
+                            20,
+                            false,
+                            400,
+                        );
+                        pass.set_enabled(false);
+                        self.tool_pass_widget_states.push(ToolPassWidgetState::new(
+                            Default::default(),
+                            pass.power(),
+                            pass.speed(),
+                        ));
+                        self.passes.push(pass);
+                    }
+                }
+                UIMessage::RemoveToolPass { index } => {
+                    // There must always be at least one pass.
+                    if self.passes.len() > 1 && index < self.passes.len() {
+                        self.passes.remove(index);
+                        self.tool_pass_widget_states.remove(index);
+                        // The pass being picked for may have moved, so stop rather than set the wrong one.
+                        self.picking_colour_for = None;
+                    }
+                }
                 UIMessage::ToolPassNameChanged { index, name } => {
                     if let Some(pass) = self.passes.get_mut(index) {
                         pass.set_name(name);
@@ -332,6 +720,12 @@ impl Seance {
                         pass.set_colour(colour);
                     }
                 }
+                UIMessage::StartPickingToolPassColour { index } => {
+                    self.picking_colour_for = Some(index);
+                }
+                UIMessage::StopPickingToolPassColour => {
+                    self.picking_colour_for = None;
+                }
                 UIMessage::ToolPassNameClicked { index } => {
                     if let Some(pass) = self.tool_pass_widget_states.get_mut(index) {
                         pass.editing = ToolPassWidgetEditing::Name;
@@ -376,24 +770,58 @@ impl Seance {
                         pass.set_enabled(enabled);
                     }
                 }
+                UIMessage::ToolPassGroupChanged { index, group } => {
+                    if let Some(pass) = self.passes.get_mut(index) {
+                        pass.set_group(group);
+                    }
+                }
+                UIMessage::ToolPassStrokeWidthChanged {
+                    index,
+                    stroke_width,
+                } => {
+                    if let Some(pass) = self.passes.get_mut(index) {
+                        pass.set_stroke_width(stroke_width);
+                    }
+                }
+                UIMessage::GroupingChanged { grouping } => {
+                    self.grouping = grouping;
+                }
                 UIMessage::PreviewZoomLevelChanged { zoom } => {
                     self.preview_zoom_level = zoom.min(MAX_ZOOM_LEVEL).max(MIN_ZOOM_LEVEL);
                     if let Some(preview) = &mut self.design_preview_image {
                         preview.zoom(self.preview_zoom_level);
                     }
                 }
+                UIMessage::PreviewShowAllPathsChanged { show_all_paths } => {
+                    self.preview_show_all_paths = show_all_paths;
+                    if let Some(preview) = &mut self.design_preview_image {
+                        preview.set_show_all_paths(show_all_paths, &self.design_file);
+                    }
+                }
+                UIMessage::PreviewGridSpacingChanged { grid_spacing_mm } => {
+                    self.preview_grid_spacing_mm = grid_spacing_mm;
+                    if let Some(preview) = &mut self.design_preview_image {
+                        preview.set_grid_spacing(grid_spacing_mm);
+                    }
+                }
                 UIMessage::DesignPreviewSize { size_before_wrap } => {
                     let resize = self.design_preview_image.is_some();
                     let preview = self.design_preview_image.get_or_insert_with(|| {
                         DesignPreview::new(
                             size_before_wrap,
+                            self.machine.bed().clone(),
                             self.preview_zoom_level,
                             &self.design_file,
+                            &self.passes,
+                            self.preview_show_all_paths,
+                            self.preview_grid_spacing_mm,
                             self.render_request.clone(),
                         )
                     });
                     if resize {
                         preview.resize(size_before_wrap, &self.design_file);
+                    } else if let Some(offset_mm) = self.restored_design_offset_mm.take() {
+                        preview.set_design_offset(offset_mm);
                     }
                 }
                 UIMessage::DesignMoveStepChanged { step } => {
@@ -402,12 +830,72 @@ impl Seance {
                 UIMessage::MoveDesign { direction, step } => {
                     if let Some(preview) = &mut self.design_preview_image {
                         let new_offset = direction.apply(preview.get_design_offset(), step);
-                        preview.set_design_offset(new_offset, &self.design_file);
+                        preview.set_design_offset(new_offset);
+                    }
+                }
+                UIMessage::DesignOffsetChanged { offset_mm } => {
+                    if let Some(preview) = &mut self.design_preview_image {
+                        preview.set_design_offset(offset_mm);
                     }
                 }
+                UIMessage::SendToLaser => {
+                    let grouping = self.grouping;
+                    let machine = self.machine.clone();
+                    self.send_in_background(move |design_file, passes, print_device, offset| {
+                        let metadata = JobMetadata {
+                            name: design_file.name.clone(),
+                            author: std::env::var("USER")
+                                .or_else(|_| std::env::var("USERNAME"))
+                                .ok(),
+                            submitted_at: SystemTime::now(),
+                        };
+                        cut_file(
+                            design_file,
+                            passes,
+                            print_device,
+                            &machine,
+                            &JobOptions {
+                                offset,
+                                grouping,
+                                metadata: Some(metadata),
+                                ..JobOptions::default()
+                            },
+                        )
+                    });
+                }
+                UIMessage::FrameDesign => {
+                    let grouping = self.grouping;
+                    let machine = self.machine.clone();
+                    self.send_in_background(move |design_file, passes, print_device, offset| {
+                        frame_file(
+                            design_file,
+                            passes,
+                            print_device,
+                            &machine,
+                            &JobOptions {
+                                offset,
+                                grouping,
+                                ..JobOptions::default()
+                            },
+                            1,
+                        )
+                    });
+                }
+                UIMessage::PrintDeviceChecked { valid } => {
+                    self.print_device_valid = valid;
+                    self.print_device_checked_at = Some(Instant::now());
+                    self.checking_print_device = false;
+                }
+                UIMessage::SendFinished { succeeded, job_id } => {
+                    self.send_status = if succeeded {
+                        SendStatus::Sent { job_id }
+                    } else {
+                        SendStatus::Failed
+                    };
+                }
                 UIMessage::ResetDesignPosition => {
                     if let Some(preview) = &mut self.design_preview_image {
-                        preview.set_design_offset(Default::default(), &self.design_file);
+                        preview.set_design_offset(Default::default());
                     }
                 }
                 UIMessage::EnterKeyPressed => {
@@ -434,8 +922,33 @@ impl Seance {
                         &self.ui_message_tx,
                     );
                 }
+                UIMessage::Undo => {
+                    if self.pass_history.undo(&mut self.passes) {
+                        self.tool_pass_widget_states = tool_pass_widget_states(&self.passes);
+                    }
+                    self.renaming_pass = None;
+                }
+                UIMessage::Redo => {
+                    if self.pass_history.redo(&mut self.passes) {
+                        self.tool_pass_widget_states = tool_pass_widget_states(&self.passes);
+                    }
+                    self.renaming_pass = None;
+                }
+            }
+
+            if !is_undo_or_redo && self.passes != passes_before {
+                // Every key typed into a name is a change, only remember the name from before typing started.
+                if renaming_pass.is_none() || renaming_pass != self.renaming_pass {
+                    self.pass_history.record(passes_before);
+                }
+                self.renaming_pass = renaming_pass;
             }
         }
+
+        // Keep the preview's travel lines in sync with the passes, this only re-renders if they've changed.
+        if let Some(preview) = &mut self.design_preview_image {
+            preview.set_tool_passes(&self.passes, &self.design_file);
+        }
     }
 }
 
@@ -449,14 +962,38 @@ impl eframe::App for Seance {
                 passes: self.passes.clone(),
                 print_device: self.print_device.clone(),
                 design_move_step_mm: self.design_move_step_mm,
+                material_presets: self.material_presets.clone(),
+                recent_designs: self.recent_designs.clone(),
+                last_design: self
+                    .design_file
+                    .read()
+                    .ok()
+                    .and_then(|design| design.as_ref().map(|(_, _, path)| path.clone())),
+                design_offset_mm: self
+                    .design_preview_image
+                    .as_ref()
+                    .map(|preview| *preview.get_design_offset())
+                    .or(self.restored_design_offset_mm)
+                    .map(|offset| (offset.x, offset.y))
+                    .unwrap_or_default(),
+                preview_zoom_level: self.preview_zoom_level,
+                preview_grid_spacing_mm: self.preview_grid_spacing_mm,
+                grouping: self.grouping,
+                bed: self.machine.bed().clone(),
             },
         );
     }
 
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         self.handle_ui_messages(ctx);
-
-        if !FileDialog::poll(&self.file_dialog, &self.ui_message_tx, &mut self.hasher) {
+        self.check_print_device(ctx);
+
+        if !FileDialog::poll(
+            &self.file_dialog,
+            self.machine.bed(),
+            &self.ui_message_tx,
+            &mut self.hasher,
+        ) {
             let _ = self.file_dialog.take();
         }
 
@@ -468,6 +1005,10 @@ impl eframe::App for Seance {
             settings_dialog(ctx, &self.ui_message_tx, settings);
         }
 
+        if let Some(name) = &self.save_preset_dialog {
+            save_preset_dialog(ctx, &self.ui_message_tx, name);
+        }
+
         self.previous_frame_widgets = Default::default();
 
         // Slow down key presses to make typing bearable.
@@ -479,6 +1020,27 @@ impl eframe::App for Seance {
                 let is_web = cfg!(target_arch = "wasm32");
                 if !is_web {
                     ui.menu_button("File", |ui| {
+                        ui.add_enabled_ui(!self.recent_designs.is_empty(), |ui| {
+                            ui.menu_button("Open Recent", |ui| {
+                                for path in &self.recent_designs {
+                                    // Designs that have been moved or deleted are shown, but can't be opened.
+                                    if ui
+                                        .add_enabled(
+                                            path.exists(),
+                                            Button::new(path.to_string_lossy()),
+                                        )
+                                        .clicked()
+                                    {
+                                        let _ =
+                                            self.ui_message_tx.send(UIMessage::OpenRecentDesign {
+                                                path: path.clone(),
+                                            });
+                                        ui.close_menu();
+                                    }
+                                }
+                            });
+                        });
+
                         if ui.button("Settings").clicked() {
                             let _ = self.ui_message_tx.send(UIMessage::ShowSettingsDialog);
                         }
@@ -498,6 +1060,8 @@ impl eframe::App for Seance {
             });
         });
 
+        // Passes can be reordered by dragging them, which changes them directly rather than through a message.
+        let passes_before = self.passes.clone();
         egui::CentralPanel::default().show(ctx, |ui| {
             StripBuilder::new(ui)
                 .size(Size::exact(20.0))
@@ -515,14 +1079,9 @@ impl eframe::App for Seance {
                                 toolbar_widget(
                                     ui,
                                     &self.design_file,
-                                    &self.passes,
-                                    &self.print_device,
-                                    &self
-                                        .design_preview_image
-                                        .as_ref()
-                                        .map(|preview| preview.get_design_offset())
-                                        .cloned()
-                                        .unwrap_or_default(),
+                                    &self.material_presets,
+                                    self.print_device_valid,
+                                    &self.send_status,
                                     &self.ui_message_tx,
                                 );
                             });
@@ -531,24 +1090,37 @@ impl eframe::App for Seance {
                         ui_main(
                             ui,
                             &mut self.passes,
+                            self.grouping,
                             &mut self.tool_pass_widget_states,
                             &mut self.previous_frame_widgets,
+                            self.machine.bed(),
                             &self.design_file,
+                            &self.design_colours,
                             &mut self.design_preview_image,
                             self.preview_zoom_level,
+                            self.preview_show_all_paths,
+                            self.preview_grid_spacing_mm,
+                            self.picking_colour_for,
                             self.design_move_step_mm,
                             &self.ui_message_tx,
                         );
                     });
                 });
         });
+        if self.passes != passes_before {
+            self.pass_history.record(passes_before);
+            self.renaming_pass = None;
+        }
+
+        // Text boxes have their own undo, so only undo changes to the passes when nothing is being typed.
+        let is_typing = ctx.wants_keyboard_input();
 
         // Handle events.
         ctx.input(|i| {
             // Handle dropped files.
             if !i.raw.dropped_files.is_empty() {
                 if let Some(path) = &i.raw.dropped_files[0].path {
-                    match load_design(path, &mut self.hasher) {
+                    match load_design(path, self.machine.bed(), &mut self.hasher) {
                         Ok(file) => {
                             let _ = self
                                 .ui_message_tx
@@ -575,8 +1147,27 @@ impl eframe::App for Seance {
             if i.key_pressed(Key::Space) {
                 let _ = self.ui_message_tx.send(UIMessage::SpaceKeyPressed);
             }
+
+            if i.key_pressed(Key::Escape) {
+                let _ = self
+                    .ui_message_tx
+                    .send(UIMessage::StopPickingToolPassColour);
+            }
+
+            if !is_typing && i.modifiers.command {
+                if i.key_pressed(Key::Y) || (i.modifiers.shift && i.key_pressed(Key::Z)) {
+                    let _ = self.ui_message_tx.send(UIMessage::Redo);
+                } else if i.key_pressed(Key::Z) {
+                    let _ = self.ui_message_tx.send(UIMessage::Undo);
+                }
+            }
         });
 
+        // Keep redrawing while a job is being sent, so that the result is shown as soon as it is known.
+        if self.send_status == SendStatus::Sending {
+            ctx.request_repaint();
+        }
+
         // We need to redraw the UI until the design preview has finished rendering,
         // otherwise the user may be left very frustrated that it is taking a while to render.
         if let Some(preview) = &self.design_preview_image {
@@ -614,10 +1205,34 @@ enum UIMessage {
         /// The device we should use to as our printer-like device.
         printer: PrintDevice,
     },
+    /// The bed settings have changed.
+    /// This only affects the state of the settings dialog, it does not save the settings.
+    BedSettingsChanged {
+        /// The bed of the laser cutter that jobs are cut on.
+        bed: PrintBed,
+    },
+    /// The devices that can be picked in the settings dialog should be found again.
+    RefreshPrintDevices,
     /// The current state of the settings dialog should be applied to the app state.
     SaveSettings,
     /// The settings dialog should be closed.
     CloseSettingsDialog,
+    /// Show the dialog to save the current tool passes as a material preset.
+    ShowSavePresetDialog,
+    /// The name being entered for a new material preset has changed.
+    SavePresetNameChanged {
+        /// The new name.
+        name: String,
+    },
+    /// Save the current tool passes as a material preset, with the name from the save preset dialog.
+    SavePreset,
+    /// The save preset dialog should be closed without saving.
+    CloseSavePresetDialog,
+    /// A recently opened design should be opened again.
+    OpenRecentDesign {
+        /// The path of the design.
+        path: PathBuf,
+    },
     /// A new design file has been loaded.
     DesignFileChanged {
         /// The design file that has been loaded.
@@ -630,6 +1245,17 @@ enum UIMessage {
         /// The new list of tool passes.
         passes: Vec<ToolPass>,
     },
+    /// A new tool pass should be added to the end of the list.
+    AddToolPass,
+    /// The last change to the tool passes should be undone.
+    Undo,
+    /// The last undone change to the tool passes should be redone.
+    Redo,
+    /// A tool pass should be removed.
+    RemoveToolPass {
+        /// The index of the tool pass to remove.
+        index: usize,
+    },
     /// The name of a tool pass has changed.
     ToolPassNameChanged {
         /// The index of the tool pass that has changed.
@@ -656,8 +1282,15 @@ enum UIMessage {
         /// The index of the tool pass that has changed.
         index: usize,
         /// The new colour of associated with the tool pass.
-        colour: [u8; 3],
+        colour: Colour,
     },
+    /// The next path clicked in the design preview should set the colour of a tool pass.
+    StartPickingToolPassColour {
+        /// The index of the tool pass to set the colour of.
+        index: usize,
+    },
+    /// Clicking in the design preview should go back to moving the design.
+    StopPickingToolPassColour,
     /// The name of a tool pass has been clicked.
     ToolPassNameClicked {
         /// The index of the tool pass that was clicked.
@@ -683,10 +1316,39 @@ enum UIMessage {
         index: usize,
         enabled: bool,
     },
+    /// The layer or CSS class of paths that a tool pass cuts has changed.
+    ToolPassGroupChanged {
+        /// The index of the tool pass that has changed.
+        index: usize,
+        /// The new name of the group, `None` if the tool pass doesn't cut a named group.
+        group: Option<String>,
+    },
+    /// The stroke width of paths that a tool pass cuts has changed.
+    ToolPassStrokeWidthChanged {
+        /// The index of the tool pass that has changed.
+        index: usize,
+        /// The new stroke width, `None` if the tool pass doesn't cut paths by stroke width.
+        stroke_width: Option<StrokeWidth>,
+    },
+    /// What paths are grouped by to choose the tool pass that cuts them has changed.
+    GroupingChanged {
+        /// The new grouping.
+        grouping: GroupingKey,
+    },
     /// The zoom level of the design preview has changed.
     PreviewZoomLevelChanged {
         zoom: f32,
     },
+    /// Whether the design preview shows all paths, or just those that will be cut, has changed.
+    PreviewShowAllPathsChanged {
+        /// `true` if all paths should be shown.
+        show_all_paths: bool,
+    },
+    /// The distance between the markers drawn across the bed in the design preview has changed.
+    PreviewGridSpacingChanged {
+        /// The new distance in mm, or `None` if the markers should be hidden.
+        grid_spacing_mm: Option<f32>,
+    },
     /// This event is emitted when we know how large the design preview area is (e.g. after UI resize).
     DesignPreviewSize {
         /// The size available for the design preview.
@@ -706,8 +1368,29 @@ enum UIMessage {
         /// The amount to move the design in mm.
         step: f32,
     },
+    /// The design has been dragged to a new position on the bed.
+    DesignOffsetChanged {
+        /// The new offset of the design from the top-left corner, in mm.
+        offset_mm: egui::Vec2,
+    },
     /// Reset the design to align with the top-left edge.
     ResetDesignPosition,
+    /// The design should be sent to the laser cutter to be cut.
+    SendToLaser,
+    /// The laser cutter's head should be moved around the edge of the design, without cutting it.
+    FrameDesign,
+    /// The print device has been checked.
+    PrintDeviceChecked {
+        /// `true` if the print device can be used.
+        valid: bool,
+    },
+    /// A job has finished being sent to the laser cutter.
+    SendFinished {
+        /// `true` if the job was sent, any error has already been shown to the user.
+        succeeded: bool,
+        /// The ID that the device gave the job, if it gives jobs IDs.
+        job_id: Option<String>,
+    },
     /// The enter key has been pressed.
     EnterKeyPressed,
     /// The tab key has been pressed.
@@ -831,6 +1514,7 @@ impl FileDialog {
     ///
     /// # Arguments
     /// * `dialog`: The file dialog to poll.
+    /// * `bed`: The bed that designs will be cut on.
     /// * `ui_message_tx`: The channel that messages will be sent into according to the interaction the user has with the file dialog.
     /// * `hasher`: Hasher that can be used to get the hash of files.
     ///
@@ -838,6 +1522,7 @@ impl FileDialog {
     /// Whether the file dialog should be kept (`true`) or destroyed (`false`).
     fn poll(
         dialog: &Option<FileDialog>,
+        bed: &PrintBed,
         ui_message_tx: &UIMessageTx,
         hasher: &mut Box<dyn hash::Hasher>,
     ) -> bool {
@@ -849,7 +1534,7 @@ impl FileDialog {
                         keep_dialog = false;
 
                         if let Some(path) = path {
-                            match load_design(&path, hasher) {
+                            match load_design(&path, bed, hasher) {
                                 Ok(file) => {
                                     let _ = ui_message_tx
                                         .send(UIMessage::DesignFileChanged { design_file: file });
@@ -931,11 +1616,30 @@ impl FileDialog {
             return Err("Could not decode file".to_string());
         };
 
-        let Ok(passes) = serde_json::from_str::<Vec<ToolPass>>(&json_string) else {
-            return Err("Could not load tool passes from file".to_string());
+        let Ok(value) = serde_json::from_str::<serde_json::Value>(&json_string) else {
+            return Err("File is not a settings file".to_string());
+        };
+
+        // Bare lists of passes were written before settings files were versioned.
+        let version = if value.is_array() {
+            Some(0)
+        } else {
+            value.get("version").and_then(serde_json::Value::as_u64)
+        };
+        let Some(version) = version else {
+            return Err("Could not find the version of the settings file".to_string());
         };
 
-        Ok(passes)
+        let Ok(mut settings) = serde_json::from_value::<SettingsFile>(value) else {
+            return Err(format!(
+                "Could not load tool passes from version {version} settings file"
+            ));
+        };
+        settings
+            .migrate()
+            .map_err(|err| format!("Could not load tool passes: {err}"))?;
+
+        Ok(settings.passes)
     }
 }
 
@@ -944,9 +1648,9 @@ impl FileDialog {
 /// # Arguments
 /// * `ui`: The UI to draw the widget into.
 /// * `design_file`: The currently loaded design file, if any.
-/// * `tool_passes`: The current passes of the tool.
-/// * `print_device`: The device to use as our "printer".
-/// * `offset`: How much to move the design by relative to its starting position, in mm, where +x is more right and +y is more down.
+/// * `material_presets`: The saved tool passes for each material.
+/// * `print_device_valid`: Whether the device to use as our "printer" can be used.
+/// * `send_status`: How the last job sent to the laser cutter went.
 /// * `ui_message_tx`: Channel that can be used to send events.
 ///
 /// # Returns
@@ -954,9 +1658,9 @@ impl FileDialog {
 fn toolbar_widget(
     ui: &mut egui::Ui,
     design_file: &Arc<RwLock<Option<(DesignFile, u64, PathBuf)>>>,
-    tool_passes: &Vec<ToolPass>,
-    print_device: &PrintDevice,
-    offset: &Vec2,
+    material_presets: &[MaterialPreset],
+    print_device_valid: bool,
+    send_status: &SendStatus,
     ui_message_tx: &UIMessageTx,
 ) -> egui::Response {
     StripBuilder::new(ui)
@@ -975,23 +1679,90 @@ fn toolbar_widget(
                     if ui.button("Export Laser Settings").clicked() {
                         let _ = ui_message_tx.send(UIMessage::ShowExportToolPathSettingsDialog);
                     }
+
+                    egui::ComboBox::from_id_source("material_presets")
+                        .selected_text("Material Presets")
+                        .show_ui(ui, |ui| {
+                            for preset in material_presets {
+                                if ui.selectable_label(false, &preset.name).clicked() {
+                                    let _ = ui_message_tx.send(UIMessage::ToolPassesListChanged {
+                                        passes: preset.passes.clone(),
+                                    });
+                                }
+                            }
+                            if !material_presets.is_empty() {
+                                ui.separator();
+                            }
+                            if ui.selectable_label(false, "Save current as preset...").clicked() {
+                                let _ = ui_message_tx.send(UIMessage::ShowSavePresetDialog);
+                            }
+                        });
                 });
             });
 
             strip.cell(|ui| {
                 ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
-                    let hover_text = if print_device.is_valid() {
+                    let hover_text = if print_device_valid {
                         "Sends your design to the laser cutter. You will need to press Start on the laser cutter after sending."
                     } else {
                         "No valid laser cutter has been configured, please configure in settings. Note: This button may be disabled due to being unable to access the configured device."
                     };
+                    let can_send = print_device_valid && *send_status != SendStatus::Sending;
                     let button = egui::Button::new("Send to Laser");
-                    if ui.add_enabled(print_device.is_valid(), button).on_hover_text(hover_text).clicked() {
-                        if let Ok(design_lock) = design_file.read() {
-                            if let Some(file) = &*design_lock {
-                                if let Err(err) = cut_file(&file.0, tool_passes, print_device, (offset.x, offset.y)) {
-                                    handle_cut_file_error(err, ui_message_tx);
-                                }
+                    if ui.add_enabled(can_send, button).on_hover_text(hover_text).clicked() {
+                        let _ = ui_message_tx.send(UIMessage::SendToLaser);
+                    }
+
+                    let hover_text = if print_device_valid {
+                        "Moves the laser cutter's head around the edge of your design without cutting, to check where it will be cut. You will need to press Start on the laser cutter after sending."
+                    } else {
+                        "No valid laser cutter has been configured, please configure in settings."
+                    };
+                    let button = egui::Button::new("Frame");
+                    if ui.add_enabled(can_send, button).on_hover_text(hover_text).clicked() {
+                        let _ = ui_message_tx.send(UIMessage::FrameDesign);
+                    }
+
+                    match send_status {
+                        SendStatus::Idle => {}
+                        SendStatus::Sending => {
+                            ui.spinner().on_hover_text("Sending to the laser cutter");
+                        }
+                        SendStatus::Sent { job_id: None } => {
+                            ui.label(RichText::new("✔ Sent").weak());
+                        }
+                        SendStatus::Sent {
+                            job_id: Some(job_id),
+                        } => {
+                            ui.label(RichText::new(format!("✔ Sent as {job_id}")).weak());
+                        }
+                        SendStatus::Failed => {
+                            ui.label(RichText::new("✖ Not sent").weak());
+                        }
+                    }
+
+                    if let Ok(design_lock) = design_file.read() {
+                        if let Some(file) = &*design_lock {
+                            let hidden_layers: Vec<&str> =
+                                file.0.hidden_layers().map(LayerVisibility::name).collect();
+                            let message = match hidden_layers.len() {
+                                0 => None,
+                                1 => Some("1 hidden layer was ignored".to_string()),
+                                count => Some(format!("{count} hidden layers were ignored")),
+                            };
+                            if let Some(message) = message {
+                                ui.label(RichText::new(message).weak())
+                                    .on_hover_text(hidden_layers.join(", "));
+                            }
+
+                            let message = match file.0.warnings.ignored_images {
+                                0 => None,
+                                1 => Some("1 embedded image was ignored".to_string()),
+                                count => Some(format!("{count} embedded images were ignored")),
+                            };
+                            if let Some(message) = message {
+                                ui.label(RichText::new(message).weak())
+                                    .on_hover_text("Images are not supported, only paths are cut");
                             }
                         }
                     }
@@ -1025,6 +1796,21 @@ fn handle_cut_file_error(err: SendToDeviceError, ui_message_tx: &UIMessageTx) {
                 format!("Error from SVG parsing library: {details}"),
             )
         }
+        SendToDeviceError::GenerateHpglError(error) => {
+            let details = match error {
+                HpglError::WrongPassCount { expected, got } => format!(
+                    "There are {got} tool passes but between {} and {} are supported",
+                    expected.start(),
+                    expected.end()
+                ),
+                HpglError::NoPassesEnabled => "None of the tool passes are enabled".to_string(),
+                HpglError::OriginOffBed(err) => {
+                    format!("The toolhead can't be parked at the top-left of the bed: {err}")
+                }
+                HpglError::FailedToWrite(err) => format!("Failed to write the HPGL: {err}"),
+            };
+            ("Error generating HPGL".to_string(), details)
+        }
         SendToDeviceError::FailedToOpenPrinter(err) => (
             "Error opening printer".to_string(),
             format!("I/O error: {err:?}"),
@@ -1033,6 +1819,29 @@ fn handle_cut_file_error(err: SendToDeviceError, ui_message_tx: &UIMessageTx) {
             "Error writing to printer".to_string(),
             format!("I/O error: {err:?}"),
         ),
+        SendToDeviceError::PathsOffBed(errors) => {
+            let details = errors
+                .iter()
+                .map(|err| {
+                    format!(
+                        "Path {} of colour {}: {}",
+                        err.path_index + 1,
+                        err.colour,
+                        err.error
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            ("Design doesn't fit on the bed".to_string(), details)
+        }
+        SendToDeviceError::NothingToFrame => (
+            "Nothing to frame".to_string(),
+            "None of the enabled tool passes cut any of the design".to_string(),
+        ),
+        SendToDeviceError::FrameOffBed(err) => (
+            "Frame doesn't fit on the bed".to_string(),
+            format!("The frame around the design can't be reached: {err}"),
+        ),
     };
     let _ = ui_message_tx.send(UIMessage::ShowError {
         error,
@@ -1040,26 +1849,52 @@ fn handle_cut_file_error(err: SendToDeviceError, ui_message_tx: &UIMessageTx) {
     });
 }
 
+/// Describes a grid spacing for the user.
+///
+/// # Arguments
+/// * `grid_spacing_mm`: The distance between the markers drawn across the bed in mm, `None` if they are hidden.
+///
+/// # Returns
+/// The description of the grid spacing.
+fn grid_spacing_label(grid_spacing_mm: Option<f32>) -> String {
+    match grid_spacing_mm {
+        Some(spacing) => format!("{spacing}mm"),
+        None => "Off".to_string(),
+    }
+}
+
 /// Draws the main UI (tool paths and design preview).
 ///
 /// # Arguments
 /// * `ui`: The UI to draw the widget to.
 /// * `tool_passes`: The passes of the tool head.
+/// * `grouping`: What paths are grouped by to choose the tool pass that cuts them.
 /// * `tool_pass_widget_states`: Current states of tool pass widgets.
 /// * `frame_widgets`: Map of widgets being drawn this frame.
+/// * `bed`: The bed of the laser cutter that jobs are cut on.
 /// * `design_file`: The loaded design file, if any.
+/// * `design_colours`: The colours of the paths in the loaded design, empty if there isn't one.
 /// * `design_preview_image`: The preview image to draw to the UI.
 /// * `preview_zoom_level`: How much the preview image is zoomed in.
+/// * `preview_show_all_paths`: Whether the preview shows all paths, rather than just those that will be cut.
+/// * `preview_grid_spacing_mm`: The distance between the markers drawn across the bed in mm, `None` if they are hidden.
+/// * `picking_colour_for`: The index of the tool pass whose colour is being picked from the preview, if any.
 /// * `design_move_step_mm`: The current amount to step the design by when moving it.
 /// * `ui_message_tx`: Channel into which UI events can be sent.
 fn ui_main(
     ui: &mut egui::Ui,
     tool_passes: &mut Vec<ToolPass>,
+    grouping: GroupingKey,
     tool_pass_widget_states: &mut Vec<ToolPassWidgetState>,
     frame_widgets: &mut HashMap<egui::Id, SeanceUIElement>,
+    bed: &PrintBed,
     design_file: &Arc<RwLock<Option<DesignWithMeta>>>,
+    design_colours: &HashSet<PathColour>,
     design_preview_image: &mut Option<DesignPreview>,
     preview_zoom_level: f32,
+    preview_show_all_paths: bool,
+    preview_grid_spacing_mm: Option<f32>,
+    picking_colour_for: Option<usize>,
     design_move_step_mm: f32,
     ui_message_tx: &UIMessageTx,
 ) {
@@ -1071,13 +1906,16 @@ fn ui_main(
                 tool_passes_widget(
                     ui,
                     tool_passes,
+                    grouping,
                     tool_pass_widget_states,
+                    design_colours,
+                    picking_colour_for,
                     frame_widgets,
                     ui_message_tx,
                 );
             });
             strip.cell(|ui| {
-                let ratio = BED_HEIGHT_MM / BED_WIDTH_MM;
+                let ratio = bed.height_mm() / bed.width_mm();
                 let mut width = ui.available_width();
                 let mut height = width * ratio;
                 let max_height = ui.available_height() * 0.8;
@@ -1097,6 +1935,7 @@ fn ui_main(
                                     ui,
                                     design_file,
                                     design_preview_image,
+                                    picking_colour_for,
                                     ui_message_tx,
                                     egui::Vec2 {
                                         x: width,
@@ -1117,6 +1956,42 @@ fn ui_main(
                                             zoom: zoom_value,
                                         });
                                 }
+
+                                let mut show_all_paths = preview_show_all_paths;
+                                if ui
+                                    .checkbox(&mut show_all_paths, "Show all paths")
+                                    .on_hover_text(
+                                        "Show paths that won't be cut, as they don't match an enabled tool pass",
+                                    )
+                                    .changed()
+                                {
+                                    let _ = ui_message_tx.send(
+                                        UIMessage::PreviewShowAllPathsChanged { show_all_paths },
+                                    );
+                                }
+
+                                egui::ComboBox::from_label("Grid")
+                                    .selected_text(grid_spacing_label(preview_grid_spacing_mm))
+                                    .show_ui(ui, |ui| {
+                                        let options = std::iter::once(None).chain(
+                                            GRID_SPACING_OPTIONS_MM.into_iter().map(Some),
+                                        );
+                                        for grid_spacing_mm in options {
+                                            if ui
+                                                .selectable_label(
+                                                    grid_spacing_mm == preview_grid_spacing_mm,
+                                                    grid_spacing_label(grid_spacing_mm),
+                                                )
+                                                .clicked()
+                                            {
+                                                let _ = ui_message_tx.send(
+                                                    UIMessage::PreviewGridSpacingChanged {
+                                                        grid_spacing_mm,
+                                                    },
+                                                );
+                                            }
+                                        }
+                                    });
                             });
                             ui.separator();
                             ui.label("Position Design");
@@ -1244,16 +2119,45 @@ fn ui_main(
 /// # Arguments
 /// * `ui`: The UI to draw the widget into.
 /// * `tool_passes`: The tool passes to draw.
+/// * `grouping`: What paths are grouped by to choose the tool pass that cuts them.
 /// * `tool_pass_widget_states`: The states of the tool pass widgets that we're drawing, should be persistent across frames.
+/// * `design_colours`: The colours of the paths in the loaded design, empty if there isn't one.
+/// * `picking_colour_for`: The index of the tool pass whose colour is being picked from the preview, if any.
 /// * `frame_widgets`: The map that created widgets should be added to.
 /// * `ui_message_tx`: A channel for sending UI messages into.
+#[allow(clippy::too_many_arguments)]
 fn tool_passes_widget(
     ui: &mut egui::Ui,
     tool_passes: &mut Vec<ToolPass>,
+    grouping: GroupingKey,
     tool_pass_widget_states: &mut Vec<ToolPassWidgetState>,
+    design_colours: &HashSet<PathColour>,
+    picking_colour_for: Option<usize>,
     frame_widgets: &mut HashMap<egui::Id, SeanceUIElement>,
     ui_message_tx: &UIMessageTx,
 ) {
+    // The last pass can't be removed, there must always be something to cut with.
+    let can_remove = tool_passes.len() > 1;
+
+    egui::ComboBox::from_label("Cut paths by")
+        .selected_text(grouping_label(grouping))
+        .show_ui(ui, |ui| {
+            for option in [
+                GroupingKey::StrokeColour,
+                GroupingKey::FillColour,
+                GroupingKey::LayerName,
+                GroupingKey::CssClass,
+                GroupingKey::StrokeWidth,
+            ] {
+                if ui
+                    .selectable_label(option == grouping, grouping_label(option))
+                    .clicked()
+                {
+                    let _ = ui_message_tx.send(UIMessage::GroupingChanged { grouping: option });
+                }
+            }
+        });
+
     // List of laser passes.
     ScrollArea::vertical().show(ui, |ui| {
         let drag_area = dnd(ui, "seance_laser_passes")
@@ -1282,8 +2186,15 @@ fn tool_passes_widget(
                         tool_pass_widget(
                             &mut child_ui,
                             pass,
+                            grouping,
                             state.index,
                             &mut tool_pass_widget_states[state.index], // TODO: BAD!
+                            can_remove,
+                            // Without a design there's nothing to say whether a pass will cut anything,
+                            // and only the stroke colours of the design are known.
+                            (grouping == GroupingKey::StrokeColour && !design_colours.is_empty())
+                                .then(|| design_colours.contains(pass.colour())),
+                            picking_colour_for == Some(state.index),
                             frame_widgets,
                             ui_message_tx,
                         );
@@ -1294,9 +2205,51 @@ fn tool_passes_widget(
         if drag_area.is_drag_finished() {
             drag_area.update_vec(tool_passes);
         }
+
+        let can_add = tool_passes.len() < MAX_TOOL_PASSES;
+        if ui
+            .add_enabled(can_add, Button::new("+ Add Pass"))
+            .on_disabled_hover_text(format!(
+                "The machine supports up to {MAX_TOOL_PASSES} passes"
+            ))
+            .clicked()
+        {
+            let _ = ui_message_tx.send(UIMessage::AddToolPass);
+        }
     });
 }
 
+/// Creates the widget states for a list of tool passes.
+///
+/// # Arguments
+/// * `tool_passes`: The tool passes that will be drawn.
+///
+/// # Returns
+/// A widget state for each tool pass.
+fn tool_pass_widget_states(tool_passes: &[ToolPass]) -> Vec<ToolPassWidgetState> {
+    tool_passes
+        .iter()
+        .map(|pass| ToolPassWidgetState::new(Default::default(), pass.power(), pass.speed()))
+        .collect()
+}
+
+/// Describes what paths are grouped by for the user.
+///
+/// # Arguments
+/// * `grouping`: What paths are grouped by.
+///
+/// # Returns
+/// The description of the grouping.
+fn grouping_label(grouping: GroupingKey) -> &'static str {
+    match grouping {
+        GroupingKey::StrokeColour => "Stroke colour",
+        GroupingKey::FillColour => "Fill colour",
+        GroupingKey::LayerName => "Layer",
+        GroupingKey::CssClass => "CSS class",
+        GroupingKey::StrokeWidth => "Stroke width",
+    }
+}
+
 /// The state of a tool pass widget.
 struct ToolPassWidgetState {
     /// Which aspect of the tool pass that is being edited.
@@ -1342,8 +2295,12 @@ enum ToolPassWidgetEditing {
 /// # Arguments
 /// * `ui`: The UI to draw the widget into.
 /// * `tool_pass`: The tool pass to draw.
+/// * `grouping`: What paths are grouped by, which decides what the tool pass is matched to paths by.
 /// * `pass_index`: The index into the tool passes array that is being drawn.
 /// * `state`: The state of the widget.
+/// * `can_remove`: Whether the tool pass can be removed.
+/// * `in_design`: Whether the colour of the tool pass is in the loaded design, `None` if there isn't one.
+/// * `is_picking_colour`: Whether the colour of the tool pass is being picked from the preview.
 /// * `frame_widgets`: The map of widgets to add drawn widgets to.
 /// * `ui_message_tx`: The channel to send UI events into.
 ///
@@ -1352,14 +2309,21 @@ enum ToolPassWidgetEditing {
 fn tool_pass_widget(
     ui: &mut egui::Ui,
     tool_pass: &ToolPass,
+    grouping: GroupingKey,
     pass_index: usize,
     state: &mut ToolPassWidgetState,
+    can_remove: bool,
+    in_design: Option<bool>,
+    is_picking_colour: bool,
     frame_widgets: &mut HashMap<egui::Id, SeanceUIElement>,
     ui_message_tx: &UIMessageTx,
 ) -> egui::Response {
     StripBuilder::new(ui)
         .size(Size::exact(20.0))
-        .sizes(Size::remainder(), 6)
+        .sizes(Size::remainder(), 5)
+        .size(Size::exact(24.0))
+        .size(Size::remainder())
+        .size(Size::exact(24.0))
         .horizontal(|mut strip| {
             // Drag Handle
             strip.cell(|ui| {
@@ -1478,27 +2442,91 @@ fn tool_pass_widget(
                     }
                 });
             });
-            // Colour Hex-code
+            // Colour Hex-code, or what else the pass is matched to paths by
             strip.cell(|ui| {
                 Frame::default().inner_margin(6.0).show(ui, |ui| {
                     ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
-                        let [r, g, b] = tool_pass.colour();
-                        let colour_u32: u64 =
-                            ((*r as u64) << 16) + ((*g as u64) << 8) + (*b as u64);
-                        ui.label(format!("#{colour_u32:06X}"));
+                        match grouping {
+                            GroupingKey::StrokeColour | GroupingKey::FillColour => {
+                                ui.label(tool_pass.colour().to_string());
+                            }
+                            GroupingKey::LayerName | GroupingKey::CssClass => {
+                                let mut group = tool_pass.group().unwrap_or_default().to_string();
+                                let hint = if grouping == GroupingKey::LayerName {
+                                    "Layer"
+                                } else {
+                                    "Class"
+                                };
+                                if ui
+                                    .add(TextEdit::singleline(&mut group).hint_text(hint))
+                                    .changed()
+                                {
+                                    let _ = ui_message_tx.send(UIMessage::ToolPassGroupChanged {
+                                        index: pass_index,
+                                        group: (!group.is_empty()).then_some(group),
+                                    });
+                                }
+                            }
+                            GroupingKey::StrokeWidth => {
+                                let mut width_mm =
+                                    tool_pass.stroke_width().map_or(0.0, |width| width.mm());
+                                if ui
+                                    .add(
+                                        egui::DragValue::new(&mut width_mm)
+                                            .speed(0.01)
+                                            .range(0.0..=MAX_STROKE_WIDTH_MM)
+                                            .suffix("mm"),
+                                    )
+                                    .on_hover_text("The stroke width of paths that this pass cuts")
+                                    .changed()
+                                {
+                                    let _ =
+                                        ui_message_tx.send(UIMessage::ToolPassStrokeWidthChanged {
+                                            index: pass_index,
+                                            stroke_width: Some(StrokeWidth::from_mm(width_mm)),
+                                        });
+                                }
+                            }
+                        }
+
+                        match in_design {
+                            Some(true) => {
+                                ui.label(RichText::new("✔").color(Color32::DARK_GREEN))
+                                    .on_hover_text("This colour is in the design");
+                            }
+                            Some(false) => {
+                                ui.label(RichText::new("✖").weak()).on_hover_text(
+                                    "This colour is not in the design, this pass won't cut anything",
+                                );
+                            }
+                            None => {}
+                        }
                     });
                 });
             });
             // Colour Swatch
             strip.cell(|ui| {
-                let mut colour = tool_pass.colour().clone();
+                let Colour(mut colour) = *tool_pass.colour();
                 if ui.color_edit_button_srgb(&mut colour).changed() {
                     let _ = ui_message_tx.send(UIMessage::ToolPassColourChanged {
                         index: pass_index,
-                        colour: colour.clone(),
+                        colour: Colour(colour),
                     });
                 };
             });
+            // Eyedropper
+            strip.cell(|ui| {
+                let eyedropper = ui
+                    .add(Button::new("💧").small().selected(is_picking_colour))
+                    .on_hover_text("Pick the colour from a path in the design");
+                if eyedropper.clicked() {
+                    let _ = ui_message_tx.send(if is_picking_colour {
+                        UIMessage::StopPickingToolPassColour
+                    } else {
+                        UIMessage::StartPickingToolPassColour { index: pass_index }
+                    });
+                }
+            });
             // Enable Checkbox
             strip.cell(|ui| {
                 let mut enabled_val = tool_pass.enabled().clone();
@@ -1510,6 +2538,17 @@ fn tool_pass_widget(
                     });
                 }
             });
+            // Remove Button
+            strip.cell(|ui| {
+                if ui
+                    .add_enabled(can_remove, Button::new("🗑").small())
+                    .on_hover_text("Remove pass")
+                    .on_disabled_hover_text("There must be at least one pass")
+                    .clicked()
+                {
+                    let _ = ui_message_tx.send(UIMessage::RemoveToolPass { index: pass_index });
+                }
+            });
         })
 }
 
@@ -1519,6 +2558,7 @@ fn tool_pass_widget(
 /// * `ui`: The UI to draw the preview into.
 /// * `design_file`: The design file to draw.
 /// * `design_file_preview`: The generated preview.
+/// * `picking_colour_for`: The index of the tool pass whose colour is being picked from the preview, if any.
 /// * `ui_message_tx`: A channel that UI events can be sent into.
 /// * `size`: How big to draw the preview.
 ///
@@ -1528,6 +2568,7 @@ fn design_file_widget(
     ui: &mut egui::Ui,
     design_file: &Arc<RwLock<Option<DesignWithMeta>>>,
     design_preview: &mut Option<DesignPreview>,
+    picking_colour_for: Option<usize>,
     ui_message_tx: &UIMessageTx,
     size: egui::Vec2,
 ) -> egui::Response {
@@ -1553,20 +2594,44 @@ fn design_file_widget(
         return design_file_placeholder(ui, widget_rect);
     };
 
-    let Some(image) = design_preview.image(ui.ctx(), design_file) else {
+    if !design_preview.is_ready(ui.ctx(), design_file) {
         return design_file_placeholder(ui, widget_rect);
-    };
+    }
 
     let mut child_ui = ui.child_ui(widget_rect, Layout::left_to_right(Align::Min), None);
 
-    let response = ScrollArea::both()
+    let mut scroll_area = ScrollArea::both()
         .scroll_bar_visibility(egui::scroll_area::ScrollBarVisibility::VisibleWhenNeeded)
         .animated(false)
         .min_scrolled_width(widget_rect.size().x)
         .min_scrolled_height(widget_rect.size().y)
         .max_width(widget_rect.size().x)
-        .max_height(widget_rect.size().y)
-        .show(&mut child_ui, |ui| ui.add(image));
+        .max_height(widget_rect.size().y);
+    if let Some(offset) = design_preview.take_scroll_offset() {
+        scroll_area = scroll_area.scroll_offset(offset);
+    }
+    let response = scroll_area.show(&mut child_ui, |ui| {
+        design_preview.show(ui, picking_colour_for, ui_message_tx)
+    });
+    design_preview.show_rulers(ui, widget_rect, response.state.offset);
+
+    // Ctrl + scroll wheel (or pinching) zooms towards the pointer, plain scrolling still moves around the bed.
+    if let Some(pointer) = ui
+        .ctx()
+        .pointer_hover_pos()
+        .filter(|pointer| widget_rect.contains(*pointer))
+    {
+        let zoom_delta = ui.input(|i| i.zoom_delta());
+        if zoom_delta != 1.0 {
+            design_preview.zoom_towards(
+                zoom_delta,
+                pointer - widget_rect.min,
+                response.state.offset,
+                ui_message_tx,
+            );
+        }
+    }
+
     preview_files_being_dropped(ui, widget_rect);
     response.inner
 }
@@ -1607,7 +2672,7 @@ fn preview_files_being_dropped(ui: &mut egui::Ui, rect: Rect) {
                 if let Some(path) = &file.path {
                     if let Some(ext) = path.extension() {
                         if let Some(name) = path.file_name() {
-                            if ext.eq_ignore_ascii_case("svg") {
+                            if ext.eq_ignore_ascii_case("svg") || ext.eq_ignore_ascii_case("dxf") {
                                 show_preview = true;
                                 write!(text, "{}", name.to_string_lossy()).ok();
                             }
@@ -1689,6 +2754,63 @@ fn error_dialog(
     );
 }
 
+/// Shows the dialog to save the current tool passes as a material preset.
+///
+/// # Arguments
+/// * `ctx`: The egui context.
+/// * `ui_message_tx`: A message channel that events can be sent into.
+/// * `name`: The name being entered for the preset.
+fn save_preset_dialog(ctx: &egui::Context, ui_message_tx: &UIMessageTx, name: &str) {
+    let window_size = ctx.screen_rect().max;
+    let save_preset_dialog_size = Vec2 { x: 320.0, y: 120.0 };
+    ctx.show_viewport_immediate(
+        egui::ViewportId::from_hash_of("save_preset_dialog"),
+        egui::ViewportBuilder::default()
+            .with_title("Save Material Preset")
+            .with_inner_size([save_preset_dialog_size.x, save_preset_dialog_size.y])
+            .with_position(Pos2 {
+                x: (window_size.x / 2.0) - (save_preset_dialog_size.x / 2.0),
+                y: (window_size.y / 2.0) - (save_preset_dialog_size.y / 2.0),
+            })
+            .with_resizable(false),
+        move |ctx, _| {
+            let ui_message_tx = ui_message_tx.clone();
+            egui::CentralPanel::default().show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Name");
+                    let mut name = name.to_string();
+                    let name_edit = ui
+                        .text_edit_singleline(&mut name)
+                        .on_hover_text("Saving with the name of an existing preset replaces it.");
+                    name_edit.request_focus();
+                    if name_edit.changed() {
+                        let _ = ui_message_tx.send(UIMessage::SavePresetNameChanged { name });
+                    }
+                });
+                ui.with_layout(Layout::right_to_left(Align::BOTTOM), |ui| {
+                    if ui.button("Cancel").clicked() {
+                        let _ = ui_message_tx.send(UIMessage::CloseSavePresetDialog);
+                    }
+                    if ui
+                        .add_enabled(!name.trim().is_empty(), Button::new("Save"))
+                        .clicked()
+                    {
+                        let _ = ui_message_tx.send(UIMessage::SavePreset);
+                    }
+                });
+            });
+            ctx.input(|i| {
+                if i.viewport().close_requested() || i.key_pressed(Key::Escape) {
+                    // Tell parent to close us.
+                    let _ = ui_message_tx.send(UIMessage::CloseSavePresetDialog);
+                } else if i.key_pressed(Key::Enter) {
+                    let _ = ui_message_tx.send(UIMessage::SavePreset);
+                }
+            });
+        },
+    );
+}
+
 /// Shows the settings dialog.
 ///
 /// # Arguments
@@ -1715,69 +2837,312 @@ fn settings_dialog(
         move |ctx, _| {
             let ui_message_tx = ui_message_tx.clone();
             egui::CentralPanel::default().show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let is_network = matches!(settings.print_device, PrintDevice::Tcp { .. });
+                    let is_serial = matches!(settings.print_device, PrintDevice::Serial { .. });
+                    #[cfg(not(target_os = "windows"))]
+                    let is_system_printer =
+                        matches!(settings.print_device, PrintDevice::SystemPrinter { .. });
+                    #[cfg(target_os = "windows")]
+                    let is_system_printer = false;
+                    let is_usb = !is_network && !is_serial && !is_system_printer;
+                    ui.label("Connection");
+                    if ui.radio(is_usb, "USB").clicked() && !is_usb {
+                        let _ = ui_message_tx.send(UIMessage::PrinterSettingsChanged {
+                            printer: PrintDevice::default(),
+                        });
+                    }
+                    if ui
+                        .radio(is_network, "Network")
+                        .on_hover_text("Send jobs to a laser cutter that takes raw jobs over the network.")
+                        .clicked()
+                        && !is_network
+                    {
+                        let _ = ui_message_tx.send(UIMessage::PrinterSettingsChanged {
+                            printer: PrintDevice::Tcp {
+                                host: String::new(),
+                                port: DEFAULT_TCP_PORT,
+                                retry: WriteRetry::default(),
+                            },
+                        });
+                    }
+                    if ui
+                        .radio(is_serial, "Serial")
+                        .on_hover_text("Send jobs to a plotter on a serial port.")
+                        .clicked()
+                        && !is_serial
+                    {
+                        let _ = ui_message_tx.send(UIMessage::PrinterSettingsChanged {
+                            printer: PrintDevice::Serial {
+                                path: String::new(),
+                                baud: DEFAULT_SERIAL_BAUD,
+                                flow_control: SerialFlowControl::default(),
+                                retry: WriteRetry::default(),
+                            },
+                        });
+                    }
+                    #[cfg(not(target_os = "windows"))]
+                    if ui
+                        .radio(is_system_printer, "System Printer")
+                        .on_hover_text("Send jobs to a printer queue that the laser cutter is set up as.")
+                        .clicked()
+                        && !is_system_printer
+                    {
+                        let _ = ui_message_tx.send(UIMessage::PrinterSettingsChanged {
+                            printer: PrintDevice::SystemPrinter {
+                                queue_name: String::new(),
+                            },
+                        });
+                    }
+                });
+
                 ui.horizontal(|ui| {
                     let mut printer = settings.print_device.clone();
                     match &mut printer {
                         #[cfg(not(target_os = "windows"))]
-                        PrintDevice::Path { path } => {
+                        PrintDevice::Path { path, .. } => {
+                            let original_path = path.clone();
+
                             ui.label("Print Device");
+                            egui::ComboBox::from_id_source("print_device")
+                                .selected_text(path.clone())
+                                .show_ui(ui, |ui| {
+                                    let candidates: Vec<(String, String)> = settings
+                                        .found_devices
+                                        .iter()
+                                        .filter_map(|candidate| match &candidate.device {
+                                            PrintDevice::Path { path, .. } => {
+                                                Some((path.clone(), candidate.description.clone()))
+                                            }
+                                            _ => None,
+                                        })
+                                        .collect();
+                                    if candidates.is_empty() {
+                                        ui.label("No USB printers were found");
+                                    }
+                                    for (candidate_path, description) in candidates {
+                                        ui.selectable_value(path, candidate_path, description);
+                                    }
+                                })
+                                .response
+                                .on_hover_text("The USB printers that are connected.");
+                            if ui
+                                .button("Refresh")
+                                .on_hover_text("Look for connected devices again.")
+                                .clicked()
+                            {
+                                let _ = ui_message_tx.send(UIMessage::RefreshPrintDevices);
+                            }
                             let printer_edit = ui
                                 .text_edit_singleline(path)
                                 .on_hover_text(r#"This is the device that will be used to print."#);
-                            if printer_edit.changed() || printer_edit.lost_focus() {
+                            if *path != original_path || printer_edit.lost_focus() {
                                 let _ = ui_message_tx
                                     .send(UIMessage::PrinterSettingsChanged { printer });
                             }
                         }
                         #[cfg(target_os = "windows")]
-                        PrintDevice::USBPort { port: current_port } => {
-                            let ports = usb_enumeration::enumerate(None, None);
-                            let mut selected: Option<usb_enumeration::UsbDevice> = None;
-                            if let Some(port) = current_port {
-                                selected = ports
-                                    .iter()
-                                    .find(|p| {
-                                        p.vendor_id == port.vendor_id
-                                            && p.product_id == port.product_id
-                                    })
-                                    .cloned();
-                            }
+                        PrintDevice::USBPort {
+                            port: current_port,
+                            retry,
+                        } => {
+                            let candidates = settings.found_devices.clone();
+                            let selected = candidates.iter().find_map(|candidate| match &candidate
+                                .device
+                            {
+                                PrintDevice::USBPort { port, .. }
+                                    if port.is_some() && port == current_port =>
+                                {
+                                    Some(candidate.description.clone())
+                                }
+                                _ => None,
+                            });
 
                             let original_selected = current_port.clone();
 
-                            match &selected {
-                                Some(head) => {
-                                    let label = head.description.clone().unwrap();
+                            match selected {
+                                Some(label) => {
                                     egui::ComboBox::from_label("Print Device").selected_text(label)
                                 }
                                 None => egui::ComboBox::from_label("Print Device"),
                             }
                             .show_ui(ui, |ui| {
-                                for port in ports {
-                                    if let Some(label) = port.description {
-                                        ui.selectable_value(
-                                            current_port,
-                                            Some(USBPort {
-                                                vendor_id: port.vendor_id,
-                                                product_id: port.product_id,
-                                            }),
-                                            label,
-                                        );
-                                    }
+                                for candidate in candidates {
+                                    let PrintDevice::USBPort { port, .. } = candidate.device else {
+                                        continue;
+                                    };
+                                    ui.selectable_value(current_port, port, candidate.description);
                                 }
                             });
+                            if ui
+                                .button("Refresh")
+                                .on_hover_text("Look for connected devices again.")
+                                .clicked()
+                            {
+                                let _ = ui_message_tx.send(UIMessage::RefreshPrintDevices);
+                            }
 
                             if *current_port != original_selected {
                                 let _ = ui_message_tx.send(UIMessage::PrinterSettingsChanged {
                                     printer: PrintDevice::USBPort {
                                         port: current_port.clone(),
+                                        retry: *retry,
                                     },
                                 });
                             }
                         }
+                        PrintDevice::Tcp { host, port, .. } => {
+                            let original = (host.clone(), *port);
+
+                            ui.label("Host");
+                            let host_edit = ui
+                                .text_edit_singleline(host)
+                                .on_hover_text("The name or address of the laser cutter on the network.");
+                            ui.label("Port");
+                            ui.add(egui::DragValue::new(port)).on_hover_text(format!(
+                                "The port that the laser cutter takes jobs on, usually {DEFAULT_TCP_PORT}."
+                            ));
+                            if (host.clone(), *port) != original || host_edit.lost_focus() {
+                                let _ = ui_message_tx
+                                    .send(UIMessage::PrinterSettingsChanged { printer });
+                            }
+                        }
+                        #[cfg(not(target_os = "windows"))]
+                        PrintDevice::SystemPrinter { queue_name } => {
+                            let original_queue_name = queue_name.clone();
+
+                            ui.label("Queue");
+                            egui::ComboBox::from_id_source("printer_queue")
+                                .selected_text(queue_name.clone())
+                                .show_ui(ui, |ui| {
+                                    let queue_names: Vec<String> = settings
+                                        .found_devices
+                                        .iter()
+                                        .filter_map(|candidate| match &candidate.device {
+                                            PrintDevice::SystemPrinter { queue_name } => {
+                                                Some(queue_name.clone())
+                                            }
+                                            _ => None,
+                                        })
+                                        .collect();
+                                    if queue_names.is_empty() {
+                                        ui.label("No printer queues were found");
+                                    }
+                                    for candidate_queue_name in queue_names {
+                                        let label = candidate_queue_name.clone();
+                                        ui.selectable_value(queue_name, candidate_queue_name, label);
+                                    }
+                                })
+                                .response
+                                .on_hover_text("The printer queues that are set up on this computer.");
+                            if ui
+                                .button("Refresh")
+                                .on_hover_text("Look for connected devices again.")
+                                .clicked()
+                            {
+                                let _ = ui_message_tx.send(UIMessage::RefreshPrintDevices);
+                            }
+                            if *queue_name != original_queue_name {
+                                let _ = ui_message_tx
+                                    .send(UIMessage::PrinterSettingsChanged { printer });
+                            }
+                        }
+                        PrintDevice::Serial {
+                            path,
+                            baud,
+                            flow_control,
+                            ..
+                        } => {
+                            let original = (path.clone(), *baud, *flow_control);
+
+                            ui.label("Port");
+                            egui::ComboBox::from_id_source("serial_port")
+                                .selected_text(path.clone())
+                                .show_ui(ui, |ui| {
+                                    let candidates: Vec<(String, String)> = settings
+                                        .found_devices
+                                        .iter()
+                                        .filter_map(|candidate| match &candidate.device {
+                                            PrintDevice::Serial { path, .. } => {
+                                                Some((path.clone(), candidate.description.clone()))
+                                            }
+                                            _ => None,
+                                        })
+                                        .collect();
+                                    if candidates.is_empty() {
+                                        ui.label("No serial ports were found");
+                                    }
+                                    for (candidate_path, description) in candidates {
+                                        ui.selectable_value(path, candidate_path, description);
+                                    }
+                                })
+                                .response
+                                .on_hover_text("The serial ports on this computer.");
+                            if ui
+                                .button("Refresh")
+                                .on_hover_text("Look for connected devices again.")
+                                .clicked()
+                            {
+                                let _ = ui_message_tx.send(UIMessage::RefreshPrintDevices);
+                            }
+                            let path_edit = ui.text_edit_singleline(path).on_hover_text(
+                                "The serial port that the plotter is connected to, e.g. /dev/ttyUSB0 or COM1.",
+                            );
+                            egui::ComboBox::from_label("Baud")
+                                .selected_text(baud.to_string())
+                                .show_ui(ui, |ui| {
+                                    for rate in SERIAL_BAUD_RATES {
+                                        ui.selectable_value(baud, rate, rate.to_string());
+                                    }
+                                })
+                                .response
+                                .on_hover_text(format!(
+                                    "The baud rate that the plotter is set to, usually {DEFAULT_SERIAL_BAUD}."
+                                ));
+                            egui::ComboBox::from_label("Flow Control")
+                                .selected_text(format!("{flow_control:?}"))
+                                .show_ui(ui, |ui| {
+                                    for option in [
+                                        SerialFlowControl::Hardware,
+                                        SerialFlowControl::Software,
+                                        SerialFlowControl::None,
+                                    ] {
+                                        ui.selectable_value(flow_control, option, format!("{option:?}"));
+                                    }
+                                })
+                                .response
+                                .on_hover_text(
+                                    "How the plotter tells us to stop sending while its buffer is full.",
+                                );
+                            if (path.clone(), *baud, *flow_control) != original
+                                || path_edit.lost_focus()
+                            {
+                                let _ = ui_message_tx
+                                    .send(UIMessage::PrinterSettingsChanged { printer });
+                            }
+                        }
                     }
                 });
 
+                ui.horizontal(|ui| {
+                    let selected = PrintBed::presets()
+                        .iter()
+                        .find(|(_, bed)| *bed == settings.bed)
+                        .map_or("Custom", |(name, _)| name);
+                    egui::ComboBox::from_label("Bed")
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            for (name, bed) in PrintBed::presets() {
+                                if ui.selectable_label(*bed == settings.bed, *name).clicked() {
+                                    let _ = ui_message_tx
+                                        .send(UIMessage::BedSettingsChanged { bed: bed.clone() });
+                                }
+                            }
+                        })
+                        .response
+                        .on_hover_text("The laser cutter that jobs are cut on, designs are placed on its bed.");
+                });
+
                 ui.with_layout(Layout::right_to_left(Align::BOTTOM), |ui| {
                     if ui.button("Save and Close").clicked() {
                         let _ = ui_message_tx.send(UIMessage::SaveSettings);
@@ -1801,16 +3166,49 @@ fn settings_dialog(
     );
 }
 
+/// Describes how far a design is larger than the bed, if it won't fit inside the bed's margins.
+///
+/// # Arguments
+/// * `bed`: The bed that the design will be cut on.
+/// * `width_mm`: The width of the design, in mm.
+/// * `height_mm`: The height of the design, in mm.
+///
+/// # Returns
+/// How far the design overflows the bed, or `None` if the design fits on the bed.
+fn describe_bed_overflow(bed: &PrintBed, width_mm: f32, height_mm: f32) -> Option<String> {
+    if bed.fit_offset_range(width_mm, height_mm).is_some() {
+        return None;
+    }
+
+    let (x_axis, y_axis) = (bed.usable_x_axis(), bed.usable_y_axis());
+    let (bed_width_mm, bed_height_mm) =
+        (x_axis.end() - x_axis.start(), y_axis.end() - y_axis.start());
+    let overflow_x = width_mm - bed_width_mm;
+    let overflow_y = height_mm - bed_height_mm;
+    let overflow = match (overflow_x > 0.0, overflow_y > 0.0) {
+        (false, false) => return None,
+        (true, false) => format!("{overflow_x:.1}mm too wide"),
+        (false, true) => format!("{overflow_y:.1}mm too tall"),
+        (true, true) => format!("{overflow_x:.1}mm too wide and {overflow_y:.1}mm too tall"),
+    };
+
+    Some(format!(
+        "The design is {width_mm:.1}mm x {height_mm:.1}mm, which is {overflow} for the {bed_width_mm:.1}mm x {bed_height_mm:.1}mm that can be cut on. Anything off the bed won't be cut."
+    ))
+}
+
 /// Attempts to load a design from a path.
 ///
 /// # Arguments
 /// * `path`: The path to attempt to load from.
+/// * `bed`: The bed that the design will be cut on.
 /// * `hasher`: Hasher to use to get the hash of the design file.
 ///
 /// # Returns
 /// The design file, if successfully loaded, otherwise an error string.
 fn load_design(
     path: &PathBuf,
+    bed: &PrintBed,
     hasher: &mut Box<dyn hash::Hasher>,
 ) -> Result<DesignWithMeta, String> {
     let mut path_without_extension = path.clone();
@@ -1828,7 +3226,8 @@ fn load_design(
         return Err("Unrecognised file extenstion".to_string());
     };
 
-    if !extension.eq_ignore_ascii_case("svg") {
+    let is_dxf = extension.eq_ignore_ascii_case("dxf");
+    if !extension.eq_ignore_ascii_case("svg") && !is_dxf {
         return Err(format!(
             "Unrecognised file extension: '{}'",
             extension.to_string_lossy()
@@ -1837,32 +3236,68 @@ fn load_design(
 
     match fs::read(path) {
         Ok(bytes) => {
-            let svg = parse_svg(&path, &bytes).map_err(|err| {
-                let error_string = format!("Error reading SVG file: {err}");
-                log::error!("{error_string}");
-                error_string
-            })?;
-            let width = svg.size().width() / SVG_UNITS_PER_MM;
-            let height = svg.size().height() / SVG_UNITS_PER_MM;
+            let design_file = if is_dxf {
+                let svg = parse_dxf_to_svg(&bytes, &DxfColourTable::default()).map_err(|err| {
+                    let details = match err {
+                        DxfError::NotUtf8 => "File is not UTF-8 encoded".to_string(),
+                        DxfError::InvalidGroupCode { line } => {
+                            format!("Invalid group code on line {line}")
+                        }
+                        DxfError::InvalidValue { line } => format!("Invalid value on line {line}"),
+                        DxfError::NoEntities => "Design contains nothing to cut".to_string(),
+                        DxfError::InvalidArc => {
+                            "Design contains an arc that is too large to cut".to_string()
+                        }
+                        DxfError::ErrorBuildingTree(err) => format!("{err}"),
+                    };
+                    let error_string = format!("Error reading DXF file: {details}");
+                    log::error!("{error_string}");
+                    error_string
+                })?;
+                let (width_mm, height_mm) = design_size_mm(&svg);
+                DesignFile {
+                    name: file_name.to_string(),
+                    tree: svg,
+                    svg: vec![],
+                    width_mm,
+                    height_mm,
+                    layers: vec![],
+                    warnings: ParseWarnings::default(),
+                }
+            } else {
+                DesignFile::from_bytes_at(file_name.to_string(), path, &bytes, bed).map_err(
+                    |err| {
+                        let details = match err {
+                            SendToDeviceError::ErrorParsingSvg(err) => format!("{err}"),
+                            err => format!("{err:?}"),
+                        };
+                        let error_string = format!("Error reading SVG file: {details}");
+                        log::error!("{error_string}");
+                        error_string
+                    },
+                )?
+            };
 
             bytes.hash(hasher);
             let hash = hasher.finish();
 
-            Ok((
-                DesignFile {
-                    name: file_name.to_string(),
-                    tree: svg,
-                    width_mm: width,
-                    height_mm: height,
-                },
-                hash,
-                path.clone(),
-            ))
+            Ok((design_file, hash, path.clone()))
         }
         Err(err) => Err(format!("Failed to read file: {}", err)),
     }
 }
 
+/// Remembers that a design has been opened, moving it to the top of the recent designs.
+///
+/// # Arguments
+/// * `recent_designs`: The designs that have been opened recently, most recent first.
+/// * `path`: The path of the design that has been opened.
+fn add_recent_design(recent_designs: &mut Vec<PathBuf>, path: PathBuf) {
+    recent_designs.retain(|recent| recent != &path);
+    recent_designs.insert(0, path);
+    recent_designs.truncate(MAX_RECENT_DESIGNS);
+}
+
 /// The focus is changing from one UI element to another.
 /// Makes decisions about whether to allow the focus to change and what to do about it.
 ///
@@ -1945,28 +3380,27 @@ fn focus_changing(
 ///
 /// # Returns
 /// An array of strings containing all of the possible capitalisations of the input string.
+/// The number of capitalisations doubles with each character, so strings longer than
+/// [`MAX_CAPITALISED_LENGTH`] characters are returned as they are.
 pub fn all_capitalisations_of(input: &str) -> Vec<String> {
-    let mut result = vec![];
-
-    let bitmask = ((2_u32.pow(input.len() as u32)) - 1) as usize;
+    let chars: Vec<char> = input.chars().collect();
+    let combinations = u32::try_from(chars.len())
+        .ok()
+        .filter(|length| *length <= MAX_CAPITALISED_LENGTH)
+        .and_then(|length| 1_u64.checked_shl(length));
+    let Some(combinations) = combinations else {
+        log::warn!("Not capitalising '{input}', it is too long");
+        return vec![input.to_string()];
+    };
 
-    for mask in 0..=bitmask {
+    let mut result = vec![];
+    for mask in 0..combinations {
         let mut new_str = String::new();
-        for i in 0..input.len() {
+        for (i, c) in chars.iter().enumerate() {
             if mask & (1 << i) > 0 {
-                new_str += &input
-                    .chars()
-                    .nth(i)
-                    .expect(&format!("Could not get character {i}"))
-                    .to_uppercase()
-                    .to_string();
+                new_str.extend(c.to_uppercase());
             } else {
-                new_str += &input
-                    .chars()
-                    .nth(i)
-                    .expect(&format!("Could not get character {i}"))
-                    .to_lowercase()
-                    .to_string();
+                new_str.extend(c.to_lowercase());
             }
         }
         result.push(new_str);
@@ -1977,7 +3411,13 @@ pub fn all_capitalisations_of(input: &str) -> Vec<String> {
 
 #[cfg(test)]
 mod test {
-    use super::all_capitalisations_of;
+    use std::path::PathBuf;
+
+    use seance::{BedMargins, Machine};
+
+    use super::{
+        add_recent_design, all_capitalisations_of, describe_bed_overflow, MAX_RECENT_DESIGNS,
+    };
 
     #[test]
     fn capitalisations() {
@@ -1987,6 +3427,61 @@ mod test {
         assert_eq!(
             result,
             vec!["SVG", "SVg", "SvG", "Svg", "sVG", "sVg", "svG", "svg"]
-        )
+        );
+
+        let long = "a".repeat(64);
+        assert_eq!(all_capitalisations_of(&long), vec![long.clone()]);
+    }
+
+    #[test]
+    fn recent_designs_are_most_recent_first() {
+        let mut recent = vec![];
+        for i in 0..MAX_RECENT_DESIGNS + 2 {
+            add_recent_design(&mut recent, PathBuf::from(format!("{i}.svg")));
+        }
+        add_recent_design(&mut recent, PathBuf::from("5.svg"));
+
+        assert_eq!(recent.len(), MAX_RECENT_DESIGNS);
+        assert_eq!(
+            recent[..3],
+            [
+                PathBuf::from("5.svg"),
+                PathBuf::from("11.svg"),
+                PathBuf::from("10.svg")
+            ]
+        );
+        assert_eq!(recent[MAX_RECENT_DESIGNS - 1], PathBuf::from("2.svg"));
+    }
+
+    #[test]
+    fn designs_larger_than_the_bed_are_described() {
+        let machine = Machine::GCC_SPIRIT;
+        let bed = machine.bed();
+        let (width_mm, height_mm) = (bed.width_mm(), bed.height_mm());
+        assert_eq!(describe_bed_overflow(bed, width_mm, height_mm), None);
+
+        let overflow = describe_bed_overflow(bed, width_mm + 12.5, height_mm)
+            .expect("Design should overflow the bed");
+        assert_eq!(
+            overflow.contains("12.5mm too wide for"),
+            true,
+            "Overflow should say how much too wide the design is: {overflow}"
+        );
+
+        // Designs have to fit inside the margins, not just on the bed.
+        let bed = bed
+            .clone()
+            .with_margins(BedMargins {
+                top: 5.0,
+                ..BedMargins::NONE
+            })
+            .expect("Margins should fit on the bed");
+        let overflow = describe_bed_overflow(&bed, width_mm, height_mm)
+            .expect("Design should overflow the margins");
+        assert_eq!(
+            overflow.contains("5.0mm too tall for"),
+            true,
+            "Overflow should say how much too tall the design is: {overflow}"
+        );
     }
 }
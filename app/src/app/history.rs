@@ -0,0 +1,134 @@
+//! `history`
+//!
+//! Keeps previous states around so that changes can be undone and redone.
+
+use std::{collections::VecDeque, mem};
+
+/// The states that can be undone to and redone to.
+pub struct UndoHistory<T> {
+    /// States that can be undone to, most recent last.
+    undo: VecDeque<T>,
+    /// States that can be redone to, most recent last.
+    redo: Vec<T>,
+    /// The most states that can be undone to, the oldest are forgotten after this.
+    capacity: usize,
+}
+
+impl<T> UndoHistory<T> {
+    /// Creates a new, empty [`UndoHistory`].
+    ///
+    /// # Arguments
+    /// * `capacity`: The most states that can be undone to.
+    ///
+    /// # Returns
+    /// A new [`UndoHistory`].
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            undo: VecDeque::with_capacity(capacity),
+            redo: vec![],
+            capacity,
+        }
+    }
+
+    /// Remembers a state from before a change was made, so that the change can be undone.
+    /// Anything that had been undone can no longer be redone.
+    ///
+    /// # Arguments
+    /// * `state`: The state before the change.
+    pub fn record(&mut self, state: T) {
+        self.push_undo(state);
+        self.redo.clear();
+    }
+
+    /// Undoes the most recent change.
+    ///
+    /// # Arguments
+    /// * `current`: The current state, which is replaced by the state before the change.
+    ///
+    /// # Returns
+    /// `true` if there was a change to undo.
+    pub fn undo(&mut self, current: &mut T) -> bool {
+        let Some(previous) = self.undo.pop_back() else {
+            return false;
+        };
+
+        self.redo.push(mem::replace(current, previous));
+        true
+    }
+
+    /// Redoes the most recently undone change.
+    ///
+    /// # Arguments
+    /// * `current`: The current state, which is replaced by the state after the change.
+    ///
+    /// # Returns
+    /// `true` if there was a change to redo.
+    pub fn redo(&mut self, current: &mut T) -> bool {
+        let Some(next) = self.redo.pop() else {
+            return false;
+        };
+
+        let previous = mem::replace(current, next);
+        self.push_undo(previous);
+        true
+    }
+
+    /// Adds a state that can be undone to, forgetting the oldest if there are too many.
+    ///
+    /// # Arguments
+    /// * `state`: The state to add.
+    fn push_undo(&mut self, state: T) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.undo.len() == self.capacity {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(state);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::UndoHistory;
+
+    #[test]
+    fn undo_and_redo() {
+        let mut history = UndoHistory::new(50);
+        let mut state = 1;
+
+        history.record(state);
+        state = 2;
+        history.record(state);
+        state = 3;
+
+        assert_eq!((history.undo(&mut state), state), (true, 2));
+        assert_eq!((history.undo(&mut state), state), (true, 1));
+        assert_eq!((history.undo(&mut state), state), (false, 1));
+
+        assert_eq!((history.redo(&mut state), state), (true, 2));
+
+        // A new change means the undone change can't come back.
+        history.record(state);
+        state = 4;
+        assert_eq!((history.redo(&mut state), state), (false, 4));
+        assert_eq!((history.undo(&mut state), state), (true, 2));
+    }
+
+    #[test]
+    fn oldest_states_are_forgotten() {
+        let mut history = UndoHistory::new(3);
+        let mut state = 0;
+        for next in 1..=5 {
+            history.record(state);
+            state = next;
+        }
+
+        let mut undone = vec![];
+        while history.undo(&mut state) {
+            undone.push(state);
+        }
+        assert_eq!(undone, vec![4, 3, 2]);
+    }
+}
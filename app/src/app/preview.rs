@@ -3,17 +3,28 @@
 //! Generates previews of design files.
 
 use std::{
+    collections::BTreeMap,
     sync::{Arc, Mutex, RwLock},
     time::Duration,
 };
 
-use egui::{ColorImage, ImageData, TextureHandle, TextureOptions};
+use egui::{
+    Align2, Color32, ColorImage, CursorIcon, FontId, ImageData, Rect, Sense, Stroke, TextureHandle,
+    TextureOptions,
+};
 use oneshot::TryRecvError;
-use resvg::{tiny_skia::Color, usvg};
+use resvg::{
+    tiny_skia::{self, Color},
+    usvg,
+};
 
-use seance::{DesignFile, BED_HEIGHT_MM, BED_WIDTH_MM};
+use seance::{
+    paths::{filter_paths_to_tool_passes, nearest_path_colour, trace_all_paths, travel_moves},
+    svg::get_paths_grouped_by_colour,
+    DesignFile, PathColour, PathInMM, PointInMillimeters, PrintBed, ToolPass,
+};
 
-use super::DesignWithMeta;
+use super::{DesignWithMeta, UIMessage, UIMessageTx};
 
 /// The maximum that we can zoom into the design preview.
 pub const MAX_ZOOM_LEVEL: f32 = 5.0;
@@ -23,16 +34,79 @@ pub const MIN_ZOOM_LEVEL: f32 = 1.05;
 /// The background colour for the design preview.
 const PREVIEW_BACKGROUND_COLOUR: [u8; 4] = [230, 230, 230, 255];
 
+/// The colour of the markers drawn across the bed.
+const GRID_MARKER_COLOUR: [u8; 4] = [100, 100, 100, 255];
+
+/// The default distance between the markers drawn across the bed, in mm.
+pub const DEFAULT_GRID_SPACING_MM: f32 = 10.0;
+
+/// The distances between the markers drawn across the bed that the user can choose from, in mm.
+pub const GRID_SPACING_OPTIONS_MM: [f32; 4] = [1.0, 5.0, DEFAULT_GRID_SPACING_MM, 50.0];
+
+/// The size of the markers drawn across the bed, in mm. Markers are made smaller for finer grids.
+const GRID_MARKER_SIZE_MM: f32 = 2.0;
+
+/// The closest together that markers are drawn, in pixels. When zoomed out, a fine grid only has
+/// some of its markers drawn, so that they can still be told apart.
+const MIN_GRID_MARKER_GAP_PIXELS: f32 = 4.0;
+
+/// The colour behind the rulers along the top and left of the preview.
+const RULER_BACKGROUND_COLOUR: [u8; 4] = [255, 255, 255, 200];
+
+/// The thickness of the rulers along the top and left of the preview, in pixels.
+const RULER_THICKNESS_PIXELS: f32 = 20.0;
+
+/// The size of the text on the rulers, in points.
+const RULER_FONT_SIZE: f32 = 10.0;
+
+/// The length of the ticks marking where each ruler label is, in pixels.
+const RULER_TICK_LENGTH_PIXELS: f32 = 4.0;
+
+/// The distances between the labels on the rulers that can be used, in mm.
+/// The smallest that leaves room between the labels at the current zoom level is used.
+const RULER_LABEL_STEPS_MM: [f32; 8] = [1.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0, 500.0];
+
+/// The closest together that labels on the rulers are drawn, in pixels.
+const MIN_RULER_LABEL_GAP_PIXELS: f32 = 40.0;
+
+/// The colour of the lines showing where the toolhead moves between paths with the tool off.
+const TRAVEL_LINE_COLOUR: [u8; 4] = [0, 150, 255, 120];
+
+/// The length of the dashes, and the gaps between them, in the travel lines, in pixels.
+const TRAVEL_LINE_DASH_LENGTH: f32 = 4.0;
+
+/// How far from a path, in pixels, the user can click to pick its colour.
+const PICK_COLOUR_TOLERANCE_PIXELS: f32 = 5.0;
+
+/// How long the render thread waits between checking for new render requests, in milliseconds.
+const RENDER_POLL_INTERVAL_MS: u64 = 10;
+
 /// The cache for the design preview.
+///
+/// The design is only rasterised when it (or what will be cut from it) changes,
+/// moving and zooming are done by egui when the texture is drawn.
 pub struct DesignPreview {
     /// The size of the preview.
     size: egui::Vec2,
+    /// The bed that the preview shows, whose origin the grid and rulers are measured from.
+    bed: PrintBed,
     /// The current zoom level.
     zoom: f32,
     /// How much the design is offset (in mm) from top-left corner.
     design_offset_mm: egui::Vec2,
-    /// The texture handle created from the texture buffer, this is what egui uses to draw the preview in the UI.
-    image_texture: Option<TextureHandle>,
+    /// The tool passes that will be run, used to work out the travel between paths
+    /// and which paths will be cut.
+    tool_passes: Vec<ToolPass>,
+    /// Whether to show all paths, rather than just those that will be cut.
+    show_all_paths: bool,
+    /// The distance between the markers drawn across the bed in mm, or `None` if they are hidden.
+    grid_spacing_mm: Option<f32>,
+    /// Where to scroll the preview to the next time it is drawn, so that zooming stays centred on the pointer.
+    pending_scroll_offset: Option<egui::Vec2>,
+    /// The rasterised design and its size in mm, this is what egui uses to draw the design in the UI.
+    design_texture: Option<(TextureHandle, egui::Vec2)>,
+    /// Every path in the rendered design, used to work out which path has been clicked on.
+    design_paths: BTreeMap<PathColour, Vec<PathInMM>>,
     /// Where to put requests to re-render.
     render_request: Arc<Mutex<Option<RenderRequest>>>,
     /// The callback for the latest render request. Callbacks for old requests will be dropped.
@@ -44,42 +118,45 @@ impl DesignPreview {
     ///
     /// # Arguments
     /// * `size`: The size to draw the preview at.
+    /// * `bed`: The bed that the preview shows.
     /// * `zoom`: The current zoom level.
     /// * `design_file`: The design file to draw the preview for.
+    /// * `tool_passes`: The tool passes that will be run.
+    /// * `show_all_paths`: Whether to show all paths, rather than just those that will be cut.
+    /// * `grid_spacing_mm`: The distance between the markers drawn across the bed in mm, or `None` to hide them.
     /// * `render_request_tx`: Where to put requests to re-render.
     ///
     /// # Returns
     /// A new [`DesignPreview`].
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         size: egui::Vec2,
+        bed: PrintBed,
         mut zoom: f32,
         design_file: &Arc<RwLock<Option<DesignWithMeta>>>,
+        tool_passes: &[ToolPass],
+        show_all_paths: bool,
+        grid_spacing_mm: Option<f32>,
         render_request: Arc<Mutex<Option<RenderRequest>>>,
     ) -> Self {
         zoom = zoom.min(MAX_ZOOM_LEVEL).max(MIN_ZOOM_LEVEL);
-        let image_texture = None;
-
-        let (callback_tx, callback_rx) = oneshot::channel();
-        {
-            let mut render_request_lock = render_request
-                .lock()
-                .expect("Render requests mutex must be lockable");
-            *render_request_lock = Some(RenderRequest {
-                size: size.clone(),
-                design_offset_mm: Default::default(),
-                design_file: design_file.clone(),
-                callback: callback_tx,
-            });
-        }
 
-        Self {
+        let mut preview = Self {
             size,
+            bed,
             zoom,
             design_offset_mm: Default::default(),
-            image_texture,
+            tool_passes: tool_passes.to_vec(),
+            show_all_paths,
+            grid_spacing_mm,
+            pending_scroll_offset: None,
+            design_texture: None,
+            design_paths: BTreeMap::new(),
             render_request,
-            waiting_render_callback: Some(callback_rx),
-        }
+            waiting_render_callback: None,
+        };
+        preview.render(design_file);
+        preview
     }
 
     /// Resizes the deisgn preview.
@@ -94,6 +171,28 @@ impl DesignPreview {
         }
     }
 
+    /// Sets the bed that the preview shows, re-rendering if it has changed as the design is drawn to the bed's scale.
+    /// The design is moved back onto the bed if it no longer fits where it was.
+    ///
+    /// # Arguments
+    /// * `bed`: The bed.
+    /// * `design_file`: The design file being drawn.
+    pub fn set_bed(&mut self, bed: &PrintBed, design_file: &Arc<RwLock<Option<DesignWithMeta>>>) {
+        if *bed != self.bed {
+            self.bed = bed.clone();
+            self.set_design_offset(self.design_offset_mm);
+            self.render(design_file);
+        }
+    }
+
+    /// Gets the size of the bed that the preview shows.
+    ///
+    /// # Returns
+    /// The size of the bed, in mm.
+    fn bed_size_mm(&self) -> egui::Vec2 {
+        egui::vec2(self.bed.width_mm(), self.bed.height_mm())
+    }
+
     /// Sets the zoom level of the design preview.
     ///
     /// # Arguments
@@ -105,6 +204,42 @@ impl DesignPreview {
         }
     }
 
+    /// Zooms the preview in or out, keeping the part of the bed under the pointer where it is.
+    ///
+    /// # Arguments
+    /// * `zoom_delta`: How much to multiply the zoom level by.
+    /// * `pointer`: Where the pointer is, relative to the top-left of the visible part of the preview.
+    /// * `scroll_offset`: How far the preview is currently scrolled.
+    /// * `ui_message_tx`: A channel that UI events can be sent into.
+    pub fn zoom_towards(
+        &mut self,
+        zoom_delta: f32,
+        pointer: egui::Vec2,
+        scroll_offset: egui::Vec2,
+        ui_message_tx: &UIMessageTx,
+    ) {
+        let zoom = (self.zoom * zoom_delta)
+            .min(MAX_ZOOM_LEVEL)
+            .max(MIN_ZOOM_LEVEL);
+        if zoom == self.zoom {
+            return;
+        }
+
+        // The point under the pointer moves away from the top-left by the same factor as the zoom.
+        let scale = zoom / self.zoom;
+        self.pending_scroll_offset =
+            Some((((scroll_offset + pointer) * scale) - pointer).max(egui::Vec2::ZERO));
+        let _ = ui_message_tx.send(UIMessage::PreviewZoomLevelChanged { zoom });
+    }
+
+    /// Takes where the preview should be scrolled to, if it needs to move.
+    ///
+    /// # Returns
+    /// The scroll offset to apply, if any.
+    pub fn take_scroll_offset(&mut self) -> Option<egui::Vec2> {
+        self.pending_scroll_offset.take()
+    }
+
     /// Gets the current offset of the design from the top-left corner, in mm.
     ///
     /// # Returns
@@ -114,23 +249,65 @@ impl DesignPreview {
     }
 
     /// Sets the offset of the design from the top-left corner, in mm.
+    /// The design is kept on the bed, and doesn't need re-rendering as it is just drawn somewhere else.
     ///
     /// # Arguments
     /// * `offset_mm`: The offset to set.
-    /// * `design_file`: The design file to be offset.
-    pub fn set_design_offset(
+    pub fn set_design_offset(&mut self, mut offset_mm: egui::Vec2) {
+        let fit = self
+            .design_texture
+            .as_ref()
+            .and_then(|(_, size_mm)| self.bed.fit_offset_range(size_mm.x, size_mm.y));
+        if let Some((x_range, y_range)) = fit {
+            offset_mm.x = offset_mm.x.clamp(*x_range.start(), *x_range.end());
+            offset_mm.y = offset_mm.y.clamp(*y_range.start(), *y_range.end());
+        }
+        offset_mm.x = offset_mm.x.max(0.0);
+        offset_mm.y = offset_mm.y.max(0.0);
+        self.design_offset_mm = offset_mm;
+    }
+
+    /// Sets the tool passes that will be run, re-rendering if they have changed.
+    ///
+    /// # Arguments
+    /// * `tool_passes`: The tool passes.
+    /// * `design_file`: The design file being drawn.
+    pub fn set_tool_passes(
         &mut self,
-        mut offset_mm: egui::Vec2,
+        tool_passes: &[ToolPass],
         design_file: &Arc<RwLock<Option<DesignWithMeta>>>,
     ) {
-        offset_mm.x = offset_mm.x.max(0.0);
-        offset_mm.y = offset_mm.y.max(0.0);
-        if offset_mm != self.design_offset_mm {
-            self.design_offset_mm = offset_mm;
+        if tool_passes != self.tool_passes {
+            self.tool_passes = tool_passes.to_vec();
+            self.render(design_file);
+        }
+    }
+
+    /// Sets whether to show all paths, rather than just those that will be cut.
+    ///
+    /// # Arguments
+    /// * `show_all_paths`: Whether to show all paths.
+    /// * `design_file`: The design file being drawn.
+    pub fn set_show_all_paths(
+        &mut self,
+        show_all_paths: bool,
+        design_file: &Arc<RwLock<Option<DesignWithMeta>>>,
+    ) {
+        if show_all_paths != self.show_all_paths {
+            self.show_all_paths = show_all_paths;
             self.render(design_file);
         }
     }
 
+    /// Sets the distance between the markers drawn across the bed.
+    /// The markers are drawn with the bed, so the design doesn't need re-rendering.
+    ///
+    /// # Arguments
+    /// * `grid_spacing_mm`: The distance between the markers in mm, or `None` to hide them.
+    pub fn set_grid_spacing(&mut self, grid_spacing_mm: Option<f32>) {
+        self.grid_spacing_mm = grid_spacing_mm;
+    }
+
     /// Checks if we are currently rendering a preview of the design.
     ///
     /// # Returns
@@ -139,68 +316,210 @@ impl DesignPreview {
         self.waiting_render_callback.is_some()
     }
 
-    /// Gets the image to be drawn to the UI.
+    /// Checks whether there is a rendered design that can be drawn, picking up any newly rendered design.
     ///
     /// # Arguments
     /// * `ctx`: egui context that can be used to allocate resources if needed.
     /// * `design_file`: The file to render if we need to request a re-render.
     ///
     /// # Returns
-    /// The image to draw to the UI as the design preview, if any is available.
-    pub fn image(
+    /// `true` if there is a design to draw.
+    pub fn is_ready(
         &mut self,
         ctx: &egui::Context,
         design_file: &Arc<RwLock<Option<DesignWithMeta>>>,
-    ) -> Option<egui::Image<'_>> {
-        let mut waiting_render_callback = self.waiting_render_callback.take();
-        if let Some(waiting) = waiting_render_callback {
+    ) -> bool {
+        if let Some(waiting) = self.waiting_render_callback.take() {
             match waiting.try_recv() {
-                Ok(img) => {
-                    let texture = ctx.load_texture(
-                        "design",
-                        ImageData::Color(img.image.into()),
-                        TextureOptions::default(),
-                    );
-                    self.image_texture = Some(texture);
-                    waiting_render_callback = None;
+                Ok(rendered) => {
+                    self.design_texture = rendered.design.map(|(image, size_mm)| {
+                        let texture = ctx.load_texture(
+                            "design",
+                            ImageData::Color(image.into()),
+                            TextureOptions::default(),
+                        );
+                        (texture, size_mm)
+                    });
+                    self.design_paths = rendered.paths;
                 }
-                Err(TryRecvError::Disconnected) => {
-                    let (callback_tx, callback_rx) = oneshot::channel();
-                    {
-                        let mut render_request_lock = self
-                            .render_request
-                            .lock()
-                            .expect("Render requests mutex must be lockable");
-                        *render_request_lock = Some(RenderRequest {
-                            size: self.size,
-                            design_offset_mm: self.design_offset_mm,
-                            design_file: design_file.clone(),
-                            callback: callback_tx,
-                        });
+                Err(TryRecvError::Disconnected) => self.render(design_file),
+                Err(TryRecvError::Empty) => self.waiting_render_callback = Some(waiting),
+            }
+        }
+
+        self.design_texture.is_some()
+    }
+
+    /// Draws the bed, with the design on it, at the current zoom level.
+    /// The design can be dragged around the bed, or clicked on to pick a colour for a tool pass.
+    ///
+    /// # Arguments
+    /// * `ui`: The UI to draw the preview into.
+    /// * `picking_colour_for`: The index of the tool pass to set the colour of when a path is clicked, if any.
+    /// * `ui_message_tx`: A channel that UI events can be sent into.
+    ///
+    /// # Returns
+    /// An [`egui::Response`] covering the whole bed.
+    pub fn show(
+        &self,
+        ui: &mut egui::Ui,
+        picking_colour_for: Option<usize>,
+        ui_message_tx: &UIMessageTx,
+    ) -> egui::Response {
+        let (bed_rect, response) =
+            ui.allocate_exact_size((self.size * self.zoom).floor(), Sense::hover());
+        let painter = ui.painter_at(bed_rect);
+
+        let pixels_per_mm = bed_rect.size() / self.bed_size_mm();
+        paint_bed(
+            &painter,
+            bed_rect,
+            pixels_per_mm,
+            self.grid_spacing_mm,
+            DeviceAxis::of_bed(&self.bed),
+        );
+
+        if let Some((texture, size_mm)) = &self.design_texture {
+            let design_rect = Rect::from_min_size(
+                bed_rect.min + (self.design_offset_mm * pixels_per_mm),
+                *size_mm * pixels_per_mm,
+            );
+            painter.image(
+                texture.id(),
+                design_rect,
+                Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+                Color32::WHITE,
+            );
+
+            if let Some(index) = picking_colour_for {
+                let pick_response = ui
+                    .interact(
+                        design_rect.intersect(bed_rect),
+                        ui.id().with("design"),
+                        Sense::click(),
+                    )
+                    .on_hover_cursor(CursorIcon::Crosshair);
+                let clicked_at = pick_response
+                    .interact_pointer_pos()
+                    .filter(|_| pick_response.clicked());
+                if let Some(clicked_at) = clicked_at {
+                    let clicked_at_mm = (clicked_at - design_rect.min) / pixels_per_mm;
+                    if let Some(colour) = nearest_path_colour(
+                        &self.design_paths,
+                        PointInMillimeters {
+                            x: clicked_at_mm.x,
+                            y: clicked_at_mm.y,
+                        },
+                        PICK_COLOUR_TOLERANCE_PIXELS / pixels_per_mm.x,
+                    ) {
+                        let _ =
+                            ui_message_tx.send(UIMessage::ToolPassColourChanged { index, colour });
+                        let _ = ui_message_tx.send(UIMessage::StopPickingToolPassColour);
                     }
-                    waiting_render_callback = Some(callback_rx);
                 }
-                Err(TryRecvError::Empty) => {
-                    waiting_render_callback = Some(waiting);
+
+                return response;
+            }
+
+            let design_response = ui
+                .interact(
+                    design_rect.intersect(bed_rect),
+                    ui.id().with("design"),
+                    Sense::drag(),
+                )
+                .on_hover_cursor(CursorIcon::Grab);
+            if design_response.dragged() {
+                ui.ctx().set_cursor_icon(CursorIcon::Grabbing);
+                let delta = design_response.drag_delta();
+                if delta != egui::Vec2::ZERO {
+                    let _ = ui_message_tx.send(UIMessage::DesignOffsetChanged {
+                        offset_mm: self.design_offset_mm + (delta / pixels_per_mm),
+                    });
                 }
             }
         }
-        self.waiting_render_callback = waiting_render_callback;
 
-        let Some(texture) = &self.image_texture else {
-            return None;
-        };
-
-        let zoomed_bounding_box_width = self.size.x * self.zoom;
-        let zoomed_bounding_box_height = self.size.y * self.zoom;
+        response
+    }
 
-        let texture_width = zoomed_bounding_box_width.floor();
-        let texture_height = zoomed_bounding_box_height.floor();
+    /// Draws rulers along the top and left of the preview, labelling the bed in mm from the device's origin.
+    /// The labels are kept on the grid, and spaced out to suit the current zoom level.
+    ///
+    /// # Arguments
+    /// * `ui`: The UI to draw the rulers into.
+    /// * `rect`: The visible part of the preview.
+    /// * `scroll_offset`: How far the preview is scrolled.
+    pub fn show_rulers(&self, ui: &egui::Ui, rect: Rect, scroll_offset: egui::Vec2) {
+        let bed_size = (self.size * self.zoom).floor();
+        let bed_size_mm = self.bed_size_mm();
+        let pixels_per_mm = bed_size / bed_size_mm;
+        let step_mm = ruler_step_mm(pixels_per_mm.min_elem(), self.grid_spacing_mm);
+        let (x_axis, y_axis) = DeviceAxis::of_bed(&self.bed);
+
+        let painter = ui.painter_at(rect);
+        let [r, g, b, a] = RULER_BACKGROUND_COLOUR;
+        let background = Color32::from_rgba_unmultiplied(r, g, b, a);
+        let [r, g, b, a] = GRID_MARKER_COLOUR;
+        let text_colour = Color32::from_rgba_unmultiplied(r, g, b, a);
+        let font = FontId::monospace(RULER_FONT_SIZE);
+
+        let top = Rect::from_min_max(
+            rect.min,
+            egui::pos2(rect.max.x, rect.min.y + RULER_THICKNESS_PIXELS),
+        );
+        let left = Rect::from_min_max(
+            rect.min,
+            egui::pos2(rect.min.x + RULER_THICKNESS_PIXELS, rect.max.y),
+        );
+        painter.rect_filled(top, 0.0, background);
+        painter.rect_filled(left, 0.0, background);
+
+        // Labels along the top are for the X axis, those down the left are for the Y axis.
+        let visible_x_mm = (scroll_offset.x / pixels_per_mm.x)
+            ..=((scroll_offset.x + rect.width()) / pixels_per_mm.x).min(bed_size_mm.x);
+        for (mm, label_mm) in marks_along_axis(visible_x_mm, step_mm, x_axis) {
+            let x = rect.min.x + (mm * pixels_per_mm.x) - scroll_offset.x;
+            if x < left.max.x {
+                continue;
+            }
+            painter.text(
+                egui::pos2(x, top.center().y),
+                Align2::CENTER_CENTER,
+                format!("{label_mm}"),
+                font.clone(),
+                text_colour,
+            );
+            painter.line_segment(
+                [
+                    egui::pos2(x, top.max.y - RULER_TICK_LENGTH_PIXELS),
+                    egui::pos2(x, top.max.y),
+                ],
+                Stroke::new(1.0, text_colour),
+            );
+        }
 
-        let image = egui::Image::from_texture(texture)
-            .max_width(texture_width)
-            .max_height(texture_height);
-        Some(image)
+        let visible_y_mm = (scroll_offset.y / pixels_per_mm.y)
+            ..=((scroll_offset.y + rect.height()) / pixels_per_mm.y).min(bed_size_mm.y);
+        for (mm, label_mm) in marks_along_axis(visible_y_mm, step_mm, y_axis) {
+            let y = rect.min.y + (mm * pixels_per_mm.y) - scroll_offset.y;
+            if y < top.max.y {
+                continue;
+            }
+            painter.text(
+                egui::pos2(left.center().x, y),
+                Align2::CENTER_CENTER,
+                format!("{label_mm}"),
+                font.clone(),
+                text_colour,
+            );
+            painter.line_segment(
+                [
+                    egui::pos2(left.max.x - RULER_TICK_LENGTH_PIXELS, y),
+                    egui::pos2(left.max.x, y),
+                ],
+                Stroke::new(1.0, text_colour),
+            );
+        }
     }
 
     /// Request that the design preview be rendered.
@@ -216,8 +535,10 @@ impl DesignPreview {
                 .expect("Render requests mutex must be lockable");
             *render_request_lock = Some(RenderRequest {
                 size: self.size,
-                design_offset_mm: self.design_offset_mm,
+                bed_size_mm: self.bed_size_mm(),
                 design_file: design_file.clone(),
+                tool_passes: self.tool_passes.clone(),
+                show_all_paths: self.show_all_paths,
                 callback: callback_tx,
             });
         }
@@ -225,20 +546,183 @@ impl DesignPreview {
     }
 }
 
+/// Draws the empty bed, with a grid of markers to give the user a point of reference.
+/// The grid starts from the device's origin, so that the markers line up with where the device measures from.
+///
+/// # Arguments
+/// * `painter`: The painter to draw with, clipped to the bed.
+/// * `bed_rect`: Where the bed is in the UI.
+/// * `pixels_per_mm`: How many pixels correspond to 1mm in each dimension.
+/// * `grid_spacing_mm`: The distance between the markers in mm, or `None` to not draw them.
+/// * `(x_axis, y_axis)`: How positions across and down the bed are measured on the device.
+fn paint_bed(
+    painter: &egui::Painter,
+    bed_rect: Rect,
+    pixels_per_mm: egui::Vec2,
+    grid_spacing_mm: Option<f32>,
+    (x_axis, y_axis): (DeviceAxis, DeviceAxis),
+) {
+    let [r, g, b, a] = PREVIEW_BACKGROUND_COLOUR;
+    painter.rect_filled(bed_rect, 0.0, Color32::from_rgba_unmultiplied(r, g, b, a));
+
+    let Some(grid_spacing_mm) = grid_spacing_mm.filter(|spacing| *spacing > 0.0) else {
+        return;
+    };
+
+    let [r, g, b, a] = GRID_MARKER_COLOUR;
+    let marker_colour = Color32::from_rgba_unmultiplied(r, g, b, a);
+    let marker_size = pixels_per_mm * GRID_MARKER_SIZE_MM.min(grid_spacing_mm / 2.0);
+
+    // Skip markers that would be too close together to see, keeping the rest on multiples of the spacing.
+    let spacing_pixels = grid_spacing_mm * pixels_per_mm.min_elem();
+    let step_mm = grid_spacing_mm
+        * (MIN_GRID_MARKER_GAP_PIXELS / spacing_pixels)
+            .ceil()
+            .max(1.0);
+
+    // A fine grid has a lot of markers, so only those in the visible part of the bed are drawn.
+    let visible = painter.clip_rect().expand2(marker_size / 2.0);
+    let first = ((visible.min - bed_rect.min) / pixels_per_mm).max(egui::Vec2::ZERO);
+    let last = ((visible.max - bed_rect.min) / pixels_per_mm).min(bed_rect.size() / pixels_per_mm);
+    let columns_mm: Vec<f32> = marks_along_axis(first.x..=last.x, step_mm, x_axis)
+        .map(|(mm, _)| mm)
+        .collect();
+    for (y_mm, _) in marks_along_axis(first.y..=last.y, step_mm, y_axis) {
+        for x_mm in &columns_mm {
+            let centre = bed_rect.min + (egui::vec2(*x_mm, y_mm) * pixels_per_mm);
+            painter.rect_filled(
+                Rect::from_center_size(centre, marker_size),
+                0.0,
+                marker_colour,
+            );
+        }
+    }
+}
+
+/// Works out how far apart to put the labels on the rulers.
+///
+/// # Arguments
+/// * `pixels_per_mm`: How many pixels correspond to 1mm.
+/// * `grid_spacing_mm`: The distance between the markers drawn across the bed in mm, `None` if they are hidden.
+///
+/// # Returns
+/// The distance between the labels, in mm.
+fn ruler_step_mm(pixels_per_mm: f32, grid_spacing_mm: Option<f32>) -> f32 {
+    RULER_LABEL_STEPS_MM
+        .into_iter()
+        .filter(|step| grid_spacing_mm.map_or(true, |spacing| step % spacing == 0.0))
+        .find(|step| step * pixels_per_mm >= MIN_RULER_LABEL_GAP_PIXELS)
+        .unwrap_or(RULER_LABEL_STEPS_MM[RULER_LABEL_STEPS_MM.len() - 1])
+}
+
+/// How positions along an axis of the preview are measured on the device.
+/// The device's origin isn't always at the top-left of the bed, and its axis can run the other way to the preview's.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct DeviceAxis {
+    /// Where the device's origin is along the axis, in mm from the top or left of the preview.
+    origin_mm: f32,
+    /// Whether the device's axis runs the opposite way to the preview's.
+    mirrored: bool,
+}
+
+impl DeviceAxis {
+    /// Gets how positions across and down the preview are measured on the device.
+    ///
+    /// # Arguments
+    /// * `bed`: The bed that the preview shows.
+    ///
+    /// # Returns
+    /// How the X and Y axes of the preview are measured.
+    fn of_bed(bed: &PrintBed) -> (Self, Self) {
+        let origin = bed.device_origin();
+        (
+            DeviceAxis {
+                origin_mm: origin.x - bed.x_axis().start(),
+                mirrored: false,
+            },
+            DeviceAxis {
+                origin_mm: origin.y - bed.y_axis().start(),
+                mirrored: bed.is_y_mirrored(),
+            },
+        )
+    }
+
+    /// Converts a position along the preview into a position on the device.
+    ///
+    /// # Arguments
+    /// * `preview_mm`: The position, in mm from the top or left of the preview.
+    ///
+    /// # Returns
+    /// The position, in mm from the device's origin.
+    fn to_device_mm(self, preview_mm: f32) -> f32 {
+        if self.mirrored {
+            self.origin_mm - preview_mm
+        } else {
+            preview_mm - self.origin_mm
+        }
+    }
+
+    /// Converts a position on the device into a position along the preview.
+    ///
+    /// # Arguments
+    /// * `device_mm`: The position, in mm from the device's origin.
+    ///
+    /// # Returns
+    /// The position, in mm from the top or left of the preview.
+    fn to_preview_mm(self, device_mm: f32) -> f32 {
+        if self.mirrored {
+            self.origin_mm - device_mm
+        } else {
+            self.origin_mm + device_mm
+        }
+    }
+}
+
+/// Gets the positions along an axis that are a whole number of steps from the device's origin,
+/// which is where the grid markers and ruler labels go.
+///
+/// # Arguments
+/// * `visible_mm`: The part of the axis that can be seen, in mm from the top or left of the preview.
+/// * `step_mm`: The distance between the positions, in mm.
+/// * `axis`: How positions along the axis are measured on the device.
+///
+/// # Returns
+/// Each position, in mm from the top or left of the preview, with its distance from the device's origin in mm.
+fn marks_along_axis(
+    visible_mm: std::ops::RangeInclusive<f32>,
+    step_mm: f32,
+    axis: DeviceAxis,
+) -> impl Iterator<Item = (f32, f32)> {
+    let start = axis.to_device_mm(*visible_mm.start());
+    let end = axis.to_device_mm(*visible_mm.end());
+    let first = (start.min(end) / step_mm).ceil() as i32;
+    let last = (start.max(end) / step_mm).floor() as i32;
+    (first..=last).map(move |index| {
+        let device_mm = index as f32 * step_mm;
+        (axis.to_preview_mm(device_mm), device_mm)
+    })
+}
+
 /// The result of rendering the design preview.
 pub struct RenderedImage {
-    /// The resulting image.
-    image: ColorImage,
+    /// The rendered design and its size in mm, or `None` if there is no design to render.
+    design: Option<(ColorImage, egui::Vec2)>,
+    /// Every path in the design, in mm from the top-left of the design.
+    paths: BTreeMap<PathColour, Vec<PathInMM>>,
 }
 
 /// Request that a design preview be rendered for the given design file.
 pub struct RenderRequest {
     /// The size of the preview to render.
     size: egui::Vec2,
-    /// Offset of the design from the top-left corner, in mm.
-    design_offset_mm: egui::Vec2,
+    /// The size of the bed that the preview shows, in mm.
+    bed_size_mm: egui::Vec2,
     /// The design file to render.
     design_file: Arc<RwLock<Option<DesignWithMeta>>>,
+    /// The tool passes that will be run.
+    tool_passes: Vec<ToolPass>,
+    /// Whether to show all paths, rather than just those that will be cut.
+    show_all_paths: bool,
     /// Callback to send the rendered preview into.
     callback: RenderRequestCallback,
 }
@@ -246,15 +730,21 @@ pub struct RenderRequest {
 /// Callbacks for rendered design previews.
 pub type RenderRequestCallback = oneshot::Sender<RenderedImage>;
 
+/// The traced paths of the last design that was rendered, so that the design doesn't have to be
+/// traced again when only the tool passes or the size of the preview change.
+struct TracedDesign {
+    /// The hash of the design that was traced.
+    hash: u64,
+    /// Every path in the design, in mm from the top-left of the design.
+    paths: BTreeMap<PathColour, Vec<PathInMM>>,
+}
+
 /// Long-running task to render design previews in the background.
 ///
 /// # Arguments
 /// * `render_request`: Location where a render request can be read from. The request will be taken and replaced with `None`.
 pub fn render_task(render_request: Arc<Mutex<Option<RenderRequest>>>) {
-    let mut texture_buffer: Vec<u8> = vec![];
-    let mut previous_design_hash: Option<u64> = None;
-    let mut design_texture: Option<resvg::tiny_skia::Pixmap> = None;
-
+    let mut traced: Option<TracedDesign> = None;
     loop {
         let request = {
             let Ok(mut request_lock) = render_request.lock() else {
@@ -267,196 +757,257 @@ pub fn render_task(render_request: Arc<Mutex<Option<RenderRequest>>>) {
 
         if let Some(RenderRequest {
             size,
-            design_offset_mm,
+            bed_size_mm,
             design_file,
+            tool_passes,
+            show_all_paths,
             callback,
         }) = request
         {
             render_inner(
                 size,
-                &design_offset_mm,
+                bed_size_mm,
                 &design_file,
-                &mut texture_buffer,
-                &mut previous_design_hash,
-                &mut design_texture,
+                &tool_passes,
+                show_all_paths,
+                &mut traced,
                 callback,
             );
         }
 
-        // TODO: Nasty.
-        std::thread::sleep(Duration::from_millis(100));
+        // Moving and zooming the design happen when the texture is drawn, so only changes
+        // to the design itself come through here and polling is fine.
+        std::thread::sleep(Duration::from_millis(RENDER_POLL_INTERVAL_MS));
     }
 }
 
-/// Does the actual rendering of the design preview.
-///
-/// TODO: Really we should hand off to the GPU.
+/// Rasterises the design, ready to be uploaded as a texture.
 ///
 /// # Arguments
 /// * `size`: The size to draw the preview at.
-/// * `offset_mm`: The offset of the design from the top-left corner, in mm.
+/// * `bed_size_mm`: The size of the bed that the preview shows, in mm.
 /// * `design_file`: The design file to render.
-/// * `texture_buffer`: This is the texture that is actually shown to the user.
-/// * `previous_design_hash`: The previous hash of the design file.
-/// * `design_texture`: The texture to render an SVG design into.
+/// * `tool_passes`: The tool passes that will be run.
+/// * `show_all_paths`: Whether to show all paths, rather than just those that will be cut.
+/// * `cache`: The traced paths of the last design that was rendered, replaced if the design has changed.
 /// * `callback`: Callback into which the rendered image will be sent.
 fn render_inner(
     size: egui::Vec2,
-    offset_mm: &egui::Vec2,
+    bed_size_mm: egui::Vec2,
     design_file: &Arc<RwLock<Option<DesignWithMeta>>>,
-    texture_buffer: &mut Vec<u8>,
-    previous_design_hash: &mut Option<u64>,
-    design_texture: &mut Option<resvg::tiny_skia::Pixmap>,
+    tool_passes: &[ToolPass],
+    show_all_paths: bool,
+    cache: &mut Option<TracedDesign>,
     callback: RenderRequestCallback,
 ) {
-    // Calculate how big the texture should be.
-    let zoomed_bounding_box_width = size.x * MAX_ZOOM_LEVEL;
-    let zoomed_bounding_box_height = size.y * MAX_ZOOM_LEVEL;
-    let texture_width = zoomed_bounding_box_width.floor() as u32;
-    let texture_height = zoomed_bounding_box_height.floor() as u32;
-
-    // Resize texture buffer to fill the bounds.
-    resize_texture_buffer(
-        texture_buffer,
-        texture_width as usize,
-        texture_height as usize,
-    );
-
     let Ok(design_lock) = design_file.read() else {
         log::error!("Failed to lock design file for render");
         return;
     };
-    let design = &*design_lock;
 
-    // If we have a design file then we need to check if the hash has changed, if so then we need to re-render the design.
-    if let Some((
+    let Some((
         DesignFile {
             name: _,
             tree,
+            svg: _,
             width_mm,
             height_mm,
+            layers: _,
+            warnings: _,
         },
         hash,
         _,
-    )) = &design
-    {
-        if Some(*hash) != *previous_design_hash {
-            *previous_design_hash = Some(*hash);
-
-            // Work out the proportion of the bed taken up by the design, then scale the image by this proportion and the zoom level.
-            let width = (width_mm / BED_WIDTH_MM) * size.x * MAX_ZOOM_LEVEL;
-            let height = (height_mm / BED_HEIGHT_MM) * size.y * MAX_ZOOM_LEVEL;
-
-            // Create a pixmap to render to that is the scaled width and height of the design.
-            let Some(mut pixmap) =
-                resvg::tiny_skia::Pixmap::new((width).ceil() as u32, (height).ceil() as u32)
-            else {
-                log::error!("Could not create pixmap for rendering design preview");
-                invalidate_design_texture(previous_design_hash, design_texture);
-                return;
-            };
+    )) = &*design_lock
+    else {
+        let _ = callback.send(RenderedImage {
+            design: None,
+            paths: BTreeMap::new(),
+        });
+        return;
+    };
 
-            // Fill the pixmap with the background colour.
-            pixmap.fill(Color::from_rgba8(
-                PREVIEW_BACKGROUND_COLOUR[0],
-                PREVIEW_BACKGROUND_COLOUR[1],
-                PREVIEW_BACKGROUND_COLOUR[2],
-                PREVIEW_BACKGROUND_COLOUR[3],
-            ));
-            // Render the design at the origin of the pixmap.
-            let transform = usvg::Transform::default();
-            resvg::render(&tree, transform, &mut pixmap.as_mut());
-            *design_texture = Some(pixmap);
-        }
-    } else {
-        invalidate_design_texture(previous_design_hash, design_texture);
-    }
+    // Work out the proportion of the bed taken up by the design, then scale the image by this proportion
+    // and the maximum zoom level, so that it stays sharp however far the user zooms in.
+    let width = (width_mm / bed_size_mm.x) * size.x * MAX_ZOOM_LEVEL;
+    let height = (height_mm / bed_size_mm.y) * size.y * MAX_ZOOM_LEVEL;
 
-    // Work out how many pixels correspond to 1mm in each dimension.
-    let pixels_per_mm_x = zoomed_bounding_box_width / BED_WIDTH_MM;
-    let pixels_per_mm_y = zoomed_bounding_box_height / BED_HEIGHT_MM;
-
-    // We want to place a marker every 10mm to give the user a point of reference, so we need to work out how many pixels correspond to 10mm.
-    let pixels_per_10_mm_x = pixels_per_mm_x * 10.0;
-    let pixels_per_10_mm_y = pixels_per_mm_y * 10.0;
-
-    for (index, pixel) in texture_buffer.chunks_exact_mut(4).enumerate() {
-        // Get the x/y position of the pixel.
-        let x = index % texture_width as usize;
-        let y = index / texture_width as usize;
-
-        // Store whether we have written to the pixel so that we know whether to fill with the background colour later.
-        let mut written = false;
-        if let Some(design) = design_texture {
-            // Clamp the width and height of the design so that it is not larger than the available size.
-            let width = design.width().min(texture_width) as usize;
-            let height = design.height().min(texture_height) as usize;
-            let x = (index % texture_width as usize).saturating_sub(offset_mm.x.floor() as usize);
-            let y = (index / texture_width as usize).saturating_sub(offset_mm.y.floor() as usize);
-            if x > 0 && y > 0 && x < width && y < height {
-                // The starting index for this pixel in the design texture.
-                let design_texture_pixel_start = ((y * width) + x) * 4;
-                pixel.copy_from_slice(
-                    &design.data()[design_texture_pixel_start..design_texture_pixel_start + 4],
-                );
-                written = true;
-            }
-        }
+    // Create a pixmap to render to that is the scaled width and height of the design.
+    let Some(mut pixmap) = tiny_skia::Pixmap::new(width.ceil() as u32, height.ceil() as u32) else {
+        log::error!("Could not create pixmap for rendering design preview");
+        return;
+    };
 
-        // For pixels that have not had a design texture written to them, we fill with a background.
-        if !written {
-            // Work out where along the bed we are, in 10mm increments.
-            let bed_width_fraction = (x as f32) / pixels_per_10_mm_x;
-            let bed_height_fraction = (y as f32) / pixels_per_10_mm_y;
-
-            // We want just the fractional component so that...
-            let proportion_x = bed_height_fraction.fract();
-            let proportion_y = bed_width_fraction.fract();
-
-            // Anything that is -0.9 to +0.1 away from the nearest 10mm gets coloured in a different colour, so that the user sees markers for each 10mm increment.
-            if (proportion_x <= 0.1 || proportion_x >= 0.9)
-                && (proportion_y <= 0.1 || proportion_y >= 0.9)
-            {
-                pixel.copy_from_slice(&[100, 100, 100, 255]);
-            } else {
-                pixel.copy_from_slice(&PREVIEW_BACKGROUND_COLOUR);
-            }
-        }
+    // Fill the pixmap with the background colour.
+    pixmap.fill(Color::from_rgba8(
+        PREVIEW_BACKGROUND_COLOUR[0],
+        PREVIEW_BACKGROUND_COLOUR[1],
+        PREVIEW_BACKGROUND_COLOUR[2],
+        PREVIEW_BACKGROUND_COLOUR[3],
+    ));
+    // Render the design at the origin of the pixmap.
+    if show_all_paths {
+        let transform = usvg::Transform::default();
+        resvg::render(tree, transform, &mut pixmap.as_mut());
+    } else {
+        draw_paths_to_be_cut(tree, tool_passes, &mut pixmap);
     }
 
-    let ci = ColorImage::from_rgba_unmultiplied(
-        [texture_width as usize, texture_height as usize],
-        &texture_buffer[0..(texture_width as usize * texture_height as usize * 4)],
+    // Tracing is the slow part, so it's only done when the design changes.
+    let traced = match cache.take() {
+        Some(traced) if traced.hash == *hash => traced,
+        _ => TracedDesign {
+            hash: *hash,
+            paths: get_paths_grouped_by_colour(tree)
+                .map(|paths| trace_all_paths(&paths))
+                .unwrap_or_default(),
+        },
+    };
+    draw_travel_moves(
+        &traced.paths,
+        *width_mm,
+        *height_mm,
+        tool_passes,
+        &mut pixmap,
+    );
+
+    let image = ColorImage::from_rgba_premultiplied(
+        [pixmap.width() as usize, pixmap.height() as usize],
+        pixmap.data(),
     );
-    let _ = callback.send(RenderedImage { image: ci });
+    let _ = callback.send(RenderedImage {
+        design: Some((image, egui::vec2(*width_mm, *height_mm))),
+        paths: traced.paths.clone(),
+    });
+    *cache = Some(traced);
 }
 
-/// Resizes the texture buffer to a new width and height.
-/// Will only allocate new memory if the total memory required is larger that the
-/// current amount of memory that has been allocated.
+/// Draws only the paths that will be cut by an enabled tool pass, so that the
+/// preview matches what will be sent to the machine.
 ///
 /// # Arguments
-/// * `buffer`: The buffer to be resized.
-/// * `width`: The new width, in pixels.
-/// * `height`: The new height, in pixels.
-fn resize_texture_buffer(buffer: &mut Vec<u8>, width: usize, height: usize) {
-    let total_size = width * height * 4; // rgba
-
-    // We only ever want to increase the size of the buffer so that we have the maximum size the user ever uses available to us.
-    if buffer.len() < total_size {
-        buffer.resize(total_size, 0);
+/// * `tree`: The design.
+/// * `tool_passes`: The tool passes that will be run.
+/// * `pixmap`: The pixmap to draw the paths into.
+fn draw_paths_to_be_cut(
+    tree: &usvg::Tree,
+    tool_passes: &[ToolPass],
+    pixmap: &mut tiny_skia::Pixmap,
+) {
+    let Ok(paths) = get_paths_grouped_by_colour(tree) else {
+        return;
+    };
+
+    for (colour, paths) in filter_paths_to_tool_passes(&paths, tool_passes) {
+        let mut paint = tiny_skia::Paint::default();
+        paint.set_color_rgba8(colour.0[0], colour.0[1], colour.0[2], 255);
+        paint.anti_alias = true;
+
+        for path in paths {
+            let Some(stroke) = path.stroke() else {
+                continue;
+            };
+            // Draw the path in the same way that resvg would, so that it lines up with the full preview.
+            let stroke = tiny_skia::Stroke {
+                width: stroke.width().get(),
+                ..Default::default()
+            };
+            pixmap.stroke_path(path.data(), &paint, &stroke, path.abs_transform(), None);
+        }
     }
 }
 
-/// Yeets the cached values for the design preview.
+/// Draws the moves the toolhead makes between paths with the tool off, as faint dashed lines.
 ///
 /// # Arguments
-/// * `design_hash`: The hash of the design.
-/// * `design_texture`: The pixmap used to render SVGs.
-fn invalidate_design_texture(
-    design_hash: &mut Option<u64>,
-    design_texture: &mut Option<resvg::tiny_skia::Pixmap>,
+/// * `paths_in_mm`: Every path in the design, in mm from the top-left of the design.
+/// * `width_mm`: The width of the design, in mm.
+/// * `height_mm`: The height of the design, in mm.
+/// * `tool_passes`: The tool passes that will be run, only enabled passes are included.
+/// * `pixmap`: The pixmap the design has been rendered into.
+fn draw_travel_moves(
+    paths_in_mm: &BTreeMap<PathColour, Vec<PathInMM>>,
+    width_mm: f32,
+    height_mm: f32,
+    tool_passes: &[ToolPass],
+    pixmap: &mut tiny_skia::Pixmap,
 ) {
-    *design_hash = None;
-    *design_texture = None;
+    // Work out how many pixels correspond to 1mm in each dimension.
+    let pixels_per_mm_x = pixmap.width() as f32 / width_mm;
+    let pixels_per_mm_y = pixmap.height() as f32 / height_mm;
+
+    let mut path_builder = tiny_skia::PathBuilder::new();
+    for (start, end) in travel_moves(paths_in_mm, tool_passes) {
+        path_builder.move_to(start.x * pixels_per_mm_x, start.y * pixels_per_mm_y);
+        path_builder.line_to(end.x * pixels_per_mm_x, end.y * pixels_per_mm_y);
+    }
+    let Some(path) = path_builder.finish() else {
+        return;
+    };
+
+    let mut paint = tiny_skia::Paint::default();
+    paint.set_color_rgba8(
+        TRAVEL_LINE_COLOUR[0],
+        TRAVEL_LINE_COLOUR[1],
+        TRAVEL_LINE_COLOUR[2],
+        TRAVEL_LINE_COLOUR[3],
+    );
+    paint.anti_alias = true;
+    let stroke = tiny_skia::Stroke {
+        width: 1.0,
+        dash: tiny_skia::StrokeDash::new(
+            vec![TRAVEL_LINE_DASH_LENGTH, TRAVEL_LINE_DASH_LENGTH],
+            0.0,
+        ),
+        ..Default::default()
+    };
+    pixmap.stroke_path(
+        &path,
+        &paint,
+        &stroke,
+        tiny_skia::Transform::identity(),
+        None,
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use seance::{BedMode, PointInMillimeters, PrintBed};
+
+    use super::{marks_along_axis, DeviceAxis};
+
+    #[test]
+    fn rulers_are_measured_from_the_device_origin() {
+        // The flat bed of the GCC Spirit has its origin at the bottom-left, with its Y axis running up the bed.
+        let mirrored = PrintBed::GCC_SPIRIT;
+        let (x_axis, y_axis) = DeviceAxis::of_bed(&mirrored);
+        assert_eq!(y_axis.mirrored, true, "Flat bed should be mirrored");
+        let height_mm = mirrored.height_mm();
+        let labels: Vec<(f32, f32)> = marks_along_axis(0.0..=height_mm, 100.0, y_axis).collect();
+        assert_eq!(labels.first(), Some(&(height_mm, 0.0)));
+        assert_eq!(labels.last(), Some(&(height_mm - 400.0, 400.0)));
+
+        // A point read off the rulers is where the device puts it.
+        let point = PointInMillimeters { x: 100.0, y: 50.0 };
+        let placed = mirrored
+            .place_point(point)
+            .expect("Point should be on the bed");
+        let read_off = (x_axis.to_device_mm(point.x), y_axis.to_device_mm(point.y));
+        assert_eq!(
+            (
+                (read_off.0 / mirrored.mm_per_plotter_unit()).round() as i32,
+                (read_off.1 / mirrored.mm_per_plotter_unit()).round() as i32
+            ),
+            (placed.x, placed.y)
+        );
+
+        // A rotary attachment turns the work the same way as the preview, from the top.
+        let rotary = PrintBed::GCC_SPIRIT
+            .with_mode(BedMode::Rotary {
+                circumference_mm: 200.0,
+            })
+            .expect("Rotary bed should be valid");
+        let (_, y_axis) = DeviceAxis::of_bed(&rotary);
+        let labels: Vec<(f32, f32)> = marks_along_axis(0.0..=200.0, 100.0, y_axis).collect();
+        assert_eq!(labels, vec![(0.0, 0.0), (100.0, 100.0), (200.0, 200.0)]);
+    }
 }
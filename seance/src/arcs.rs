@@ -0,0 +1,316 @@
+//! `arcs`
+//!
+//! Finds runs of points along a path that lie on a circle, so that they can be
+//! traced as a single arc rather than lots of tiny straight lines.
+
+use std::f32::consts::PI;
+
+use crate::{
+    bed::MAX_HPGL_COORDINATE,
+    paths::{ResolvedPath, ResolvedPoint, ResolvedSegment},
+};
+
+/// How far, in mm, a point may be from a fitted arc and still count as being on it.
+const ARC_TOLERANCE_MM: f32 = 0.05;
+
+/// The fewest points that will be replaced by an arc.
+const MIN_ARC_POINTS: usize = 5;
+
+/// The largest radius, in mm, that will be traced as an arc.
+/// Beyond this the arc is close enough to a straight line that it isn't worth it.
+const MAX_ARC_RADIUS_MM: f32 = 500.0;
+
+/// The largest angle, in radians, between two neighbouring points on an arc.
+const MAX_ARC_STEP: f32 = PI / 4.0;
+
+/// Replaces runs of points that lie on a circle with arcs.
+/// Points that don't lie on a circle are moved to in straight lines.
+///
+/// # Arguments
+/// * `points`: The points along the path, in HPGL/2 units.
+/// * `mm_per_plotter_unit`: The resolution of the bed that the points are on, see [`crate::PrintBed::mm_per_plotter_unit`].
+///
+/// # Returns
+/// The path, where the first segment is the start of the path.
+pub fn fit_arcs(points: &[ResolvedPoint], mm_per_plotter_unit: f32) -> ResolvedPath {
+    let mut path = vec![];
+    let Some(first) = points.first() else {
+        return path;
+    };
+    path.push(ResolvedSegment::Line(*first));
+
+    let mut start = 0;
+    while start + 1 < points.len() {
+        if let Some((end, arc)) = longest_arc(points, start, mm_per_plotter_unit) {
+            path.push(arc);
+            start = end;
+            continue;
+        }
+
+        // There's no arc here, skip over any points that are (nearly) on a straight line
+        // so that the search for the next arc doesn't start in the middle of them.
+        let end = straight_run_end(points, start, mm_per_plotter_unit).max(start + 1);
+        for point in &points[start + 1..=end] {
+            path.push(ResolvedSegment::Line(*point));
+        }
+        start = end;
+    }
+
+    path
+}
+
+/// Finds the longest arc starting at a point.
+///
+/// # Arguments
+/// * `points`: The points along the path.
+/// * `start`: The index of the point to start the arc at.
+/// * `mm_per_plotter_unit`: The resolution of the bed that the points are on.
+///
+/// # Returns
+/// The index of the last point on the arc and the arc, if there is one.
+fn longest_arc(
+    points: &[ResolvedPoint],
+    start: usize,
+    mm_per_plotter_unit: f32,
+) -> Option<(usize, ResolvedSegment)> {
+    let mut longest = None;
+    for end in (start + MIN_ARC_POINTS - 1)..points.len() {
+        let run = &points[start..=end];
+        // Too flat to tell whether it's part of a circle yet.
+        if is_straight(run, mm_per_plotter_unit) {
+            continue;
+        }
+
+        match fit_arc(run, mm_per_plotter_unit) {
+            Some(arc) => longest = Some((end, arc)),
+            None => break,
+        }
+    }
+
+    longest
+}
+
+/// Finds the end of a run of points that are all within tolerance of a straight line.
+///
+/// # Arguments
+/// * `points`: The points along the path.
+/// * `start`: The index of the point at the start of the run.
+/// * `mm_per_plotter_unit`: The resolution of the bed that the points are on.
+///
+/// # Returns
+/// The index of the last point in the run.
+fn straight_run_end(points: &[ResolvedPoint], start: usize, mm_per_plotter_unit: f32) -> usize {
+    let mut end = start + 1;
+    while end + 1 < points.len() && is_straight(&points[start..=end + 1], mm_per_plotter_unit) {
+        end += 1;
+    }
+    end
+}
+
+/// Checks whether all of the points in a run are within tolerance of the line
+/// between the first and last points.
+///
+/// # Arguments
+/// * `run`: The points to check.
+/// * `mm_per_plotter_unit`: The resolution of the bed that the points are on.
+///
+/// # Returns
+/// `true` if the points are in a straight line.
+#[allow(clippy::cast_precision_loss)]
+fn is_straight(run: &[ResolvedPoint], mm_per_plotter_unit: f32) -> bool {
+    let tolerance = ARC_TOLERANCE_MM / mm_per_plotter_unit;
+    let (Some(first), Some(last)) = (run.first(), run.last()) else {
+        return true;
+    };
+    // An `f32` holds every position up to 2^24 plotter units exactly, hundreds of metres at the usual resolution.
+    let (x1, y1) = (first.x as f32, first.y as f32);
+    let (dx, dy) = (last.x as f32 - x1, last.y as f32 - y1);
+    let length = dx.hypot(dy);
+
+    run.iter().all(|point| {
+        let (px, py) = (point.x as f32 - x1, point.y as f32 - y1);
+        let distance = if length == 0.0 {
+            px.hypot(py)
+        } else {
+            ((px * dy) - (py * dx)).abs() / length
+        };
+        distance <= tolerance
+    })
+}
+
+/// Tries to fit an arc through a run of points.
+///
+/// # Arguments
+/// * `run`: The points the arc must pass through.
+/// * `mm_per_plotter_unit`: The resolution of the bed that the points are on.
+///
+/// # Returns
+/// The arc, if all of the points are within tolerance of it.
+#[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+fn fit_arc(run: &[ResolvedPoint], mm_per_plotter_unit: f32) -> Option<ResolvedSegment> {
+    let tolerance = ARC_TOLERANCE_MM / mm_per_plotter_unit;
+    let last = run.last()?;
+
+    let (centre_x, centre_y, radius) = fit_circle(run)?;
+    if radius > MAX_ARC_RADIUS_MM / mm_per_plotter_unit
+        || centre_x.abs() > MAX_HPGL_COORDINATE
+        || centre_y.abs() > MAX_HPGL_COORDINATE
+    {
+        return None;
+    }
+
+    let mut sweep: f32 = 0.0;
+    let mut previous_angle: Option<f32> = None;
+    for point in run {
+        let (dx, dy) = (point.x as f32 - centre_x, point.y as f32 - centre_y);
+        if (dx.hypot(dy) - radius).abs() > tolerance {
+            return None;
+        }
+
+        let angle = dy.atan2(dx);
+        if let Some(previous_angle) = previous_angle {
+            let mut step = angle - previous_angle;
+            if step > PI {
+                step -= 2.0 * PI;
+            } else if step <= -PI {
+                step += 2.0 * PI;
+            }
+
+            // The arc must keep going the same way, in reasonably small steps.
+            if step == 0.0
+                || step.abs() > MAX_ARC_STEP
+                || (sweep != 0.0 && step.signum() != sweep.signum())
+            {
+                return None;
+            }
+            sweep += step;
+        }
+        previous_angle = Some(angle);
+    }
+
+    if sweep.abs() > 2.0 * PI + (tolerance / radius) {
+        return None;
+    }
+
+    // The centre was checked against `MAX_HPGL_COORDINATE` above, so it fits in an `i32`.
+    Some(ResolvedSegment::Arc {
+        centre: ResolvedPoint {
+            x: centre_x.round() as i32,
+            y: centre_y.round() as i32,
+        },
+        sweep_degrees: sweep.to_degrees(),
+        end: *last,
+    })
+}
+
+/// Finds the circle that best fits a set of points, using a least squares fit.
+///
+/// # Arguments
+/// * `points`: The points to fit the circle to.
+///
+/// # Returns
+/// The centre and radius of the circle, or `None` if the points are in a straight line.
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::similar_names
+)]
+fn fit_circle(points: &[ResolvedPoint]) -> Option<(f32, f32, f32)> {
+    // A run of points is far shorter than 2^52, so its length is exact as an `f64`.
+    let count = points.len() as f64;
+    let mean_x = points.iter().map(|point| f64::from(point.x)).sum::<f64>() / count;
+    let mean_y = points.iter().map(|point| f64::from(point.y)).sum::<f64>() / count;
+
+    // The sums of the products of the points' offsets from their mean, named for the offsets they multiply.
+    let (mut suu, mut svv, mut suv, mut suuu, mut svvv, mut suvv, mut svuu) =
+        (0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+    for point in points {
+        let u = f64::from(point.x) - mean_x;
+        let v = f64::from(point.y) - mean_y;
+        suu += u * u;
+        svv += v * v;
+        suv += u * v;
+        suuu += u * u * u;
+        svvv += v * v * v;
+        suvv += u * v * v;
+        svuu += v * u * u;
+    }
+
+    let determinant = (suu * svv) - (suv * suv);
+    if determinant.abs() < f64::EPSILON {
+        return None;
+    }
+
+    let rhs_u = (suuu + suvv) / 2.0;
+    let rhs_v = (svvv + svuu) / 2.0;
+    let centre_u = ((rhs_u * svv) - (rhs_v * suv)) / determinant;
+    let centre_v = ((rhs_v * suu) - (rhs_u * suv)) / determinant;
+    let radius = ((centre_u * centre_u) + (centre_v * centre_v) + ((suu + svv) / count)).sqrt();
+
+    // The circle is worked out in `f64` so that the sums don't lose precision, narrowing it back only
+    // loses precision far finer than a plotter unit, and circles too large for an `f32` are too large to cut anyway.
+    Some((
+        (centre_u + mean_x) as f32,
+        (centre_v + mean_y) as f32,
+        radius as f32,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::fit_arcs;
+    use crate::paths::{ResolvedPoint, ResolvedSegment, MM_PER_PLOTTER_UNIT};
+
+    #[test]
+    fn straight_lines_are_not_arcs() {
+        let points: Vec<ResolvedPoint> = (0..20)
+            .map(|index| ResolvedPoint {
+                x: index * 40,
+                y: index * 13,
+            })
+            .collect();
+        let path = fit_arcs(&points, MM_PER_PLOTTER_UNIT);
+        assert_eq!(path.len(), points.len());
+        assert_eq!(
+            path.iter()
+                .filter(|segment| matches!(segment, ResolvedSegment::Arc { .. }))
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn circle_is_one_arc() {
+        let points: Vec<ResolvedPoint> = (0..=64)
+            .map(|index| {
+                let angle = (index as f32) * std::f32::consts::TAU / 64.0;
+                ResolvedPoint {
+                    x: (1000.0 + (400.0 * angle.cos())).round() as i32,
+                    y: (1000.0 + (400.0 * angle.sin())).round() as i32,
+                }
+            })
+            .collect();
+        let path = fit_arcs(&points, MM_PER_PLOTTER_UNIT);
+        assert_eq!(path.len(), 2);
+        let ResolvedSegment::Arc {
+            centre,
+            sweep_degrees,
+            end,
+        } = path[1]
+        else {
+            panic!("Circle should be traced as an arc");
+        };
+        assert_eq!(centre, ResolvedPoint { x: 1000, y: 1000 });
+        assert_eq!(sweep_degrees.round(), 360.0);
+        assert_eq!(end, points[0]);
+
+        // At 2mm per unit the same circle has a radius of 800mm, too large to be worth tracing as an arc.
+        let path = fit_arcs(&points, 2.0);
+        assert_eq!(
+            path.iter()
+                .filter(|segment| matches!(segment, ResolvedSegment::Arc { .. }))
+                .count(),
+            0
+        );
+    }
+}
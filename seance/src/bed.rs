@@ -0,0 +1,1015 @@
+//! `bed`
+//!
+//! Describes the bed of a device, the area that the toolhead can move over.
+
+use std::{fmt, ops::RangeInclusive};
+
+use crate::{
+    paths::{ResolvedPoint, MM_PER_PLOTTER_UNIT},
+    PointInMillimeters, BED_X_AXIS_MAXIMUM_MM, BED_X_AXIS_MINIMUM_MM, BED_Y_AXIS_MAXIMUM_MM,
+    BED_Y_AXIS_MINIMUM_MM,
+};
+
+/// The largest coordinate that can be sent in HPGL/2, in plotter units.
+pub(crate) const MAX_HPGL_COORDINATE: f32 = 1_073_741_823.0;
+
+/// Reasons that a bed can't be used.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrintBedError {
+    /// One of the ends of an axis is infinite or not a number.
+    NotFinite,
+    /// An axis starts after it ends.
+    Reversed,
+    /// An axis goes further than can be sent to the machine in HPGL.
+    TooLarge,
+    /// The distance moved per plotter unit isn't a positive, finite number.
+    InvalidResolution,
+    /// The margins aren't finite, are negative, or don't leave any of the bed to cut on.
+    InvalidMargins,
+    /// A keep-out zone isn't finite, or one of its sides starts after it ends.
+    InvalidKeepOutZone,
+    /// The circumference of a rotary attachment isn't a positive, finite number.
+    InvalidCircumference,
+}
+
+impl fmt::Display for PrintBedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrintBedError::NotFinite => write!(f, "bed axes must be finite numbers"),
+            PrintBedError::Reversed => write!(f, "bed axes must start before they end"),
+            PrintBedError::TooLarge => write!(f, "bed axes are too large to be sent in HPGL"),
+            PrintBedError::InvalidResolution => {
+                write!(f, "the distance moved per plotter unit must be more than 0")
+            }
+            PrintBedError::InvalidMargins => {
+                write!(f, "bed margins must be positive and leave space to cut")
+            }
+            PrintBedError::InvalidKeepOutZone => {
+                write!(f, "keep-out zones must be finite and start before they end")
+            }
+            PrintBedError::InvalidCircumference => {
+                write!(
+                    f,
+                    "the circumference of a rotary attachment must be more than 0"
+                )
+            }
+        }
+    }
+}
+
+/// An axis of the bed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BedAxis {
+    /// The horizontal axis.
+    X,
+    /// The vertical axis.
+    Y,
+}
+
+impl fmt::Display for BedAxis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BedAxis::X => write!(f, "X"),
+            BedAxis::Y => write!(f, "Y"),
+        }
+    }
+}
+
+/// Reasons that a point can't be placed on the device.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlacePointError {
+    /// The point is off the side of the bed.
+    OutOfBedX {
+        /// The position of the point along the X axis, in mm.
+        value: f32,
+        /// The positions the toolhead can move to along the X axis, in mm.
+        range: RangeInclusive<f32>,
+    },
+    /// The point is off the top or bottom of the bed.
+    OutOfBedY {
+        /// The position of the point along the Y axis, in mm.
+        value: f32,
+        /// The positions the toolhead can move to along the Y axis, in mm.
+        range: RangeInclusive<f32>,
+    },
+    /// The point is in an area of the bed that mustn't be cut, e.g. where there is a clamp.
+    InKeepOutZone {
+        /// The index of the keep-out zone in [`PrintBed::keep_out_zones`].
+        index: usize,
+        /// The name of the keep-out zone.
+        name: String,
+    },
+    /// The point is on the bed, but is too far from the device's origin to be sent in HPGL.
+    HpglOverflow {
+        /// The axis that the point is too far along.
+        axis: BedAxis,
+        /// The position of the point along the axis, in plotter units.
+        value: f32,
+    },
+}
+
+impl fmt::Display for PlacePointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlacePointError::OutOfBedX { value, range } => {
+                write_off_bed(f, *value, range, BedAxis::X)
+            }
+            PlacePointError::OutOfBedY { value, range } => {
+                write_off_bed(f, *value, range, BedAxis::Y)
+            }
+            PlacePointError::InKeepOutZone { name, .. } => {
+                write!(f, "the point is in the keep-out zone '{name}'")
+            }
+            PlacePointError::HpglOverflow { axis, value } => write!(
+                f,
+                "{value} plotter units along the {axis} axis is too far to be sent in HPGL"
+            ),
+        }
+    }
+}
+
+/// How the Y axis of the device moves the work.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum BedMode {
+    /// The work lies flat on the bed.
+    #[default]
+    Flat,
+    /// The Y axis turns a rotary attachment, which the work is wrapped around.
+    Rotary {
+        /// The distance around the work, in mm.
+        circumference_mm: f32,
+    },
+}
+
+/// How much of each edge of the bed can't be cut on, e.g. because the frame is in the way.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BedMargins {
+    /// The margin along the top of the bed, in mm.
+    pub top: f32,
+    /// The margin along the right of the bed, in mm.
+    pub right: f32,
+    /// The margin along the bottom of the bed, in mm.
+    pub bottom: f32,
+    /// The margin along the left of the bed, in mm.
+    pub left: f32,
+}
+
+impl BedMargins {
+    /// No margins, the whole bed can be cut on.
+    pub const NONE: BedMargins = BedMargins {
+        top: 0.0,
+        right: 0.0,
+        bottom: 0.0,
+        left: 0.0,
+    };
+}
+
+impl Default for BedMargins {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+/// A rectangle on the bed that mustn't be cut, e.g. because there is a clamp there.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct KeepOutZone {
+    /// The name of the zone, shown to the user.
+    pub name: String,
+    /// The positions covered by the zone along the X axis, in mm.
+    pub x_axis: RangeInclusive<f32>,
+    /// The positions covered by the zone along the Y axis, in mm.
+    pub y_axis: RangeInclusive<f32>,
+}
+
+impl KeepOutZone {
+    /// Checks whether a point is in the zone.
+    ///
+    /// # Arguments
+    /// * `point`: The point on the bed, in mm.
+    ///
+    /// # Returns
+    /// `true` if the point is in the zone, including on its edges.
+    pub fn contains(&self, point: PointInMillimeters) -> bool {
+        self.x_axis.contains(&point.x) && self.y_axis.contains(&point.y)
+    }
+}
+
+/// Writes out that a point is off the bed along an axis.
+///
+/// # Arguments
+/// * `f`: Where to write the message.
+/// * `value`: The position of the point along the axis, in mm.
+/// * `range`: The positions the toolhead can move to along the axis, in mm.
+/// * `axis`: The axis the point is off the bed along.
+///
+/// # Returns
+/// Whether the message could be written.
+fn write_off_bed(
+    f: &mut fmt::Formatter<'_>,
+    value: f32,
+    range: &RangeInclusive<f32>,
+    axis: BedAxis,
+) -> fmt::Result {
+    write!(
+        f,
+        "{value:.2}mm is off the bed, which goes from {:.2}mm to {:.2}mm along the {axis} axis",
+        range.start(),
+        range.end()
+    )
+}
+
+/// The bed of a device, in mm, where +x is more right and +y is more down.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "UncheckedPrintBed")]
+pub struct PrintBed {
+    /// The range of positions the toolhead can move to along the X axis, in mm.
+    x_axis: RangeInclusive<f32>,
+    /// The range of positions the toolhead can move to along the Y axis, in mm.
+    y_axis: RangeInclusive<f32>,
+    /// How far the toolhead moves for each plotter unit, in mm.
+    mm_per_plotter_unit: f32,
+    /// How much of each edge of the bed can't be cut on.
+    margins: BedMargins,
+    /// Areas of the bed that can't be cut on.
+    keep_out_zones: Vec<KeepOutZone>,
+    /// How the Y axis of the device moves the work.
+    mode: BedMode,
+}
+
+/// The beds that we know about, by name.
+static PRESETS: [(&str, PrintBed); 3] = [
+    ("GCC Spirit", PrintBed::GCC_SPIRIT),
+    ("Epilog Zing 16", PrintBed::EPILOG_ZING_16),
+    ("Trotec Speedy 100", PrintBed::TROTEC_SPEEDY_100),
+];
+
+impl PrintBed {
+    /// The bed of the GCC Spirit.
+    pub const GCC_SPIRIT: PrintBed = PrintBed::new(
+        BED_X_AXIS_MINIMUM_MM..=BED_X_AXIS_MAXIMUM_MM,
+        BED_Y_AXIS_MINIMUM_MM..=BED_Y_AXIS_MAXIMUM_MM,
+        MM_PER_PLOTTER_UNIT,
+    );
+
+    /// The bed of the Epilog Zing 16, 16" x 12".
+    pub const EPILOG_ZING_16: PrintBed =
+        PrintBed::new(0.0..=406.4, 0.0..=304.8, MM_PER_PLOTTER_UNIT);
+
+    /// The bed of the Trotec Speedy 100.
+    pub const TROTEC_SPEEDY_100: PrintBed =
+        PrintBed::new(0.0..=610.0, 0.0..=305.0, MM_PER_PLOTTER_UNIT);
+
+    /// Creates a new bed, with no margins or keep-out zones.
+    /// The axes aren't checked, use [`PrintBed::try_new`] for beds that aren't known to be good.
+    ///
+    /// # Arguments
+    /// * `x_axis`: The range of positions the toolhead can move to along the X axis, in mm.
+    /// * `y_axis`: The range of positions the toolhead can move to along the Y axis, in mm.
+    /// * `mm_per_plotter_unit`: How far the toolhead moves for each plotter unit, in mm.
+    ///
+    /// # Returns
+    /// The bed.
+    pub const fn new(
+        x_axis: RangeInclusive<f32>,
+        y_axis: RangeInclusive<f32>,
+        mm_per_plotter_unit: f32,
+    ) -> Self {
+        Self {
+            x_axis,
+            y_axis,
+            mm_per_plotter_unit,
+            margins: BedMargins::NONE,
+            keep_out_zones: Vec::new(),
+            mode: BedMode::Flat,
+        }
+    }
+
+    /// Creates a new bed, checking that its axes and resolution can be used.
+    ///
+    /// # Arguments
+    /// * `x_axis`: The range of positions the toolhead can move to along the X axis, in mm.
+    /// * `y_axis`: The range of positions the toolhead can move to along the Y axis, in mm.
+    /// * `mm_per_plotter_unit`: How far the toolhead moves for each plotter unit, in mm.
+    ///
+    /// # Returns
+    /// The bed, or a [`PrintBedError`] if either axis isn't finite, is reversed, or can't be sent in HPGL,
+    /// or the resolution isn't a positive number.
+    pub fn try_new(
+        x_axis: RangeInclusive<f32>,
+        y_axis: RangeInclusive<f32>,
+        mm_per_plotter_unit: f32,
+    ) -> Result<Self, PrintBedError> {
+        if !mm_per_plotter_unit.is_finite() || mm_per_plotter_unit <= 0.0 {
+            return Err(PrintBedError::InvalidResolution);
+        }
+        check_axis(&x_axis, mm_per_plotter_unit)?;
+        check_axis(&y_axis, mm_per_plotter_unit)?;
+        Ok(Self::new(x_axis, y_axis, mm_per_plotter_unit))
+    }
+
+    /// Gets the beds that we know about, by name.
+    ///
+    /// # Returns
+    /// The names and beds.
+    pub fn presets() -> &'static [(&'static str, PrintBed)] {
+        &PRESETS
+    }
+
+    /// Sets the margins around the edges of the bed that can't be cut on.
+    ///
+    /// # Arguments
+    /// * `margins`: The margins, in mm.
+    ///
+    /// # Returns
+    /// The bed with the margins, or [`PrintBedError::InvalidMargins`] if they aren't positive or don't leave space to cut.
+    pub fn with_margins(mut self, margins: BedMargins) -> Result<Self, PrintBedError> {
+        let BedMargins {
+            top,
+            right,
+            bottom,
+            left,
+        } = margins;
+        let is_valid = [top, right, bottom, left]
+            .iter()
+            .all(|margin| margin.is_finite() && *margin >= 0.0)
+            && left + right < self.width_mm()
+            && top + bottom < self.height_mm();
+        if !is_valid {
+            return Err(PrintBedError::InvalidMargins);
+        }
+
+        self.margins = margins;
+        Ok(self)
+    }
+
+    /// Sets how the Y axis of the device moves the work, e.g. to use a rotary attachment for a job.
+    ///
+    /// # Arguments
+    /// * `mode`: How the Y axis moves the work.
+    ///
+    /// # Returns
+    /// The bed in that mode, or [`PrintBedError::InvalidCircumference`] if a rotary attachment's circumference
+    /// isn't a positive number, or [`PrintBedError::TooLarge`] if it can't be sent in HPGL.
+    pub fn with_mode(mut self, mode: BedMode) -> Result<Self, PrintBedError> {
+        if let BedMode::Rotary { circumference_mm } = mode {
+            if !circumference_mm.is_finite() || circumference_mm <= 0.0 {
+                return Err(PrintBedError::InvalidCircumference);
+            }
+            check_axis(&(0.0..=circumference_mm), self.mm_per_plotter_unit)?;
+        }
+
+        self.mode = mode;
+        Ok(self)
+    }
+
+    /// Adds an area of the bed that can't be cut on.
+    ///
+    /// # Arguments
+    /// * `zone`: The area that can't be cut on.
+    ///
+    /// # Returns
+    /// The bed with the zone, or [`PrintBedError::InvalidKeepOutZone`] if the zone isn't finite or is reversed.
+    pub fn with_keep_out_zone(mut self, zone: KeepOutZone) -> Result<Self, PrintBedError> {
+        let is_valid = [&zone.x_axis, &zone.y_axis].iter().all(|axis| {
+            axis.start().is_finite() && axis.end().is_finite() && axis.start() <= axis.end()
+        });
+        if !is_valid {
+            return Err(PrintBedError::InvalidKeepOutZone);
+        }
+
+        self.keep_out_zones.push(zone);
+        Ok(self)
+    }
+
+    /// Looks up one of the [`PrintBed::presets`] by name, ignoring case.
+    ///
+    /// # Arguments
+    /// * `name`: The name of the bed.
+    ///
+    /// # Returns
+    /// The bed, if there is one with that name.
+    pub fn by_name(name: &str) -> Option<&'static PrintBed> {
+        Self::presets()
+            .iter()
+            .find(|(preset_name, _)| preset_name.eq_ignore_ascii_case(name))
+            .map(|(_, bed)| bed)
+    }
+
+    /// Gets the range of positions along the X axis.
+    ///
+    /// # Returns
+    /// The X axis range, in mm.
+    pub fn x_axis(&self) -> &RangeInclusive<f32> {
+        &self.x_axis
+    }
+
+    /// Gets the range of positions along the Y axis when the work lies flat on the bed.
+    /// Use [`PrintBed::usable_y_axis`] for the positions that can be cut on in the bed's mode.
+    ///
+    /// # Returns
+    /// The Y axis range, in mm.
+    pub fn y_axis(&self) -> &RangeInclusive<f32> {
+        &self.y_axis
+    }
+
+    /// Gets how the Y axis of the device moves the work.
+    ///
+    /// # Returns
+    /// The bed's mode.
+    pub fn mode(&self) -> &BedMode {
+        &self.mode
+    }
+
+    /// Gets the margins around the edges of the bed that can't be cut on.
+    ///
+    /// # Returns
+    /// The margins, in mm.
+    pub fn margins(&self) -> &BedMargins {
+        &self.margins
+    }
+
+    /// Gets the areas of the bed that can't be cut on.
+    ///
+    /// # Returns
+    /// The keep-out zones.
+    pub fn keep_out_zones(&self) -> &[KeepOutZone] {
+        &self.keep_out_zones
+    }
+
+    /// Gets the range of positions along the X axis that can be cut on, inside the margins.
+    ///
+    /// # Returns
+    /// The usable X axis range, in mm.
+    pub fn usable_x_axis(&self) -> RangeInclusive<f32> {
+        self.x_axis.start() + self.margins.left..=self.x_axis.end() - self.margins.right
+    }
+
+    /// Gets the range of positions along the Y axis that can be cut on, inside the margins.
+    /// With a rotary attachment this is once around the work, and the margins don't apply.
+    ///
+    /// # Returns
+    /// The usable Y axis range, in mm.
+    pub fn usable_y_axis(&self) -> RangeInclusive<f32> {
+        match self.mode {
+            BedMode::Flat => {
+                self.y_axis.start() + self.margins.top..=self.y_axis.end() - self.margins.bottom
+            }
+            BedMode::Rotary { circumference_mm } => 0.0..=circumference_mm,
+        }
+    }
+
+    /// Works out where a design can be put so that all of it is inside the margins of the bed.
+    /// Keep-out zones aren't taken into account, as a design can still fit around them.
+    ///
+    /// # Arguments
+    /// * `design_width_mm`: The width of the design, in mm.
+    /// * `design_height_mm`: The height of the design, in mm.
+    ///
+    /// # Returns
+    /// The ranges of offsets of the top-left of the design along the X and Y axes, in mm,
+    /// or `None` if the design is larger than the bed.
+    pub fn fit_offset_range(
+        &self,
+        design_width_mm: f32,
+        design_height_mm: f32,
+    ) -> Option<(RangeInclusive<f32>, RangeInclusive<f32>)> {
+        let (x_axis, y_axis) = (self.usable_x_axis(), self.usable_y_axis());
+        let x_end = x_axis.end() - design_width_mm;
+        let y_end = y_axis.end() - design_height_mm;
+        if x_end < *x_axis.start() || y_end < *y_axis.start() {
+            return None;
+        }
+
+        Some((*x_axis.start()..=x_end, *y_axis.start()..=y_end))
+    }
+
+    /// Gets how far the toolhead moves for each plotter unit.
+    ///
+    /// # Returns
+    /// The resolution, in mm per plotter unit.
+    pub fn mm_per_plotter_unit(&self) -> f32 {
+        self.mm_per_plotter_unit
+    }
+
+    /// Works out where a point on the bed is on the device, relative to the device's origin.
+    /// On a flat bed the device's Y axis runs the opposite way to ours, see [`PrintBed::to_device_mm`].
+    ///
+    /// # Arguments
+    /// * `point`: The point on the bed, in mm, where +x is more right and +y is more down.
+    ///
+    /// # Returns
+    /// The point on the device, in plotter units, or a [`PlacePointError`] if the point is off the bed, in its margins,
+    /// in a keep-out zone, or too far from the device's origin to be sent in HPGL.
+    pub fn place_point(&self, point: PointInMillimeters) -> Result<ResolvedPoint, PlacePointError> {
+        // Points that round onto the edge of the bed aren't lost to float error.
+        let tolerance_mm = self.mm_per_plotter_unit / 2.0;
+        let x_axis = self.usable_x_axis();
+        if !is_on_axis(point.x, &x_axis, tolerance_mm) {
+            return Err(PlacePointError::OutOfBedX {
+                value: point.x,
+                range: x_axis,
+            });
+        }
+        let point = match self.mode {
+            BedMode::Flat => {
+                let y_axis = self.usable_y_axis();
+                if !is_on_axis(point.y, &y_axis, tolerance_mm) {
+                    return Err(PlacePointError::OutOfBedY {
+                        value: point.y,
+                        range: y_axis,
+                    });
+                }
+                point
+            }
+            // Going further along Y goes round the work again, so any position is on the work.
+            BedMode::Rotary { circumference_mm } => PointInMillimeters {
+                y: point.y.rem_euclid(circumference_mm),
+                ..point
+            },
+        };
+        if let Some((index, zone)) = self
+            .keep_out_zones
+            .iter()
+            .enumerate()
+            .find(|(_, zone)| zone.contains(point))
+        {
+            return Err(PlacePointError::InKeepOutZone {
+                index,
+                name: zone.name.clone(),
+            });
+        }
+
+        self.to_plotter_units(point)
+    }
+
+    /// Converts a point on the bed into plotter units, relative to the device's origin, without checking
+    /// that it can be cut on, e.g. for where the toolhead parks, which may be in the margins.
+    ///
+    /// # Arguments
+    /// * `point`: The point on the bed, in mm, where +x is more right and +y is more down.
+    ///
+    /// # Returns
+    /// The point on the device, in plotter units, or [`PlacePointError::HpglOverflow`] if it is too far from
+    /// the device's origin to be sent in HPGL.
+    pub(crate) fn to_plotter_units(
+        &self,
+        point: PointInMillimeters,
+    ) -> Result<ResolvedPoint, PlacePointError> {
+        let point = self.to_device_mm(point);
+        Ok(ResolvedPoint {
+            x: self.mm_to_plotter_units(point.x, BedAxis::X)?,
+            y: self.mm_to_plotter_units(point.y, BedAxis::Y)?,
+        })
+    }
+
+    /// Gets where the device's origin is on the bed.
+    /// On a flat bed this is the bottom-left corner, as the device's Y axis runs up the bed.
+    ///
+    /// # Returns
+    /// The device's origin, in mm, where +x is more right and +y is more down.
+    pub fn device_origin(&self) -> PointInMillimeters {
+        PointInMillimeters {
+            x: *self.x_axis.start(),
+            y: match self.mode {
+                BedMode::Flat => *self.y_axis.end(),
+                BedMode::Rotary { .. } => 0.0,
+            },
+        }
+    }
+
+    /// Checks whether the device's Y axis runs the opposite way to ours.
+    /// A rotary attachment turns the work the same way as our Y axis, so it isn't mirrored.
+    ///
+    /// # Returns
+    /// `true` if moving down the bed moves the device towards its origin.
+    pub fn is_y_mirrored(&self) -> bool {
+        matches!(self.mode, BedMode::Flat)
+    }
+
+    /// Works out how far a point on the bed is from the device's origin, along the device's axes.
+    /// The point isn't checked, use [`PrintBed::place_point`] for points that are going to be sent to the device.
+    ///
+    /// # Arguments
+    /// * `point`: The point on the bed, in mm, where +x is more right and +y is more down.
+    ///
+    /// # Returns
+    /// The point on the device, in mm.
+    pub fn to_device_mm(&self, point: PointInMillimeters) -> PointInMillimeters {
+        let origin = self.device_origin();
+        PointInMillimeters {
+            x: point.x - origin.x,
+            y: if self.is_y_mirrored() {
+                origin.y - point.y
+            } else {
+                point.y - origin.y
+            },
+        }
+    }
+
+    /// Converts a distance from the device's origin into plotter units.
+    ///
+    /// # Arguments
+    /// * `mm`: The distance from the device's origin, in mm.
+    /// * `axis`: The axis the distance is along.
+    ///
+    /// # Returns
+    /// The distance in plotter units, or [`PlacePointError::HpglOverflow`] if it is too large to be sent.
+    #[allow(clippy::cast_possible_truncation)]
+    fn mm_to_plotter_units(&self, mm: f32, axis: BedAxis) -> Result<i32, PlacePointError> {
+        let value = (mm / self.mm_per_plotter_unit).round();
+        if value.abs() > MAX_HPGL_COORDINATE {
+            return Err(PlacePointError::HpglOverflow { axis, value });
+        }
+
+        Ok(value as i32)
+    }
+
+    /// Gets the width of the bed.
+    ///
+    /// # Returns
+    /// The width of the bed, in mm.
+    pub fn width_mm(&self) -> f32 {
+        self.x_axis.end() - self.x_axis.start()
+    }
+
+    /// Gets the height of the bed, or the circumference of the work on a rotary attachment.
+    ///
+    /// # Returns
+    /// The height of the bed, in mm.
+    pub fn height_mm(&self) -> f32 {
+        match self.mode {
+            BedMode::Flat => self.y_axis.end() - self.y_axis.start(),
+            BedMode::Rotary { circumference_mm } => circumference_mm,
+        }
+    }
+}
+
+impl Default for PrintBed {
+    fn default() -> Self {
+        Self::GCC_SPIRIT
+    }
+}
+
+/// A [`PrintBed`] as it has been read in, before its axes have been checked.
+#[derive(serde::Deserialize)]
+struct UncheckedPrintBed {
+    /// The range of positions the toolhead can move to along the X axis, in mm.
+    x_axis: RangeInclusive<f32>,
+    /// The range of positions the toolhead can move to along the Y axis, in mm.
+    y_axis: RangeInclusive<f32>,
+    /// How far the toolhead moves for each plotter unit, in mm. Beds saved before this could be set use the HPGL/2 default.
+    #[serde(default = "default_mm_per_plotter_unit")]
+    mm_per_plotter_unit: f32,
+    /// How much of each edge of the bed can't be cut on.
+    #[serde(default)]
+    margins: BedMargins,
+    /// Areas of the bed that can't be cut on.
+    #[serde(default)]
+    keep_out_zones: Vec<KeepOutZone>,
+    /// How the Y axis of the device moves the work.
+    #[serde(default)]
+    mode: BedMode,
+}
+
+impl TryFrom<UncheckedPrintBed> for PrintBed {
+    type Error = PrintBedError;
+
+    fn try_from(bed: UncheckedPrintBed) -> Result<Self, Self::Error> {
+        bed.keep_out_zones.into_iter().try_fold(
+            PrintBed::try_new(bed.x_axis, bed.y_axis, bed.mm_per_plotter_unit)?
+                .with_margins(bed.margins)?
+                .with_mode(bed.mode)?,
+            PrintBed::with_keep_out_zone,
+        )
+    }
+}
+
+/// The resolution to use for beds that don't have one saved.
+///
+/// # Returns
+/// The HPGL/2 default resolution, in mm per plotter unit.
+fn default_mm_per_plotter_unit() -> f32 {
+    MM_PER_PLOTTER_UNIT
+}
+
+/// Checks whether a position is on an axis of the bed.
+///
+/// # Arguments
+/// * `value`: The position along the axis, in mm.
+/// * `axis`: The range of positions along the axis, in mm.
+/// * `tolerance_mm`: How far off either end of the axis the position can be, in mm.
+///
+/// # Returns
+/// `true` if the position is on the axis.
+fn is_on_axis(value: f32, axis: &RangeInclusive<f32>, tolerance_mm: f32) -> bool {
+    (axis.start() - tolerance_mm..=axis.end() + tolerance_mm).contains(&value)
+}
+
+/// Checks that an axis of a bed can be used.
+///
+/// # Arguments
+/// * `axis`: The range of positions along the axis, in mm.
+/// * `mm_per_plotter_unit`: How far the toolhead moves for each plotter unit, in mm.
+///
+/// # Returns
+/// `Ok(())` if the axis can be used, otherwise why it can't.
+fn check_axis(axis: &RangeInclusive<f32>, mm_per_plotter_unit: f32) -> Result<(), PrintBedError> {
+    if !axis.start().is_finite() || !axis.end().is_finite() {
+        return Err(PrintBedError::NotFinite);
+    }
+
+    if axis.start() > axis.end() {
+        return Err(PrintBedError::Reversed);
+    }
+
+    let max_mm = MAX_HPGL_COORDINATE * mm_per_plotter_unit;
+    if axis.start().abs() > max_mm || axis.end().abs() > max_mm {
+        return Err(PrintBedError::TooLarge);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        BedAxis, BedMargins, BedMode, KeepOutZone, PlacePointError, PrintBed, PrintBedError,
+    };
+    use crate::{
+        paths::{ResolvedPoint, MM_PER_PLOTTER_UNIT},
+        PointInMillimeters,
+    };
+
+    /// The real limits of the GCC Spirit's axes, which the cutter won't actually move to.
+    const REAL_SPIRIT_BED: PrintBed =
+        PrintBed::new(-50.72..=901.52, -4.80..=463.20, MM_PER_PLOTTER_UNIT);
+
+    #[test]
+    fn corners_are_placed_on_the_device() {
+        let point = |x, y| PointInMillimeters { x, y };
+        let placed = |x, y| Ok(ResolvedPoint { x, y });
+
+        // The top of the bed is the far end of the device's Y axis.
+        let bed = PrintBed::new(-50.72..=500.0, -4.80..=463.20, MM_PER_PLOTTER_UNIT);
+        assert_eq!(bed.place_point(point(-50.72, -4.80)), placed(0, 18720));
+        assert_eq!(bed.place_point(point(500.0, -4.80)), placed(22029, 18720));
+        assert_eq!(bed.place_point(point(-50.72, 463.20)), placed(0, 0));
+        assert_eq!(bed.place_point(point(500.0, 463.20)), placed(22029, 0));
+
+        // The middle of a bed with a negative minimum stays in the middle.
+        assert_eq!(
+            REAL_SPIRIT_BED.place_point(point(425.4, 229.2)),
+            placed(19045, 9360)
+        );
+
+        // The far corner of the whole bed is further than a 16 bit coordinate can reach.
+        assert_eq!(
+            PrintBed::GCC_SPIRIT.place_point(point(901.52, 0.0)),
+            placed(36061, 18528)
+        );
+        assert_eq!(
+            REAL_SPIRIT_BED.place_point(point(901.52, 463.20)),
+            placed(38090, 0)
+        );
+    }
+
+    #[test]
+    fn points_that_cant_be_placed_are_errors() {
+        let point = |x, y| PointInMillimeters { x, y };
+        assert_eq!(
+            REAL_SPIRIT_BED.place_point(point(-60.0, 0.0)),
+            Err(PlacePointError::OutOfBedX {
+                value: -60.0,
+                range: -50.72..=901.52
+            })
+        );
+        assert_eq!(
+            REAL_SPIRIT_BED.place_point(point(0.0, 470.0)),
+            Err(PlacePointError::OutOfBedY {
+                value: 470.0,
+                range: -4.80..=463.20
+            })
+        );
+
+        // Each end of a huge bed can be reached, but not one from the other.
+        let huge_bed = PrintBed::try_new(-2.0e7..=2.0e7, 0.0..=50.0, MM_PER_PLOTTER_UNIT)
+            .expect("Bed should be usable");
+        assert_eq!(
+            huge_bed.place_point(point(2.0e7, 0.0)),
+            Err(PlacePointError::HpglOverflow {
+                axis: BedAxis::X,
+                value: 1.6e9
+            })
+        );
+    }
+
+    #[test]
+    fn resolution_changes_placement() {
+        let point = PointInMillimeters { x: 10.0, y: 50.0 };
+        let coarse = PrintBed::new(0.0..=100.0, 0.0..=50.0, 0.025);
+        let fine = PrintBed::new(0.0..=100.0, 0.0..=50.0, 0.0125);
+        assert_eq!(
+            coarse.place_point(point),
+            Ok(ResolvedPoint { x: 400, y: 0 })
+        );
+        assert_eq!(fine.place_point(point), Ok(ResolvedPoint { x: 800, y: 0 }));
+
+        // A finer resolution can't reach as far.
+        assert_eq!(
+            PrintBed::try_new(0.0..=2.0e7, 0.0..=50.0, 0.025).map(|bed| bed.mm_per_plotter_unit()),
+            Ok(0.025)
+        );
+        assert_eq!(
+            PrintBed::try_new(0.0..=2.0e7, 0.0..=50.0, 0.0125),
+            Err(PrintBedError::TooLarge)
+        );
+        assert_eq!(
+            PrintBed::try_new(0.0..=100.0, 0.0..=50.0, 0.0),
+            Err(PrintBedError::InvalidResolution)
+        );
+    }
+
+    #[test]
+    fn margins_and_keep_out_zones_are_off_limits() {
+        let point = |x, y| PointInMillimeters { x, y };
+        let bed = PrintBed::new(0.0..=100.0, 0.0..=50.0, MM_PER_PLOTTER_UNIT)
+            .with_margins(BedMargins {
+                top: 5.0,
+                right: 5.0,
+                bottom: 5.0,
+                left: 20.0,
+            })
+            .and_then(|bed| {
+                bed.with_keep_out_zone(KeepOutZone {
+                    name: "Clamp".to_string(),
+                    x_axis: 40.0..=60.0,
+                    y_axis: 0.0..=10.0,
+                })
+            })
+            .expect("Bed should be valid");
+
+        assert_eq!(
+            bed.place_point(point(50.0, 8.0)),
+            Err(PlacePointError::InKeepOutZone {
+                index: 0,
+                name: "Clamp".to_string()
+            })
+        );
+        assert_eq!(
+            bed.place_point(point(10.0, 25.0)),
+            Err(PlacePointError::OutOfBedX {
+                value: 10.0,
+                range: 20.0..=95.0
+            })
+        );
+        // Points are still placed relative to the edges of the bed, not the margins.
+        assert_eq!(
+            bed.place_point(point(50.0, 25.0)),
+            Ok(ResolvedPoint { x: 2000, y: 1000 })
+        );
+
+        assert_eq!(
+            bed.clone().with_margins(BedMargins {
+                left: 60.0,
+                right: 60.0,
+                ..BedMargins::NONE
+            }),
+            Err(PrintBedError::InvalidMargins)
+        );
+        assert_eq!(
+            bed.with_keep_out_zone(KeepOutZone {
+                name: "Backwards".to_string(),
+                x_axis: 60.0..=40.0,
+                y_axis: 0.0..=10.0,
+            }),
+            Err(PrintBedError::InvalidKeepOutZone)
+        );
+    }
+
+    #[test]
+    fn rotary_attachments_wrap_y() {
+        let point = |x, y| PointInMillimeters { x, y };
+        let bed = REAL_SPIRIT_BED
+            .with_mode(BedMode::Rotary {
+                circumference_mm: 200.0,
+            })
+            .expect("Rotary mode should be valid");
+
+        // Once round is 200mm, and the rotary isn't mirrored like the flat bed is.
+        assert_eq!(
+            bed.place_point(point(0.0, 500.0)),
+            Ok(ResolvedPoint { x: 2029, y: 4000 })
+        );
+        assert_eq!(
+            bed.place_point(point(0.0, -50.0)),
+            Ok(ResolvedPoint { x: 2029, y: 6000 })
+        );
+        assert_eq!((bed.usable_y_axis(), bed.height_mm()), (0.0..=200.0, 200.0));
+
+        assert_eq!(
+            REAL_SPIRIT_BED.with_mode(BedMode::Rotary {
+                circumference_mm: 0.0
+            }),
+            Err(PrintBedError::InvalidCircumference)
+        );
+    }
+
+    #[test]
+    fn designs_are_fitted_on_the_bed() {
+        let bed = PrintBed::new(-10.0..=90.0, 0.0..=50.0, MM_PER_PLOTTER_UNIT);
+        assert_eq!(
+            bed.fit_offset_range(30.0, 20.0),
+            Some((-10.0..=60.0, 0.0..=30.0))
+        );
+
+        // A design the size of the bed can only go in one place.
+        assert_eq!(
+            bed.fit_offset_range(100.0, 50.0),
+            Some((-10.0..=-10.0, 0.0..=0.0))
+        );
+        assert_eq!(bed.fit_offset_range(100.5, 50.0), None);
+        assert_eq!(bed.fit_offset_range(30.0, 51.0), None);
+
+        // Margins make less of the bed available.
+        let bed = bed
+            .with_margins(BedMargins {
+                left: 5.0,
+                top: 5.0,
+                ..BedMargins::NONE
+            })
+            .expect("Margins should be valid");
+        assert_eq!(
+            bed.fit_offset_range(30.0, 20.0),
+            Some((-5.0..=60.0, 5.0..=30.0))
+        );
+        assert_eq!(bed.fit_offset_range(100.0, 50.0), None);
+    }
+
+    #[test]
+    fn beds_are_checked() {
+        assert_eq!(
+            PrintBed::try_new(0.0..=100.0, 0.0..=50.0, MM_PER_PLOTTER_UNIT),
+            Ok(PrintBed::new(0.0..=100.0, 0.0..=50.0, MM_PER_PLOTTER_UNIT))
+        );
+        assert_eq!(
+            PrintBed::try_new(0.0..=f32::NAN, 0.0..=50.0, MM_PER_PLOTTER_UNIT),
+            Err(PrintBedError::NotFinite)
+        );
+        assert_eq!(
+            PrintBed::try_new(0.0..=100.0, f32::NEG_INFINITY..=50.0, MM_PER_PLOTTER_UNIT),
+            Err(PrintBedError::NotFinite)
+        );
+        assert_eq!(
+            PrintBed::try_new(100.0..=0.0, 0.0..=50.0, MM_PER_PLOTTER_UNIT),
+            Err(PrintBedError::Reversed)
+        );
+        assert_eq!(
+            PrintBed::try_new(0.0..=100.0, 0.0..=1.0e9, MM_PER_PLOTTER_UNIT),
+            Err(PrintBedError::TooLarge)
+        );
+    }
+
+    #[test]
+    fn saved_beds_check_their_margins_against_the_flat_bed() {
+        // The margins are larger than the rotary attachment, but they only apply to the flat bed.
+        let saved = r#"{
+            "x_axis": {"start": 0.0, "end": 500.0},
+            "y_axis": {"start": 0.0, "end": 300.0},
+            "margins": {"top": 60.0, "right": 0.0, "bottom": 60.0, "left": 0.0},
+            "mode": {"Rotary": {"circumference_mm": 100.0}}
+        }"#;
+        let bed: PrintBed = serde_json::from_str(saved).expect("Saved bed should be valid");
+        assert_eq!(
+            bed,
+            PrintBed::new(0.0..=500.0, 0.0..=300.0, MM_PER_PLOTTER_UNIT)
+                .with_margins(BedMargins {
+                    top: 60.0,
+                    right: 0.0,
+                    bottom: 60.0,
+                    left: 0.0,
+                })
+                .and_then(|bed| bed.with_mode(BedMode::Rotary {
+                    circumference_mm: 100.0,
+                }))
+                .expect("Bed should be valid")
+        );
+    }
+
+    #[test]
+    fn presets_are_valid_and_found_by_name() {
+        for (name, bed) in PrintBed::presets() {
+            assert_eq!(
+                PrintBed::try_new(
+                    bed.x_axis().clone(),
+                    bed.y_axis().clone(),
+                    bed.mm_per_plotter_unit()
+                )
+                .as_ref(),
+                Ok(bed)
+            );
+            assert_eq!(PrintBed::by_name(name), Some(bed));
+        }
+        assert_eq!(PrintBed::by_name("gcc spirit"), Some(&PrintBed::GCC_SPIRIT));
+        assert_eq!(PrintBed::by_name("Not a bed"), None);
+    }
+}
@@ -0,0 +1,109 @@
+//! `colour`
+//!
+//! The colours of paths in a design and of the tool passes that cut them.
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A colour, as its [R, G, B] channel values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Colour(pub [u8; 3]);
+
+impl fmt::Display for Colour {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Colour([r, g, b]) = self;
+        write!(f, "#{r:02X}{g:02X}{b:02X}")
+    }
+}
+
+/// The error when a string can't be parsed as a [`Colour`], holding the string.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseColourError(pub String);
+
+impl fmt::Display for ParseColourError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "'{}' is not a colour, expected a colour like #00FF00",
+            self.0
+        )
+    }
+}
+
+impl FromStr for Colour {
+    type Err = ParseColourError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseColourError(s.to_string());
+        let digits = s
+            .strip_prefix('#')
+            .filter(|digits| digits.len() == 6 && digits.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(invalid)?;
+
+        let channel = |index: usize| {
+            u8::from_str_radix(&digits[index * 2..index * 2 + 2], 16).map_err(|_| invalid())
+        };
+        Ok(Colour([channel(0)?, channel(1)?, channel(2)?]))
+    }
+}
+
+impl Serialize for Colour {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// The ways that a [`Colour`] can be written in settings files.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SerializedColour {
+    /// A hex string, e.g. `"#00FF00"`.
+    Hex(String),
+    /// The channel values, e.g. `[0, 255, 0]`, as older settings files were written.
+    Channels([u8; 3]),
+}
+
+impl<'de> Deserialize<'de> for Colour {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match SerializedColour::deserialize(deserializer)? {
+            SerializedColour::Hex(hex) => hex.parse().map_err(serde::de::Error::custom),
+            SerializedColour::Channels(channels) => Ok(Colour(channels)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Colour, ParseColourError};
+    use crate::ToolPass;
+
+    #[test]
+    fn colours_are_written_as_hex() {
+        let colour = Colour([0, 255, 16]);
+        assert_eq!(colour.to_string(), "#00FF10");
+        assert_eq!("#00ff10".parse(), Ok(colour));
+        for invalid in ["#00FF1", "00FF10", "#00FG10", "#+0FF10"] {
+            assert_eq!(
+                invalid.parse::<Colour>(),
+                Err(ParseColourError(invalid.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn old_settings_still_import() {
+        let old = r#"[{"name":"Cut","colour":[0,255,0],"power":100,"speed":20,"rast":false,"enable":true}]"#;
+        let passes: Vec<ToolPass> = serde_json::from_str(old).expect("Old settings should import");
+        assert_eq!(passes[0].colour(), &Colour([0, 255, 0]));
+
+        let exported = serde_json::to_string(&passes).expect("Settings should export");
+        assert_eq!(
+            exported,
+            r##"[{"name":"Cut","colour":"#00FF00","power":100,"speed":20,"ppi":400,"rast":false,"enable":true}]"##
+        );
+        let reimported: Vec<ToolPass> =
+            serde_json::from_str(&exported).expect("Exported settings should import");
+        assert_eq!(reimported, passes);
+    }
+}
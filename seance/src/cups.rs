@@ -0,0 +1,132 @@
+//! `cups`
+//!
+//! Sends jobs to laser cutters that are set up as printer queues on the system, through CUPS.
+
+use std::{
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+use crate::SendToDeviceError;
+
+/// Submits a job to a printer queue as raw data, so that CUPS passes the PCL to the device untouched.
+/// The job is handed to `lp`, which doesn't need the permissions that writing to the device directly does.
+///
+/// # Arguments
+/// * `queue_name`: The name of the printer queue.
+/// * `design`: The PCL to print.
+///
+/// # Returns
+/// The ID that CUPS gave the job, if it said, otherwise a [`SendToDeviceError`].
+pub(crate) fn submit_raw_job(
+    queue_name: &str,
+    design: &[u8],
+) -> Result<Option<String>, SendToDeviceError> {
+    let mut lp = Command::new("lp")
+        .args(["-d", queue_name, "-o", "raw"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(SendToDeviceError::FailedToOpenPrinter)?;
+    let written = lp
+        .stdin
+        .take()
+        .map_or(Ok(()), |mut stdin| stdin.write_all(design));
+    let output = lp
+        .wait_with_output()
+        .map_err(SendToDeviceError::FailedToWriteToPrinter)?;
+
+    // lp stops reading the job when it rejects it, so why it rejected it is more useful than the failed write.
+    if !output.status.success() {
+        let reason = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(SendToDeviceError::FailedToOpenPrinter(io::Error::other(
+            if reason.is_empty() {
+                format!("lp could not submit the job to {queue_name}")
+            } else {
+                reason
+            },
+        )));
+    }
+    written.map_err(SendToDeviceError::FailedToWriteToPrinter)?;
+
+    Ok(parse_job_id(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Gets the ID of a job from what `lp` printed when it was submitted.
+///
+/// # Arguments
+/// * `lp_output`: What `lp` printed, e.g. `request id is cutter-12 (1 file(s))`.
+///
+/// # Returns
+/// The ID of the job, or `None` if `lp` didn't say what it was.
+fn parse_job_id(lp_output: &str) -> Option<String> {
+    lp_output
+        .trim()
+        .strip_prefix("request id is ")?
+        .split_whitespace()
+        .next()
+        .map(str::to_string)
+}
+
+/// Checks whether a printer queue exists.
+///
+/// # Arguments
+/// * `queue_name`: The name of the printer queue.
+///
+/// # Returns
+/// `true` if CUPS knows about the queue.
+pub(crate) fn queue_exists(queue_name: &str) -> bool {
+    Command::new("lpstat")
+        .args(["-p", queue_name])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Finds the printer queues that are set up on the system.
+///
+/// # Returns
+/// The names of the queues, or none if CUPS couldn't be asked.
+pub(crate) fn list_queues() -> Vec<String> {
+    let output = match Command::new("lpstat").arg("-e").output() {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            log::warn!(
+                "Could not list printer queues: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            return vec![];
+        }
+        Err(err) => {
+            log::warn!("Could not list printer queues: {err}");
+            return vec![];
+        }
+    };
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|queue_name| !queue_name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_job_id;
+
+    #[test]
+    fn job_ids_are_read_from_lp() {
+        assert_eq!(
+            parse_job_id("request id is cutter-12 (1 file(s))\n"),
+            Some("cutter-12".to_string())
+        );
+        assert_eq!(parse_job_id(""), None);
+        assert_eq!(
+            parse_job_id("lp: Error - no default destination available."),
+            None
+        );
+    }
+}
@@ -7,10 +7,10 @@ use crate::ToolPass;
 /// An array of default tool passes.
 pub fn default_passes() -> Vec<ToolPass> {
     [
-        ToolPass::new("Pass 1".to_string(), 0, 0, 0, 100, 20, false),
-        ToolPass::new("Pass 2".to_string(), 255, 0, 0, 100, 20, false),
-        ToolPass::new("Pass 3".to_string(), 0, 255, 0, 100, 20, false),
-        ToolPass::new("Pass 4".to_string(), 0, 0, 255, 100, 20, false),
+        ToolPass::new("Pass 1".to_string(), 0, 0, 0, 100, 20, false, 400),
+        ToolPass::new("Pass 2".to_string(), 255, 0, 0, 100, 20, false, 400),
+        ToolPass::new("Pass 3".to_string(), 0, 255, 0, 100, 20, false, 400),
+        ToolPass::new("Pass 4".to_string(), 0, 0, 255, 100, 20, false, 400),
     ]
     .to_vec()
 }
@@ -0,0 +1,936 @@
+//! `dxf`
+//!
+//! Provides utilities for handling DXF data, as exported by most CAD tools.
+//! Only the entities that describe lines are supported: `LINE`, `LWPOLYLINE`,
+//! `ARC`, `CIRCLE` and `SPLINE`.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    f32::consts::PI,
+};
+
+use resvg::usvg;
+
+use crate::{
+    paths::{write_svg, PathColour, PathInMM, PointInMillimeters},
+    Colour,
+};
+
+/// The maximum distance, in mm, between a flattened curve and the true curve.
+const CURVE_TOLERANCE_MM: f32 = 0.05;
+
+/// The smallest angle, in radians, between the points of a flattened arc. Huge arcs would otherwise have no
+/// angle between their points at all.
+const MIN_ARC_STEP: f32 = 0.001;
+
+/// The most points that a single arc is flattened into, beyond which it is too large to be real.
+const MAX_ARC_STEPS: usize = 100_000;
+
+/// The number of points to sample along each span of a spline.
+const SPLINE_SAMPLES_PER_SPAN: usize = 16;
+
+/// The DXF colour index that means "use the colour of the layer".
+const COLOUR_BY_LAYER: i16 = 256;
+
+/// The DXF colour index that means "use the colour of the block".
+const COLOUR_BY_BLOCK: i16 = 0;
+
+/// Errors that can occur when reading a DXF file.
+#[derive(Debug)]
+pub enum DxfError {
+    /// The file is not valid UTF-8 text. Binary DXF is not supported.
+    NotUtf8,
+    /// A group code could not be read.
+    InvalidGroupCode {
+        /// The line number (from 1) of the group code.
+        line: usize,
+    },
+    /// A value could not be read as the type that its group code requires.
+    InvalidValue {
+        /// The line number (from 1) of the value.
+        line: usize,
+    },
+    /// The file did not contain anything that could be cut.
+    NoEntities,
+    /// An arc is too large, or not a real arc, so can't be traced.
+    InvalidArc,
+    /// The design could not be turned into an SVG tree.
+    ErrorBuildingTree(usvg::Error),
+}
+
+/// How DXF colours and layers are turned into path colours, which select the tool pass.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DxfColourTable {
+    /// Colours for DXF colour indices.
+    pub colour_indices: HashMap<i16, [u8; 3]>,
+    /// Colours for named layers. These take precedence over the colour of the entity.
+    pub layers: HashMap<String, [u8; 3]>,
+    /// The colour to use when no other colour can be found.
+    pub default_colour: [u8; 3],
+}
+
+impl Default for DxfColourTable {
+    fn default() -> Self {
+        Self {
+            colour_indices: HashMap::from([
+                (1, [255, 0, 0]),
+                (2, [255, 255, 0]),
+                (3, [0, 255, 0]),
+                (4, [0, 255, 255]),
+                (5, [0, 0, 255]),
+                (6, [255, 0, 255]),
+                // Index 7 is drawn as black or white depending on the background, we cut on
+                // a light background so it is black.
+                (7, [0, 0, 0]),
+            ]),
+            layers: HashMap::new(),
+            default_colour: [0, 0, 0],
+        }
+    }
+}
+
+/// A single (group code, value) pair from a DXF file.
+struct Group<'a> {
+    /// The group code, which says what the value means.
+    code: i32,
+    /// The value, as text.
+    value: &'a str,
+    /// The line number (from 1) of the value, for error reporting.
+    line: usize,
+}
+
+impl Group<'_> {
+    /// Reads the value as a floating point number.
+    ///
+    /// # Returns
+    /// The value, otherwise an error.
+    fn float(&self) -> Result<f32, DxfError> {
+        self.value
+            .parse()
+            .map_err(|_| DxfError::InvalidValue { line: self.line })
+    }
+
+    /// Reads the value as an integer.
+    ///
+    /// # Returns
+    /// The value, otherwise an error.
+    fn int(&self) -> Result<i64, DxfError> {
+        self.value
+            .parse()
+            .map_err(|_| DxfError::InvalidValue { line: self.line })
+    }
+}
+
+/// An entity that has been read from the DXF but not yet traced.
+#[derive(Default)]
+struct RawEntity {
+    /// The kind of entity, e.g. `LINE`.
+    kind: String,
+    /// The layer the entity is on.
+    layer: String,
+    /// The colour index of the entity, if set.
+    colour_index: Option<i16>,
+    /// The true colour of the entity, if set.
+    true_colour: Option<[u8; 3]>,
+    /// The values of each group code, in order of appearance.
+    values: Vec<(i32, f32)>,
+    /// Flags (group code 70).
+    flags: i64,
+}
+
+impl RawEntity {
+    /// Gets the first value for a group code.
+    ///
+    /// # Arguments
+    /// * `code`: The group code.
+    ///
+    /// # Returns
+    /// The value, or `0.0` if there is none.
+    fn first(&self, code: i32) -> f32 {
+        self.all(code).next().unwrap_or(0.0)
+    }
+
+    /// Gets all of the values for a group code.
+    ///
+    /// # Arguments
+    /// * `code`: The group code.
+    ///
+    /// # Returns
+    /// The values, in the order they appeared.
+    fn all(&self, code: i32) -> impl Iterator<Item = f32> + '_ {
+        self.values
+            .iter()
+            .filter(move |(value_code, _)| *value_code == code)
+            .map(|(_, value)| *value)
+    }
+}
+
+/// Parses a DXF file into paths, grouped by colour.
+/// The drawing is converted into mm, flipped so that +y is down, and moved so that its
+/// top-left corner is at the origin.
+///
+/// # Arguments
+/// * `bytes`: The bytes of the file.
+/// * `colour_table`: How to turn DXF colours and layers into path colours.
+///
+/// # Returns
+/// The paths grouped by colour along with the width and height of the drawing in mm, otherwise an error.
+pub fn parse_dxf(
+    bytes: &[u8],
+    colour_table: &DxfColourTable,
+) -> Result<(BTreeMap<PathColour, Vec<PathInMM>>, f32, f32), DxfError> {
+    let text = std::str::from_utf8(bytes).map_err(|_| DxfError::NotUtf8)?;
+    let groups = read_groups(text)?;
+
+    let mut units_to_mm = 1.0;
+    let mut layer_colours: HashMap<String, i16> = HashMap::new();
+    let mut entities: Vec<RawEntity> = vec![];
+
+    let mut section = "";
+    let mut iter = groups.iter().peekable();
+    while let Some(group) = iter.next() {
+        match (group.code, group.value) {
+            (0, "SECTION") => {
+                if let Some(name) = iter.next_if(|group| group.code == 2) {
+                    section = name.value;
+                }
+            }
+            (0, "ENDSEC") => section = "",
+            (9, "$INSUNITS") if section == "HEADER" => {
+                if let Some(units) = iter.next_if(|group| group.code == 70) {
+                    units_to_mm = insunits_to_mm(units.int()?);
+                }
+            }
+            (0, "LAYER") if section == "TABLES" => {
+                let mut name = None;
+                let mut colour = None;
+                while let Some(group) = iter.next_if(|group| group.code != 0) {
+                    match group.code {
+                        2 => name = Some(group.value.to_string()),
+                        62 => colour = Some(group.int()? as i16),
+                        _ => {}
+                    }
+                }
+                if let (Some(name), Some(colour)) = (name, colour) {
+                    // Negative colours mean that the layer is turned off, but still has that colour.
+                    layer_colours.insert(name, colour.abs());
+                }
+            }
+            (0, kind) if section == "ENTITIES" => {
+                let mut entity = RawEntity {
+                    kind: kind.to_string(),
+                    ..Default::default()
+                };
+                while let Some(group) = iter.next_if(|group| group.code != 0) {
+                    match group.code {
+                        8 => entity.layer = group.value.to_string(),
+                        62 => entity.colour_index = Some(group.int()? as i16),
+                        420 => {
+                            let colour = group.int()?;
+                            entity.true_colour = Some([
+                                ((colour >> 16) & 0xFF) as u8,
+                                ((colour >> 8) & 0xFF) as u8,
+                                (colour & 0xFF) as u8,
+                            ]);
+                        }
+                        70 => entity.flags = group.int()?,
+                        10 | 20 | 11 | 21 | 40 | 42 | 50 | 51 | 71 => {
+                            entity.values.push((group.code, group.float()?));
+                        }
+                        _ => {}
+                    }
+                }
+                entities.push(entity);
+            }
+            _ => {}
+        }
+    }
+
+    let mut paths: Vec<(PathColour, PathInMM)> = vec![];
+    for entity in &entities {
+        let colour = entity_colour(entity, &layer_colours, colour_table);
+        for path in trace_entity(entity, units_to_mm)? {
+            if path.len() >= 2 {
+                paths.push((colour, path));
+            }
+        }
+    }
+
+    // Work out the extents of the drawing so that it can be moved to the origin.
+    let mut min = PointInMillimeters {
+        x: f32::INFINITY,
+        y: f32::INFINITY,
+    };
+    let mut max = PointInMillimeters {
+        x: f32::NEG_INFINITY,
+        y: f32::NEG_INFINITY,
+    };
+    for point in paths.iter().flat_map(|(_, path)| path) {
+        min.x = min.x.min(point.x);
+        min.y = min.y.min(point.y);
+        max.x = max.x.max(point.x);
+        max.y = max.y.max(point.y);
+    }
+
+    if paths.is_empty() {
+        return Err(DxfError::NoEntities);
+    }
+
+    let mut grouped_paths: BTreeMap<PathColour, Vec<PathInMM>> = BTreeMap::new();
+    for (colour, path) in paths {
+        let path = path
+            .into_iter()
+            .map(|point| PointInMillimeters {
+                x: (point.x - min.x) * units_to_mm,
+                // DXF has +y going up.
+                y: (max.y - point.y) * units_to_mm,
+            })
+            .collect();
+        grouped_paths.entry(colour).or_default().push(path);
+    }
+
+    Ok((
+        grouped_paths,
+        (max.x - min.x) * units_to_mm,
+        (max.y - min.y) * units_to_mm,
+    ))
+}
+
+/// Parses a DXF file into an SVG tree, so that it can be used in the same way as an SVG design.
+///
+/// # Arguments
+/// * `bytes`: The bytes of the file.
+/// * `colour_table`: How to turn DXF colours and layers into path colours.
+///
+/// # Returns
+/// The SVG tree, otherwise an error.
+pub fn parse_dxf_to_svg(
+    bytes: &[u8],
+    colour_table: &DxfColourTable,
+) -> Result<usvg::Tree, DxfError> {
+    let (paths, width_mm, height_mm) = parse_dxf(bytes, colour_table)?;
+
+    let svg = write_svg(&paths, 0.0, 0.0, width_mm, height_mm);
+
+    usvg::Tree::from_str(&svg, &usvg::Options::default()).map_err(DxfError::ErrorBuildingTree)
+}
+
+/// Reads the (group code, value) pairs that make up a DXF file.
+///
+/// # Arguments
+/// * `text`: The text of the file.
+///
+/// # Returns
+/// The groups, otherwise an error.
+fn read_groups(text: &str) -> Result<Vec<Group<'_>>, DxfError> {
+    let mut groups = vec![];
+    let mut lines = text.lines().enumerate();
+    while let Some((code_line, code)) = lines.next() {
+        let code = code.trim();
+        if code.is_empty() {
+            continue;
+        }
+
+        let code = code.parse().map_err(|_| DxfError::InvalidGroupCode {
+            line: code_line + 1,
+        })?;
+        let Some((value_line, value)) = lines.next() else {
+            return Err(DxfError::InvalidValue {
+                line: code_line + 2,
+            });
+        };
+
+        groups.push(Group {
+            code,
+            value: value.trim(),
+            line: value_line + 1,
+        });
+    }
+
+    Ok(groups)
+}
+
+/// Gets the scale from DXF drawing units to mm.
+///
+/// # Arguments
+/// * `insunits`: The value of the `$INSUNITS` header variable.
+///
+/// # Returns
+/// The number of mm per drawing unit.
+fn insunits_to_mm(insunits: i64) -> f32 {
+    match insunits {
+        // Unitless, assume mm.
+        0 | 4 => 1.0,
+        1 => 25.4,
+        2 => 304.8,
+        5 => 10.0,
+        6 => 1000.0,
+        8 => 0.000_025_4,
+        9 => 0.0254,
+        10 => 914.4,
+        13 => 0.001,
+        14 => 100.0,
+        other => {
+            log::warn!("Unsupported DXF units {other}, assuming mm");
+            1.0
+        }
+    }
+}
+
+/// Works out the colour of the paths of an entity.
+///
+/// # Arguments
+/// * `entity`: The entity.
+/// * `layer_colours`: The colour index of each layer.
+/// * `colour_table`: How to turn DXF colours and layers into path colours.
+///
+/// # Returns
+/// The colour of the entity's paths.
+fn entity_colour(
+    entity: &RawEntity,
+    layer_colours: &HashMap<String, i16>,
+    colour_table: &DxfColourTable,
+) -> PathColour {
+    if let Some(colour) = colour_table.layers.get(&entity.layer) {
+        return Colour(*colour);
+    }
+
+    if let Some(colour) = entity.true_colour {
+        return Colour(colour);
+    }
+
+    let index = match entity.colour_index {
+        Some(COLOUR_BY_LAYER | COLOUR_BY_BLOCK) | None => layer_colours.get(&entity.layer).copied(),
+        Some(index) => Some(index),
+    };
+
+    Colour(
+        index
+            .and_then(|index| colour_table.colour_indices.get(&index))
+            .copied()
+            .unwrap_or(colour_table.default_colour),
+    )
+}
+
+/// Traces an entity into paths, in drawing units with +y going up.
+/// Unsupported entities produce no paths.
+///
+/// # Arguments
+/// * `entity`: The entity to trace.
+/// * `units_to_mm`: How many mm each drawing unit is, so that curves are traced to the same tolerance in any units.
+///
+/// # Returns
+/// The traced paths, otherwise an error if the entity's geometry can't be traced.
+fn trace_entity(entity: &RawEntity, units_to_mm: f32) -> Result<Vec<PathInMM>, DxfError> {
+    let point = |x, y| PointInMillimeters { x, y };
+
+    let paths = match entity.kind.as_str() {
+        "LINE" => vec![vec![
+            point(entity.first(10), entity.first(20)),
+            point(entity.first(11), entity.first(21)),
+        ]],
+        "LWPOLYLINE" => {
+            let vertices: Vec<PointInMillimeters> = entity
+                .all(10)
+                .zip(entity.all(20))
+                .map(|(x, y)| point(x, y))
+                .collect();
+            let bulges = lwpolyline_bulges(entity, vertices.len());
+            let closed = entity.flags & 1 == 1;
+
+            let mut path = vec![];
+            let segment_count = if closed {
+                vertices.len()
+            } else {
+                vertices.len().saturating_sub(1)
+            };
+            for index in 0..segment_count {
+                let start = vertices[index];
+                let end = vertices[(index + 1) % vertices.len()];
+                if path.is_empty() {
+                    path.push(start);
+                }
+                trace_bulge(start, end, bulges[index], units_to_mm, &mut path)?;
+            }
+            vec![path]
+        }
+        "ARC" => {
+            let centre = point(entity.first(10), entity.first(20));
+            let radius = entity.first(40);
+            let start = entity.first(50).to_radians();
+            let mut end = entity.first(51).to_radians();
+            // Arcs always go anticlockwise.
+            if end <= start {
+                end += 2.0 * PI;
+            }
+            vec![trace_arc(centre, radius, start, end, units_to_mm)?]
+        }
+        "CIRCLE" => {
+            let centre = point(entity.first(10), entity.first(20));
+            vec![trace_arc(
+                centre,
+                entity.first(40),
+                0.0,
+                2.0 * PI,
+                units_to_mm,
+            )?]
+        }
+        "SPLINE" => {
+            let control_points: Vec<PointInMillimeters> = entity
+                .all(10)
+                .zip(entity.all(20))
+                .map(|(x, y)| point(x, y))
+                .collect();
+            let knots: Vec<f32> = entity.all(40).collect();
+            // The degree (group code 71) should always be given, but if it isn't it can be worked out from the knots.
+            let degree = entity.all(71).next().map_or_else(
+                || knots.len().saturating_sub(control_points.len() + 1),
+                |degree| degree as usize,
+            );
+
+            // A B-spline has one more knot than it has control points for each degree.
+            if degree > 0
+                && degree < control_points.len()
+                && knots.len() == control_points.len() + degree + 1
+            {
+                vec![trace_spline(&control_points, &knots, degree)]
+            } else {
+                // No usable control points, fall back to the points the spline passes through.
+                vec![entity
+                    .all(11)
+                    .zip(entity.all(21))
+                    .map(|(x, y)| point(x, y))
+                    .collect()]
+            }
+        }
+        _ => vec![],
+    };
+
+    Ok(paths)
+}
+
+/// Gets the bulge of each vertex of a lightweight polyline.
+/// A bulge (group code 42) applies to the vertex it follows.
+///
+/// # Arguments
+/// * `entity`: The polyline.
+/// * `vertex_count`: The number of vertices in the polyline.
+///
+/// # Returns
+/// The bulge for each vertex, `0.0` for straight segments.
+fn lwpolyline_bulges(entity: &RawEntity, vertex_count: usize) -> Vec<f32> {
+    let mut bulges = vec![0.0; vertex_count];
+    let mut vertex = None;
+    for (code, value) in &entity.values {
+        match code {
+            10 => vertex = Some(vertex.map_or(0, |vertex| vertex + 1)),
+            42 => {
+                if let Some(bulge) = vertex.and_then(|vertex| bulges.get_mut(vertex)) {
+                    *bulge = *value;
+                }
+            }
+            _ => {}
+        }
+    }
+    bulges
+}
+
+/// Traces a polyline segment, which may be an arc, appending the points after the start.
+///
+/// # Arguments
+/// * `start`: The start of the segment.
+/// * `end`: The end of the segment.
+/// * `bulge`: The tangent of a quarter of the arc's angle, positive for anticlockwise.
+/// * `units_to_mm`: How many mm each drawing unit is.
+/// * `path`: The path to append points to.
+///
+/// # Returns
+/// `Ok(())` if the segment was traced, otherwise [`DxfError::InvalidArc`] if it is an arc that can't be traced.
+fn trace_bulge(
+    start: PointInMillimeters,
+    end: PointInMillimeters,
+    bulge: f32,
+    units_to_mm: f32,
+    path: &mut PathInMM,
+) -> Result<(), DxfError> {
+    let chord = ((end.x - start.x).powi(2) + (end.y - start.y).powi(2)).sqrt();
+    if bulge == 0.0 || chord == 0.0 {
+        path.push(end);
+        return Ok(());
+    }
+
+    let sweep = 4.0 * bulge.atan();
+    let radius = chord / (2.0 * (sweep / 2.0).sin());
+    // The centre is along the perpendicular bisector of the chord.
+    let mid = PointInMillimeters {
+        x: (start.x + end.x) / 2.0,
+        y: (start.y + end.y) / 2.0,
+    };
+    let distance_to_centre = radius * (sweep / 2.0).cos();
+    let centre = PointInMillimeters {
+        x: mid.x - distance_to_centre * (end.y - start.y) / chord,
+        y: mid.y + distance_to_centre * (end.x - start.x) / chord,
+    };
+
+    let start_angle = (start.y - centre.y).atan2(start.x - centre.x);
+    let arc = trace_arc(
+        centre,
+        radius.abs(),
+        start_angle,
+        start_angle + sweep,
+        units_to_mm,
+    )?;
+    path.extend(arc.into_iter().skip(1));
+    Ok(())
+}
+
+/// Traces an arc.
+///
+/// # Arguments
+/// * `centre`: The centre of the arc.
+/// * `radius`: The radius of the arc.
+/// * `start`: The angle to start at, in radians.
+/// * `end`: The angle to end at, in radians. May be less than `start` to go clockwise.
+/// * `units_to_mm`: How many mm each drawing unit is, as the points are traced in drawing units
+/// but the tolerance is in mm.
+///
+/// # Returns
+/// Points along the arc, including the start and end, otherwise [`DxfError::InvalidArc`] if the arc
+/// isn't finite or would take too many points to trace.
+fn trace_arc(
+    centre: PointInMillimeters,
+    radius: f32,
+    start: f32,
+    end: f32,
+    units_to_mm: f32,
+) -> Result<PathInMM, DxfError> {
+    if ![centre.x, centre.y, radius, start, end]
+        .iter()
+        .all(|value| value.is_finite())
+    {
+        return Err(DxfError::InvalidArc);
+    }
+
+    let radius_mm = radius * units_to_mm;
+    let step = if radius_mm > CURVE_TOLERANCE_MM {
+        2.0 * (1.0 - (CURVE_TOLERANCE_MM / radius_mm)).acos()
+    } else {
+        PI / 2.0
+    };
+    let steps = ((end - start).abs() / step.max(MIN_ARC_STEP))
+        .ceil()
+        .max(1.0);
+    if steps > MAX_ARC_STEPS as f32 {
+        return Err(DxfError::InvalidArc);
+    }
+    let steps = steps as usize;
+
+    Ok((0..=steps)
+        .map(|index| {
+            let angle = start + ((end - start) * (index as f32) / (steps as f32));
+            PointInMillimeters {
+                x: centre.x + (radius * angle.cos()),
+                y: centre.y + (radius * angle.sin()),
+            }
+        })
+        .collect())
+}
+
+/// Traces a B-spline.
+///
+/// # Arguments
+/// * `control_points`: The control points of the spline.
+/// * `knots`: The knot vector of the spline.
+/// * `degree`: The degree of the spline.
+///
+/// # Returns
+/// Points along the spline.
+fn trace_spline(control_points: &[PointInMillimeters], knots: &[f32], degree: usize) -> PathInMM {
+    let start = knots[degree];
+    let end = knots[control_points.len()];
+    let samples = SPLINE_SAMPLES_PER_SPAN * (control_points.len() - degree).max(1);
+
+    (0..=samples)
+        .map(|index| {
+            let t = start + ((end - start) * (index as f32) / (samples as f32));
+            de_boor(control_points, knots, degree, t)
+        })
+        .collect()
+}
+
+/// Evaluates a B-spline at a parameter using de Boor's algorithm.
+///
+/// # Arguments
+/// * `control_points`: The control points of the spline.
+/// * `knots`: The knot vector of the spline.
+/// * `degree`: The degree of the spline.
+/// * `t`: The parameter to evaluate at.
+///
+/// # Returns
+/// The point on the spline.
+fn de_boor(
+    control_points: &[PointInMillimeters],
+    knots: &[f32],
+    degree: usize,
+    t: f32,
+) -> PointInMillimeters {
+    // Find the knot span that contains t.
+    let mut span = degree;
+    while span < control_points.len() - 1 && t >= knots[span + 1] {
+        span += 1;
+    }
+
+    let mut points: Vec<PointInMillimeters> = (0..=degree)
+        .map(|j| control_points[j + span - degree])
+        .collect();
+
+    for r in 1..=degree {
+        for j in (r..=degree).rev() {
+            let i = j + span - degree;
+            let denominator = knots[i + degree + 1 - r] - knots[i];
+            let alpha = if denominator == 0.0 {
+                0.0
+            } else {
+                (t - knots[i]) / denominator
+            };
+            points[j] = PointInMillimeters {
+                x: ((1.0 - alpha) * points[j - 1].x) + (alpha * points[j].x),
+                y: ((1.0 - alpha) * points[j - 1].y) + (alpha * points[j].y),
+            };
+        }
+    }
+
+    points[degree]
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{
+        parse_dxf, parse_dxf_to_svg, trace_entity, DxfColourTable, DxfError, RawEntity,
+        SPLINE_SAMPLES_PER_SPAN,
+    };
+    use crate::{
+        hpgl::{generate_hpgl, HpglOptions},
+        paths::convert_points_to_plotter_units,
+        Colour, PointInMillimeters, PrintBed, ToolPass,
+    };
+
+    /// A 20mm x 10mm red rectangle, with a blue circle of radius 5mm to its right.
+    const RECTANGLE_AND_CIRCLE: &str = "0
+SECTION
+2
+HEADER
+9
+$INSUNITS
+70
+4
+0
+ENDSEC
+0
+SECTION
+2
+ENTITIES
+0
+LWPOLYLINE
+8
+0
+62
+1
+90
+4
+70
+1
+10
+0.0
+20
+0.0
+10
+20.0
+20
+0.0
+10
+20.0
+20
+10.0
+10
+0.0
+20
+10.0
+0
+CIRCLE
+8
+0
+62
+5
+10
+30.0
+20
+5.0
+40
+5.0
+0
+ENDSEC
+0
+EOF
+";
+
+    #[test]
+    fn rectangle_and_circle() {
+        let (paths, width, height) =
+            parse_dxf(RECTANGLE_AND_CIRCLE.as_bytes(), &DxfColourTable::default())
+                .expect("DXF should parse");
+        assert_eq!((width, height), (35.0, 10.0));
+
+        let circles = paths
+            .get(&Colour([0, 0, 255]))
+            .expect("Circle should be blue");
+        assert_eq!(circles.len(), 1);
+        for point in &circles[0] {
+            let radius = ((point.x - 30.0).powi(2) + (point.y - 5.0).powi(2)).sqrt();
+            assert_eq!((radius * 100.0).round() / 100.0, 5.0);
+        }
+
+        let resolved = convert_points_to_plotter_units(&paths, (0.0, 0.0), &PrintBed::GCC_SPIRIT)
+            .expect("Paths should be on the bed");
+        let passes = vec![ToolPass::new(
+            "Cut".to_string(),
+            255,
+            0,
+            0,
+            100,
+            100,
+            false,
+            400,
+        )];
+        let hpgl = generate_hpgl(
+            &resolved,
+            &passes,
+            &PrintBed::GCC_SPIRIT,
+            &HpglOptions::default(),
+        )
+        .expect("HPGL should generate");
+        assert_eq!(
+            hpgl,
+            "IN;SC;PU;SP1;LT;PU0,18528;SP1;PU0,18128;PD0,18128,800,18128,800,18528,0,18528,0,18128;PU0,18528;SP1;EC0;EC1;OE;"
+        );
+    }
+
+    #[test]
+    fn layers_override_colours() {
+        let colour_table = DxfColourTable {
+            layers: HashMap::from([("0".to_string(), [0, 255, 0])]),
+            ..Default::default()
+        };
+        let (paths, _, _) =
+            parse_dxf(RECTANGLE_AND_CIRCLE.as_bytes(), &colour_table).expect("DXF should parse");
+        assert_eq!(paths.keys().collect::<Vec<_>>(), vec![&Colour([0, 255, 0])]);
+    }
+
+    #[test]
+    fn converts_to_svg() {
+        let tree = parse_dxf_to_svg(RECTANGLE_AND_CIRCLE.as_bytes(), &DxfColourTable::default())
+            .expect("DXF should convert");
+        let paths = crate::svg::get_paths_grouped_by_colour(&tree).expect("Paths should group");
+        assert_eq!(paths.len(), 2);
+    }
+
+    #[test]
+    fn splines_use_their_degree() {
+        let spline = |degree: Option<f32>, knots: &[f32]| {
+            let mut values = vec![
+                (10, 0.0),
+                (20, 0.0),
+                (10, 10.0),
+                (20, 10.0),
+                (10, 20.0),
+                (20, 0.0),
+                (11, 0.0),
+                (21, 0.0),
+                (11, 20.0),
+                (21, 0.0),
+            ];
+            values.extend(degree.map(|degree| (71, degree)));
+            values.extend(knots.iter().map(|knot| (40, *knot)));
+            RawEntity {
+                kind: "SPLINE".to_string(),
+                values,
+                ..Default::default()
+            }
+        };
+        let trace_entity_ok =
+            |entity: &RawEntity| trace_entity(entity, 1.0).expect("Spline should trace");
+        let point = |x, y| PointInMillimeters { x, y };
+        let fit_points = vec![vec![point(0.0, 0.0), point(20.0, 0.0)]];
+
+        // A quadratic spline through its end control points, with the peak halfway up the middle one.
+        let knots = [0.0, 0.0, 0.0, 1.0, 1.0, 1.0];
+        let paths = trace_entity_ok(&spline(Some(2.0), &knots));
+        assert_eq!(paths[0].len(), SPLINE_SAMPLES_PER_SPAN + 1);
+        assert_eq!(paths[0].first(), Some(&point(0.0, 0.0)));
+        assert_eq!(paths[0][SPLINE_SAMPLES_PER_SPAN / 2], point(10.0, 5.0));
+        assert_eq!(paths[0].last(), Some(&point(20.0, 0.0)));
+        assert_eq!(trace_entity_ok(&spline(None, &knots)), paths);
+
+        // Splines that don't add up fall back to the points they pass through.
+        assert_eq!(trace_entity_ok(&spline(Some(3.0), &knots)), fit_points);
+        assert_eq!(trace_entity_ok(&spline(Some(2.0), &knots[1..])), fit_points);
+        assert_eq!(trace_entity_ok(&spline(Some(1.0), &knots)), fit_points);
+        assert_eq!(trace_entity_ok(&spline(None, &[])), fit_points);
+    }
+
+    #[test]
+    fn unreasonable_arcs_are_errors() {
+        let arc = |radius: f32, end_angle: f32| RawEntity {
+            kind: "ARC".to_string(),
+            values: vec![
+                (10, 0.0),
+                (20, 0.0),
+                (40, radius),
+                (50, 0.0),
+                (51, end_angle),
+            ],
+            ..Default::default()
+        };
+
+        // Arcs so large that the tolerance is lost are still traced, in small steps.
+        let paths = trace_entity(&arc(1.0e9, 90.0), 1.0).expect("Huge arc should trace");
+        assert_eq!(paths[0].len(), 1572);
+
+        assert_eq!(
+            matches!(
+                trace_entity(&arc(10.0, 1.0e9), 1.0),
+                Err(DxfError::InvalidArc)
+            ),
+            true,
+            "Arc that goes around too many times should be an error"
+        );
+        assert_eq!(
+            matches!(
+                trace_entity(&arc(f32::NAN, 90.0), 1.0),
+                Err(DxfError::InvalidArc)
+            ),
+            true,
+            "Arc that isn't a number should be an error"
+        );
+    }
+
+    #[test]
+    fn curves_are_traced_to_the_same_tolerance_in_any_units() {
+        let circle_points = |dxf: &str| {
+            let (paths, _, _) =
+                parse_dxf(dxf.as_bytes(), &DxfColourTable::default()).expect("DXF should parse");
+            paths
+                .get(&Colour([0, 0, 255]))
+                .expect("Circle should be blue")[0]
+                .len()
+        };
+
+        // A circle of radius 5mm.
+        assert_eq!(circle_points(RECTANGLE_AND_CIRCLE), 24);
+        // The same drawing in inches has a circle of radius 127mm, which needs more, shorter, segments.
+        let in_inches = RECTANGLE_AND_CIRCLE.replace("$INSUNITS\n70\n4\n", "$INSUNITS\n70\n1\n");
+        assert_eq!(circle_points(&in_inches), 113);
+    }
+}
@@ -0,0 +1,351 @@
+//! `fill`
+//!
+//! Generates hatching for filled shapes, so that they can be engraved by
+//! tracing a series of parallel lines across them.
+
+use std::collections::BTreeMap;
+
+use lyon_algorithms::path::{iterator::PathIterator, PathEvent};
+use resvg::usvg;
+
+use crate::{
+    paths::{build_path, PathColour, PathInMM, PointInMillimeters},
+    Colour, ToolPass,
+};
+
+/// The path flattening tolerance used when working out the outline of a filled shape.
+const FLATTENING_TOLERANCE: f32 = 0.1;
+
+/// The smallest distance between hatching lines, in mm. Lines any closer than this would overlap
+/// in the cut, and there would be so many of them that hatching would never finish.
+pub const MIN_HATCH_SPACING_MM: f32 = 0.01;
+
+/// How filled shapes should be hatched.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct HatchOptions {
+    /// The distance between each line of the hatching, in mm.
+    pub spacing_mm: f32,
+    /// The angle of the hatching lines, in degrees, where 0 is horizontal and
+    /// positive angles rotate clockwise (as +y is down).
+    pub angle_degrees: f32,
+}
+
+impl Default for HatchOptions {
+    fn default() -> Self {
+        Self {
+            spacing_mm: 0.5,
+            angle_degrees: 0.0,
+        }
+    }
+}
+
+/// A straight edge of the outline of a filled shape.
+type Edge = (PointInMillimeters, PointInMillimeters);
+
+/// Finds all of the filled paths in the SVG whose fill colour matches a tool pass,
+/// and generates hatching lines that cover them.
+///
+/// # Arguments
+/// * `svg`: The SVG to search for filled paths.
+/// * `tool_passes`: The tool passes, only fills with a colour matching a pass are hatched.
+/// * `options`: How to hatch the filled shapes.
+///
+/// # Returns
+/// Hatching lines in mm, grouped by the fill colour of the shapes they cover.
+pub fn hatch_fills(
+    svg: &usvg::Tree,
+    tool_passes: &Vec<ToolPass>,
+    options: &HatchOptions,
+) -> BTreeMap<PathColour, Vec<PathInMM>> {
+    let mut hatched = BTreeMap::new();
+    if options.spacing_mm.is_nan() || options.spacing_mm <= 0.0 {
+        log::warn!("Hatch spacing must be positive, not hatching fills");
+        return hatched;
+    }
+
+    let mut options = *options;
+    if options.spacing_mm < MIN_HATCH_SPACING_MM {
+        log::warn!(
+            "Hatch spacing of {}mm is too small, hatching every {MIN_HATCH_SPACING_MM}mm instead",
+            options.spacing_mm
+        );
+        options.spacing_mm = MIN_HATCH_SPACING_MM;
+    }
+
+    hatch_group(svg.root(), tool_passes, &options, &mut hatched);
+    hatched
+}
+
+/// Does the actual hatching of filled paths.
+/// Be warned, here be recursion.
+///
+/// # Arguments
+/// * `group`: The SVG group to search through for filled paths. May contain nested groups.
+/// * `tool_passes`: The tool passes, only fills with a colour matching a pass are hatched.
+/// * `options`: How to hatch the filled shapes.
+/// * `hatched`: The hatching to extend with any new lines generated.
+fn hatch_group(
+    group: &usvg::Group,
+    tool_passes: &Vec<ToolPass>,
+    options: &HatchOptions,
+    hatched: &mut BTreeMap<PathColour, Vec<PathInMM>>,
+) {
+    for child in group.children() {
+        match child {
+            usvg::Node::Group(child_group) => {
+                hatch_group(child_group, tool_passes, options, hatched)
+            }
+            usvg::Node::Path(path) => {
+                if !path.is_visible() {
+                    continue;
+                }
+
+                let Some(fill) = path.fill() else {
+                    continue;
+                };
+
+                let usvg::Paint::Color(colour) = fill.paint() else {
+                    continue;
+                };
+
+                let path_colour = Colour([colour.red, colour.green, colour.blue]);
+                if !tool_passes.iter().any(|pass| *pass.colour() == path_colour) {
+                    continue;
+                }
+
+                let edges = outline_edges(path);
+                let lines = hatch_edges(&edges, fill.rule(), options);
+                hatched.entry(path_colour).or_default().extend(lines);
+            }
+            usvg::Node::Image(_) | usvg::Node::Text(_) => {}
+        }
+
+        child.subroots(|subroot| hatch_group(subroot, tool_passes, options, hatched));
+    }
+}
+
+/// Gets the straight edges that make up the outline of a filled path.
+/// Every sub-path is treated as closed, as fills always are.
+///
+/// # Arguments
+/// * `path`: The path to get the outline of.
+///
+/// # Returns
+/// The edges of the outline.
+fn outline_edges(path: &usvg::Path) -> Vec<Edge> {
+    let mut edges = vec![];
+    for event in build_path(path).iter().flattened(FLATTENING_TOLERANCE) {
+        match event {
+            PathEvent::Line { from, to } => edges.push((from.into(), to.into())),
+            PathEvent::End { last, first, .. } => edges.push((last.into(), first.into())),
+            PathEvent::Begin { .. } | PathEvent::Quadratic { .. } | PathEvent::Cubic { .. } => {}
+        }
+    }
+
+    edges
+}
+
+/// Generates hatching lines that cover the inside of a shape.
+///
+/// The shape is rotated so that the hatching lines are horizontal, then each
+/// line is clipped against the outline, and the results rotated back.
+///
+/// # Arguments
+/// * `edges`: The edges of the outline of the shape.
+/// * `fill_rule`: How to decide what is inside the shape.
+/// * `options`: How to hatch the shape.
+///
+/// # Returns
+/// The hatching lines, each of which is a path of two points.
+fn hatch_edges(edges: &[Edge], fill_rule: usvg::FillRule, options: &HatchOptions) -> Vec<PathInMM> {
+    let angle = options.angle_degrees.to_radians();
+    let (sin, cos) = angle.sin_cos();
+    // Rotate by -angle so that hatch lines become horizontal.
+    let to_hatch_space = |p: PointInMillimeters| (p.x * cos + p.y * sin, -p.x * sin + p.y * cos);
+    let from_hatch_space = |(x, y): (f32, f32)| PointInMillimeters {
+        x: x * cos - y * sin,
+        y: x * sin + y * cos,
+    };
+
+    let rotated_edges: Vec<((f32, f32), (f32, f32))> = edges
+        .iter()
+        .map(|(start, end)| (to_hatch_space(*start), to_hatch_space(*end)))
+        .filter(|(start, end)| start.1 != end.1)
+        .collect();
+
+    let Some((min_y, max_y)) = rotated_edges
+        .iter()
+        .flat_map(|(start, end)| [start.1, end.1])
+        .fold(None, |range: Option<(f32, f32)>, y| match range {
+            Some((min, max)) => Some((min.min(y), max.max(y))),
+            None => Some((y, y)),
+        })
+    else {
+        return vec![];
+    };
+
+    // Lines are centred in each band of the shape, so a shape `n` spacings tall gets `n` lines.
+    // They are counted up front, as adding the spacing up line by line can stop moving for tiny spacings.
+    let line_count = (((max_y - min_y) / options.spacing_mm) - 0.5).ceil();
+    if !line_count.is_finite() {
+        return vec![];
+    }
+
+    let mut lines = vec![];
+    for index in 0..line_count.max(0.0) as usize {
+        let y = min_y + ((index as f32 + 0.5) * options.spacing_mm);
+        // Work out where this line crosses the outline, and in which direction the outline goes.
+        let mut crossings: Vec<(f32, i32)> = rotated_edges
+            .iter()
+            .filter(|(start, end)| (start.1 <= y) != (end.1 <= y))
+            .map(|(start, end)| {
+                let t = (y - start.1) / (end.1 - start.1);
+                let x = start.0 + (t * (end.0 - start.0));
+                let winding = if end.1 > start.1 { 1 } else { -1 };
+                (x, winding)
+            })
+            .collect();
+        crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut winding = 0;
+        let mut line_start = None;
+        for (x, direction) in crossings {
+            let was_inside = is_inside(winding, fill_rule);
+            winding += direction;
+            let now_inside = is_inside(winding, fill_rule);
+
+            if !was_inside && now_inside {
+                line_start = Some(x);
+            } else if was_inside && !now_inside {
+                if let Some(start_x) = line_start.take() {
+                    if x > start_x {
+                        lines.push(vec![
+                            from_hatch_space((start_x, y)),
+                            from_hatch_space((x, y)),
+                        ]);
+                    }
+                }
+            }
+        }
+    }
+
+    lines
+}
+
+/// Checks whether a winding number counts as being inside a shape.
+///
+/// # Arguments
+/// * `winding`: The winding number.
+/// * `fill_rule`: The fill rule of the shape.
+///
+/// # Returns
+/// `true` if the winding number is inside the shape.
+fn is_inside(winding: i32, fill_rule: usvg::FillRule) -> bool {
+    match fill_rule {
+        usvg::FillRule::NonZero => winding != 0,
+        usvg::FillRule::EvenOdd => winding % 2 != 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::{hatch_fills, HatchOptions, MIN_HATCH_SPACING_MM};
+    use crate::{svg::parse_svg, Colour, ToolPass};
+
+    #[test]
+    fn hatch_square() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="20mm" height="20mm" viewBox="0 0 20 20">
+            <rect x="5" y="5" width="10" height="10" fill="blue"/>
+        </svg>"#;
+        let tree =
+            parse_svg(&PathBuf::from("square.svg"), svg.as_bytes()).expect("SVG should parse");
+        let passes = vec![ToolPass::new(
+            "Engrave".to_string(),
+            0,
+            0,
+            255,
+            100,
+            100,
+            false,
+            400,
+        )];
+        let options = HatchOptions {
+            spacing_mm: 1.0,
+            angle_degrees: 0.0,
+        };
+
+        let hatched = hatch_fills(&tree, &passes, &options);
+        let lines = hatched
+            .get(&Colour([0, 0, 255]))
+            .expect("Square should be hatched");
+
+        assert_eq!(lines.len(), 10);
+        for line in lines {
+            assert_eq!(line.len(), 2);
+            assert_eq!(line[0].y, line[1].y, "Lines should be horizontal");
+            assert_eq!(
+                line[0].x.round(),
+                5.0,
+                "Lines should start at the left edge"
+            );
+            assert_eq!(
+                line[1].x.round(),
+                15.0,
+                "Lines should end at the right edge"
+            );
+        }
+    }
+
+    #[test]
+    fn unmatched_fills_are_not_hatched() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="20mm" height="20mm" viewBox="0 0 20 20">
+            <rect x="5" y="5" width="10" height="10" fill="red"/>
+        </svg>"#;
+        let tree =
+            parse_svg(&PathBuf::from("square.svg"), svg.as_bytes()).expect("SVG should parse");
+        let passes = vec![ToolPass::new(
+            "Engrave".to_string(),
+            0,
+            0,
+            255,
+            100,
+            100,
+            false,
+            400,
+        )];
+
+        let hatched = hatch_fills(&tree, &passes, &HatchOptions::default());
+        assert_eq!(hatched.len(), 0);
+    }
+
+    #[test]
+    fn tiny_spacing_is_limited() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="20mm" height="20mm" viewBox="0 0 20 20">
+            <rect x="5" y="5" width="10" height="10" fill="blue"/>
+        </svg>"#;
+        let tree =
+            parse_svg(&PathBuf::from("square.svg"), svg.as_bytes()).expect("SVG should parse");
+        let passes = vec![ToolPass::new(
+            "Engrave".to_string(),
+            0,
+            0,
+            255,
+            100,
+            100,
+            false,
+            400,
+        )];
+        let options = HatchOptions {
+            spacing_mm: 1.0e-9,
+            angle_degrees: 0.0,
+        };
+
+        let hatched = hatch_fills(&tree, &passes, &options);
+        let lines = hatched
+            .get(&Colour([0, 0, 255]))
+            .expect("Square should be hatched");
+        assert_eq!(lines.len(), (10.0 / MIN_HATCH_SPACING_MM).round() as usize);
+    }
+}
@@ -1,61 +1,341 @@
-use std::collections::HashMap;
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    ops::RangeInclusive,
+};
 
 use crate::{
-    paths::{mm_to_hpgl_units, PathColour, ResolvedPath},
-    ToolPass,
+    bed::PlacePointError,
+    paths::{Bounds, PathColour, ResolvedPath, ResolvedPoint, ResolvedSegment},
+    PointInMillimeters, PrintBed, ToolPass,
 };
 
+/// The most tool passes that the machine supports, one for each pen.
+pub const MAX_TOOL_PASSES: usize = 16;
+
+/// The number of tool passes that can be sent to the machine.
+pub const TOOL_PASS_COUNT: RangeInclusive<usize> = 1..=MAX_TOOL_PASSES;
+
+/// Errors that can occur when generating HPGL.
+#[derive(Debug)]
+pub enum HpglError {
+    /// There are no tool passes, or more tool passes than the machine has pens.
+    WrongPassCount {
+        /// The number of tool passes that are supported.
+        expected: RangeInclusive<usize>,
+        /// The number of tool passes that were given.
+        got: usize,
+    },
+    /// None of the tool passes are enabled, so there is nothing to cut.
+    NoPassesEnabled,
+    /// The top-left of the bed, where the toolhead starts and finishes, is too far from the device's origin
+    /// to be sent in HPGL.
+    OriginOffBed(PlacePointError),
+    /// The HPGL could not be written out.
+    FailedToWrite(io::Error),
+}
+
+impl From<io::Error> for HpglError {
+    fn from(err: io::Error) -> Self {
+        Self::FailedToWrite(err)
+    }
+}
+
+/// How the coordinates of the toolhead's moves are written.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum HpglCoordinateMode {
+    /// Every point is written as its position on the device (`PA`).
+    #[default]
+    Absolute,
+    /// The start of each path is written as its position on the device, and each point after that
+    /// as how far it is from the point before it (`PR`), which is shorter for long polylines.
+    Relative,
+}
+
+/// Options that change how HPGL is written, without changing what is traced.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct HpglOptions {
+    /// The most coordinate pairs to put in a single `PD` command, to stay within
+    /// the controller's line length limits.
+    pub max_coordinates_per_command: usize,
+    /// If provided, a Velocity Select (`VS`) command is sent at each pen change, for controllers that
+    /// don't take the speed from the PCL pen table. This is the velocity, in cm/s, for each unit of
+    /// [`ToolPass::speed`], e.g. `0.1` makes a speed of 1000 into `VS100`.
+    #[serde(default)]
+    pub velocity_per_speed: Option<f32>,
+    /// How the coordinates of the toolhead's moves are written.
+    #[serde(default)]
+    pub coordinate_mode: HpglCoordinateMode,
+    /// Whether to lift the pen and pause with Not Ready (`NR`) before each pass after the first, so that
+    /// the operator can e.g. turn the material over, then press the machine's button to carry on.
+    /// Only machines whose [`crate::DeviceProfile::supports_pause`] pause, it is ignored for the others.
+    #[serde(default)]
+    pub pause_between_passes: bool,
+}
+
+impl Default for HpglOptions {
+    fn default() -> Self {
+        Self {
+            max_coordinates_per_command: 64,
+            velocity_per_speed: None,
+            coordinate_mode: HpglCoordinateMode::Absolute,
+            pause_between_passes: false,
+        }
+    }
+}
+
+/// The HPGL for a single tool pass, along with the moves that it makes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassHpgl {
+    /// The index of the tool pass, and so the pen, that this is for.
+    pub pass_index: usize,
+    /// The colour of the paths cut by the pass.
+    pub colour: PathColour,
+    /// The HPGL for the pass, starting with the pen change.
+    pub hpgl: String,
+    /// Where the toolhead moves to with the tool off, before cutting each path, in HPGL/2 units.
+    pub travel: Vec<ResolvedPoint>,
+    /// The paths that are cut, in the order they are cut.
+    pub cuts: Vec<ResolvedPath>,
+}
+
 /// Generates the HPGL for a design.
+/// Every enabled tool pass cuts the paths matching its colour, in pass order, so a colour
+/// used by more than one enabled pass is cut once by each of them. Disabled passes cut nothing.
 ///
 /// # Aguments
 /// * `resolved_paths`: Paths resolved by [`super::paths::resolve_paths`].
 /// * `tool_passes`: Tool passes to perform.
+/// * `bed`: The bed that the paths were placed on.
+/// * `options`: How to write the HPGL.
 ///
 /// # Returns
-/// HPGL as a string.
+/// HPGL as a string, otherwise a [`HpglError`].
 pub fn generate_hpgl(
-    resolved_paths: &HashMap<PathColour, Vec<ResolvedPath>>,
+    resolved_paths: &BTreeMap<PathColour, Vec<ResolvedPath>>,
     tool_passes: &Vec<ToolPass>,
-) -> String {
-    let Some((first_pen, _)) = tool_passes
-        .iter()
-        .enumerate()
-        .find(|(_, pass)| *pass.enabled())
-    else {
-        return "".to_string();
-    };
+    bed: &PrintBed,
+    options: &HpglOptions,
+) -> Result<String, HpglError> {
+    let mut hpgl = vec![];
+    generate_hpgl_to(&mut hpgl, resolved_paths, tool_passes, bed, options)?;
+    Ok(String::from_utf8(hpgl).expect("HPGL is only ever written from strings"))
+}
+
+/// Generates the HPGL to move the toolhead around the edge of a design without cutting it,
+/// so that where the design will be cut can be checked before any material is used.
+///
+/// # Arguments
+/// * `bounds`: The bounds of the design, on the bed.
+/// * `first_pen`: The index of the first enabled tool pass, whose pen is selected as it would be for the job.
+/// * `bed`: The bed that the design will be cut on.
+/// * `repeats`: How many times to go around the edge of the design, at least once.
+///
+/// # Returns
+/// HPGL that only ever moves with the pen up, otherwise why a corner of the bounds or the origin
+/// couldn't be placed on the bed.
+pub fn generate_frame_hpgl(
+    bounds: &Bounds,
+    first_pen: usize,
+    bed: &PrintBed,
+    repeats: usize,
+) -> Result<String, PlacePointError> {
+    let corners = bounds
+        .corners()
+        .into_iter()
+        .map(|corner| bed.place_point(corner))
+        .collect::<Result<Vec<_>, _>>()?;
+    let origin = park_position(bed)?;
+
+    // Start as a job would, then move to the first corner.
+    let ResolvedPoint { x, y } = corners[0];
+    let mut hpgl = format!("{}PU{x},{y};", start_job(first_pen, origin));
+    for _ in 0..repeats.max(1) {
+        // Go around each corner, back to the first.
+        let coordinates = corners[1..]
+            .iter()
+            .chain(&corners[..1])
+            .map(|ResolvedPoint { x, y }| format!("{x},{y}"))
+            .collect::<Vec<_>>()
+            .join(",");
+        hpgl.push_str(&format!("PU{coordinates};"));
+    }
+    hpgl.push_str(&finish_job(first_pen, origin));
 
-    // In, Default Coordinate System, Pen Up, Select Pen 1, Reset scaling points to default positions.
-    // TODO: Select first pen, not always Pen 1.
-    let var_name = format!(
+    Ok(hpgl)
+}
+
+/// Finds where the toolhead parks before and after a job, the origin of the bed.
+/// The toolhead parks here rather than cutting, so it may be in the margins or a keep-out zone.
+///
+/// # Arguments
+/// * `bed`: The bed that the job is run on.
+///
+/// # Returns
+/// The origin in plotter units, or [`PlacePointError::HpglOverflow`] if it is too far from the device's origin.
+fn park_position(bed: &PrintBed) -> Result<ResolvedPoint, PlacePointError> {
+    bed.to_plotter_units(PointInMillimeters { x: 0.0, y: 0.0 })
+}
+
+/// Generates the HPGL that sets up the device and parks the toolhead at the start of a job.
+///
+/// # Arguments
+/// * `first_pen`: The index of the first enabled tool pass.
+/// * `origin`: Where the toolhead parks, from [`park_position`].
+///
+/// # Returns
+/// The HPGL.
+fn start_job(first_pen: usize, origin: ResolvedPoint) -> String {
+    // In, Default Coordinate System, Pen Up, Select the first enabled pen, Reset scaling points to default positions.
+    format!(
         "IN;SC;PU;SP{};LT;PU{},{};",
         first_pen + 1,
-        mm_to_hpgl_units(0.0, true),
-        mm_to_hpgl_units(0.0, false)
-    );
-    let mut hpgl = var_name;
+        origin.x,
+        origin.y
+    )
+}
 
-    'laser_passes_iter: for (index, pass) in tool_passes.iter().enumerate() {
-        if let Some(paths) = resolved_paths.get(&PathColour(pass.colour().clone())) {
-            if paths.is_empty() {
-                continue 'laser_passes_iter;
-            }
+/// Generates the HPGL that parks the toolhead and ends a job.
+///
+/// # Arguments
+/// * `first_pen`: The index of the first enabled tool pass.
+/// * `origin`: Where the toolhead parks, from [`park_position`].
+///
+/// # Returns
+/// The HPGL.
+fn finish_job(first_pen: usize, origin: ResolvedPoint) -> String {
+    // Pen Up, park at the origin and select the first enabled pen again, ready for the next job.
+    format!(
+        "PU{},{};SP{};EC0;EC1;OE;",
+        origin.x,
+        origin.y,
+        first_pen + 1
+    )
+}
 
-            append_hpgl(&mut hpgl, &pen_change(index));
-            for path in paths {
-                append_hpgl(&mut hpgl, &trace_path(path));
-            }
+/// Generates the HPGL for a design, writing it out a path at a time so that
+/// the whole job never has to be held in memory.
+/// Nothing is written if the tool passes can't be cut.
+///
+/// # Aguments
+/// * `w`: Where to write the HPGL.
+/// * `resolved_paths`: Paths resolved by [`super::paths::resolve_paths`].
+/// * `tool_passes`: Tool passes to perform.
+/// * `bed`: The bed that the paths were placed on.
+/// * `options`: How to write the HPGL.
+///
+/// # Returns
+/// `Ok(())` if all of the HPGL was written, otherwise a [`HpglError`].
+pub fn generate_hpgl_to(
+    w: &mut impl Write,
+    resolved_paths: &BTreeMap<PathColour, Vec<ResolvedPath>>,
+    tool_passes: &Vec<ToolPass>,
+    bed: &PrintBed,
+    options: &HpglOptions,
+) -> Result<(), HpglError> {
+    let first_pen = first_enabled_pass(tool_passes)?;
+    let origin = park_position(bed).map_err(HpglError::OriginOffBed)?;
+
+    w.write_all(start_job(first_pen, origin).as_bytes())?;
+
+    for (position, (index, _, paths)) in passes_to_cut(resolved_paths, tool_passes).enumerate() {
+        w.write_all(pen_change(index, &tool_passes[index], position == 0, options).as_bytes())?;
+        for path in paths {
+            w.write_all(trace_path(path, options).as_bytes())?;
         }
     }
 
-    hpgl.push_str(&format!(
-        "PU{},{};SP{};EC0;EC1;OE;",
-        first_pen + 1,
-        mm_to_hpgl_units(0.0, true),
-        mm_to_hpgl_units(0.0, false)
-    ));
+    w.write_all(finish_job(first_pen, origin).as_bytes())?;
 
-    hpgl
+    Ok(())
+}
+
+/// Generates the HPGL for each tool pass that has something to cut, without the
+/// set up and tear down that [`generate_hpgl`] wraps around them.
+///
+/// # Aguments
+/// * `resolved_paths`: Paths resolved by [`super::paths::resolve_paths`].
+/// * `tool_passes`: Tool passes to perform.
+/// * `options`: How to write the HPGL.
+///
+/// # Returns
+/// The HPGL and moves for each pass, in the order they are run, otherwise a [`HpglError`].
+pub fn generate_hpgl_segments(
+    resolved_paths: &BTreeMap<PathColour, Vec<ResolvedPath>>,
+    tool_passes: &Vec<ToolPass>,
+    options: &HpglOptions,
+) -> Result<Vec<PassHpgl>, HpglError> {
+    first_enabled_pass(tool_passes)?;
+
+    let segments = passes_to_cut(resolved_paths, tool_passes)
+        .enumerate()
+        .map(|(position, (index, colour, paths))| {
+            let mut hpgl = pen_change(index, &tool_passes[index], position == 0, options);
+            for path in paths {
+                append_hpgl(&mut hpgl, &trace_path(path, options));
+            }
+
+            PassHpgl {
+                pass_index: index,
+                colour,
+                hpgl,
+                travel: paths
+                    .iter()
+                    .filter_map(|path| path.first().map(ResolvedSegment::end))
+                    .collect(),
+                cuts: paths.clone(),
+            }
+        })
+        .collect();
+
+    Ok(segments)
+}
+
+/// Finds the paths cut by each enabled tool pass that has something to cut.
+///
+/// # Arguments
+/// * `resolved_paths`: Paths resolved by [`super::paths::resolve_paths`].
+/// * `tool_passes`: Tool passes to perform.
+///
+/// # Returns
+/// The index of each pass, the colour it cuts, and the paths of that colour, in the order the passes are run.
+fn passes_to_cut<'a>(
+    resolved_paths: &'a BTreeMap<PathColour, Vec<ResolvedPath>>,
+    tool_passes: &'a [ToolPass],
+) -> impl Iterator<Item = (usize, PathColour, &'a Vec<ResolvedPath>)> + 'a {
+    tool_passes
+        .iter()
+        .enumerate()
+        .filter(|(_, pass)| *pass.enabled())
+        .filter_map(|(index, pass)| {
+            let colour = *pass.colour();
+            let paths = resolved_paths.get(&colour)?;
+            if paths.is_empty() {
+                return None;
+            }
+            Some((index, colour, paths))
+        })
+}
+
+/// Checks that there are a supported number of tool passes, and that at least one of them is enabled.
+///
+/// # Arguments
+/// * `tool_passes`: Tool passes to perform.
+///
+/// # Returns
+/// The index of the first enabled pass, otherwise a [`HpglError`].
+pub(crate) fn first_enabled_pass(tool_passes: &[ToolPass]) -> Result<usize, HpglError> {
+    if !TOOL_PASS_COUNT.contains(&tool_passes.len()) {
+        return Err(HpglError::WrongPassCount {
+            expected: TOOL_PASS_COUNT,
+            got: tool_passes.len(),
+        });
+    }
+
+    tool_passes
+        .iter()
+        .position(|pass| *pass.enabled())
+        .ok_or(HpglError::NoPassesEnabled)
 }
 
 /// Appends some HPGL to the end of an existing HPGL string.
@@ -71,36 +351,693 @@ fn append_hpgl(hpgl: &mut String, to_append: &str) {
 ///
 /// # Arguments
 /// * `pen_index`: The pen index (from 0) to change to.
+/// * `pass`: The tool pass that the pen is for.
+/// * `first`: Whether this is the first pass of the job.
+/// * `options`: How to write the HPGL.
 ///
 /// # Returns
 /// The HPGL for the pen change.
-fn pen_change(pen_index: usize) -> String {
+fn pen_change(pen_index: usize, pass: &ToolPass, first: bool, options: &HpglOptions) -> String {
+    let mut hpgl = String::new();
+
+    // Pen Up, Not Ready, so that nothing is cut while the operator gets the next pass ready.
+    if options.pause_between_passes && !first {
+        hpgl.push_str("PU;NR;");
+    }
+
     // Select Pen X.
-    format!("SP{};", pen_index + 1)
+    hpgl.push_str(&format!("SP{};", pen_index + 1));
+
+    // Velocity Select, for controllers that ignore the speed in the pen table.
+    if let Some(velocity_per_speed) = options.velocity_per_speed {
+        let velocity = *pass.speed() as f32 * velocity_per_speed;
+        hpgl.push_str(&format!("VS{velocity:.2};"));
+    }
+
+    hpgl
 }
 
-/// Creates a HPGL string that traces through all of the points in a path.
+/// Creates a HPGL string that traces through all of the segments in a path.
+/// In [`HpglCoordinateMode::Relative`], the path starts and ends in absolute mode, so that
+/// the moves around it are unaffected.
 ///
 /// # Arguments
 /// * `path`: The path to trace.
+/// * `options`: How to write the HPGL.
 ///
 /// # Returns
 /// The HPGL for the traced path.
-fn trace_path(path: &ResolvedPath) -> String {
+fn trace_path(path: &ResolvedPath, options: &HpglOptions) -> String {
     let mut hpgl = String::new();
 
-    // Pen Down.
-    if let Some(point) = path.first() {
-        let x = point.x;
-        let y = point.y;
-        hpgl.push_str(&format!("PU{x},{y};"))
+    // Pen Up, move to the start of the path.
+    let Some(start) = path.first() else {
+        return hpgl;
+    };
+    let ResolvedPoint { x, y } = start.end();
+    hpgl.push_str(&format!("PU{x},{y};"));
+
+    let relative = options.coordinate_mode == HpglCoordinateMode::Relative;
+    if relative {
+        // Plot Relative.
+        hpgl.push_str("PR;");
+    }
+
+    // Where the toolhead is known to be, for relative moves to be measured from.
+    // Points are whole plotter units so the moves between them are exact and can't drift, but the device
+    // works out where arcs end for itself, so the position is re-anchored after each arc.
+    let mut anchor = Some(start.end());
+
+    // Consecutive straight lines share a single Pen Down command. The pen goes down at the start of the path,
+    // so that a path with only one point is still cut as a dot.
+    let mut line_points: Vec<ResolvedPoint> = vec![start.end()];
+    for segment in &path[1..] {
+        match segment {
+            ResolvedSegment::Line(point) => line_points.push(*point),
+            // Arc Absolute, with the current pen state. This is absolute whether plotting is absolute or relative.
+            ResolvedSegment::Arc {
+                centre: ResolvedPoint { x, y },
+                sweep_degrees,
+                ..
+            } => {
+                // The pen has to be down for the arc to be cut.
+                if line_points.is_empty() {
+                    hpgl.push_str("PD;");
+                }
+                hpgl.push_str(&lines_to(&line_points, anchor, relative, options));
+                line_points.clear();
+                hpgl.push_str(&format!("AA{x},{y},{sweep_degrees:.2};"));
+                anchor = None;
+            }
+        }
+    }
+    hpgl.push_str(&lines_to(&line_points, anchor, relative, options));
+
+    if relative {
+        // Plot Absolute, for the moves after the path.
+        hpgl.push_str("PA;");
+    }
+
+    hpgl
+}
+
+/// Creates Pen Down commands that move through a series of points in straight lines, in the coordinate mode
+/// that the path is being plotted in.
+///
+/// # Arguments
+/// * `points`: The points to move through.
+/// * `anchor`: Where the toolhead is before the first point, if it is known exactly.
+/// * `relative`: Whether the path is being plotted relatively. If the toolhead's position isn't known exactly,
+/// the first point is plotted absolutely to re-anchor it.
+/// * `options`: How to write the HPGL.
+///
+/// # Returns
+/// The HPGL for the lines.
+fn lines_to(
+    points: &[ResolvedPoint],
+    anchor: Option<ResolvedPoint>,
+    relative: bool,
+    options: &HpglOptions,
+) -> String {
+    let Some((first, rest)) = points.split_first().filter(|_| relative) else {
+        return pen_down(points, None, options);
+    };
+
+    match anchor {
+        Some(anchor) => pen_down(points, Some(anchor), options),
+        None => format!(
+            "PA;{}PR;{}",
+            pen_down(&[*first], None, options),
+            pen_down(rest, Some(*first), options)
+        ),
     }
+}
 
-    for point in path {
-        let x = point.x;
-        let y = point.y;
-        hpgl.push_str(&format!("PD{x},{y};"));
+/// Creates Pen Down commands that move through a series of points in straight lines.
+///
+/// # Arguments
+/// * `points`: The points to move through.
+/// * `relative_to`: If provided, each point is written relative to the point before it, starting from this point.
+/// Otherwise points are written as they are.
+/// * `options`: How to write the HPGL.
+///
+/// # Returns
+/// The HPGL for the lines.
+fn pen_down(
+    points: &[ResolvedPoint],
+    relative_to: Option<ResolvedPoint>,
+    options: &HpglOptions,
+) -> String {
+    let mut previous = relative_to;
+    let coordinates: Vec<String> = points
+        .iter()
+        .map(|point| {
+            let (x, y) = match previous {
+                Some(from) => (point.x - from.x, point.y - from.y),
+                None => (point.x, point.y),
+            };
+            if relative_to.is_some() {
+                previous = Some(*point);
+            }
+            format!("{x},{y}")
+        })
+        .collect();
+
+    let mut hpgl = String::new();
+    for chunk in coordinates.chunks(options.max_coordinates_per_command.max(1)) {
+        hpgl.push_str(&format!("PD{};", chunk.join(",")));
     }
 
     hpgl
 }
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use std::{
+        collections::BTreeMap,
+        io::{self, Write},
+    };
+
+    use super::{
+        generate_frame_hpgl, generate_hpgl, generate_hpgl_segments, generate_hpgl_to, trace_path,
+        HpglCoordinateMode, HpglError, HpglOptions, MAX_TOOL_PASSES, TOOL_PASS_COUNT,
+    };
+    use crate::{
+        bed::{BedAxis, BedMargins, KeepOutZone, PlacePointError},
+        default_passes::default_passes,
+        paths::{
+            convert_points_to_plotter_units, trace_paths, Bounds, ResolvedPoint, ResolvedSegment,
+            MM_PER_PLOTTER_UNIT,
+        },
+        svg::{get_paths_grouped_by_colour, parse_svg},
+        Colour, PointInMillimeters, PrintBed, ToolPass,
+    };
+
+    #[test]
+    fn circles_are_traced_as_arcs() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="40mm" height="40mm" viewBox="0 0 40 40">
+            <circle cx="20" cy="20" r="10" fill="none" stroke="black"/>
+        </svg>"#;
+        let tree =
+            parse_svg(&PathBuf::from("circle.svg"), svg.as_bytes()).expect("SVG should parse");
+        let paths = get_paths_grouped_by_colour(&tree).expect("Paths should group");
+        let passes = vec![ToolPass::new(
+            "Cut".to_string(),
+            0,
+            0,
+            0,
+            100,
+            100,
+            false,
+            400,
+        )];
+        let traced = trace_paths(&paths, &passes);
+        let resolved = convert_points_to_plotter_units(&traced, (0.0, 0.0), &PrintBed::GCC_SPIRIT)
+            .expect("Paths should be on the bed");
+
+        let hpgl = generate_hpgl(
+            &resolved,
+            &passes,
+            &PrintBed::GCC_SPIRIT,
+            &HpglOptions::default(),
+        )
+        .expect("HPGL should generate");
+        // A single arc, rather than one PD for every mm around the circle.
+        assert_eq!(
+            hpgl,
+            "IN;SC;PU;SP1;LT;PU0,18528;SP1;PU1200,17728;PD1200,17728;AA800,17728,-360.00;PU0,18528;SP1;EC0;EC1;OE;"
+        );
+    }
+
+    #[test]
+    fn lines_share_pen_down_commands() {
+        let path: Vec<ResolvedSegment> = (0..200)
+            .map(|index| {
+                ResolvedSegment::Line(ResolvedPoint {
+                    x: index,
+                    y: index % 7,
+                })
+            })
+            .collect();
+        let one_per_command = trace_path(
+            &path,
+            &HpglOptions {
+                max_coordinates_per_command: 1,
+                ..HpglOptions::default()
+            },
+        );
+        let coalesced = trace_path(&path, &HpglOptions::default());
+
+        assert_eq!(one_per_command.matches("PD").count(), 200);
+        assert_eq!(coalesced.matches("PD").count(), 4);
+        // Every merged command saves a "PD" and swaps a ";" for a ",".
+        assert_eq!(one_per_command.len() - coalesced.len(), 2 * (200 - 4));
+
+        // Both should move through exactly the same coordinates.
+        let coordinates = |hpgl: &str| {
+            hpgl.split([';', ','])
+                .map(|value| value.trim_start_matches(char::is_alphabetic).to_string())
+                .filter(|value| !value.is_empty())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(coordinates(&coalesced), coordinates(&one_per_command));
+    }
+
+    #[test]
+    fn single_points_are_cut_as_dots() {
+        let path = vec![ResolvedSegment::Line(ResolvedPoint { x: 40, y: 30 })];
+        let relative_options = HpglOptions {
+            coordinate_mode: HpglCoordinateMode::Relative,
+            ..HpglOptions::default()
+        };
+
+        assert_eq!(
+            trace_path(&path, &HpglOptions::default()),
+            "PU40,30;PD40,30;"
+        );
+        assert_eq!(trace_path(&path, &relative_options), "PU40,30;PR;PD0,0;PA;");
+    }
+
+    #[test]
+    fn relative_plotting_reaches_the_same_points() {
+        let point = |x, y| ResolvedPoint { x, y };
+        let path = vec![
+            ResolvedSegment::Line(point(100, 100)),
+            ResolvedSegment::Line(point(150, 100)),
+            ResolvedSegment::Line(point(150, 180)),
+            ResolvedSegment::Arc {
+                centre: point(150, 200),
+                sweep_degrees: 180.0,
+                end: point(150, 220),
+            },
+            ResolvedSegment::Line(point(100, 220)),
+            ResolvedSegment::Line(point(90, 215)),
+        ];
+        let relative_options = HpglOptions {
+            coordinate_mode: HpglCoordinateMode::Relative,
+            ..HpglOptions::default()
+        };
+
+        let absolute = trace_path(&path, &HpglOptions::default());
+        let relative = trace_path(&path, &relative_options);
+        assert_eq!(
+            absolute,
+            "PU100,100;PD100,100,150,100,150,180;AA150,200,180.00;PD100,220,90,215;"
+        );
+        assert_eq!(
+            relative,
+            "PU100,100;PR;PD0,0,50,0,0,80;AA150,200,180.00;PA;PD100,220;PR;PD-10,-5;PA;"
+        );
+
+        // Follow the HPGL as the device would, taking arcs to end where the path says they do.
+        let points_reached = |hpgl: &str| {
+            let (mut is_relative, mut at) = (false, (0, 0));
+            let mut reached = vec![];
+            for command in hpgl.split(';').filter(|command| !command.is_empty()) {
+                let (name, parameters) = command.split_at(2);
+                let values: Vec<f32> = parameters
+                    .split(',')
+                    .filter(|value| !value.is_empty())
+                    .map(|value| value.parse().expect("Parameters should be numbers"))
+                    .collect();
+                match name {
+                    "PA" => is_relative = false,
+                    "PR" => is_relative = true,
+                    "AA" => {
+                        at = (150, 220);
+                        reached.push(at);
+                    }
+                    _ => {
+                        for pair in values.chunks(2) {
+                            let (x, y) = (pair[0] as i32, pair[1] as i32);
+                            at = if is_relative {
+                                (at.0 + x, at.1 + y)
+                            } else {
+                                (x, y)
+                            };
+                            reached.push(at);
+                        }
+                    }
+                }
+            }
+            (reached, is_relative)
+        };
+        assert_eq!(points_reached(&relative), points_reached(&absolute));
+    }
+
+    #[test]
+    fn frames_go_around_the_design_with_the_pen_up() {
+        let paths = BTreeMap::from([(
+            Colour([0, 0, 0]),
+            vec![
+                vec![
+                    PointInMillimeters { x: 10.0, y: 20.0 },
+                    PointInMillimeters { x: 30.0, y: 25.0 },
+                ],
+                vec![PointInMillimeters { x: 15.0, y: 40.0 }],
+            ],
+        )]);
+        let bounds = Bounds::of_paths(&paths, (5.0, 0.0)).expect("There are points to bound");
+        assert_eq!(
+            bounds,
+            Bounds {
+                min: PointInMillimeters { x: 15.0, y: 20.0 },
+                max: PointInMillimeters { x: 35.0, y: 40.0 },
+            }
+        );
+
+        let bed = PrintBed::GCC_SPIRIT;
+        let [top_left, top_right, bottom_right, bottom_left] = bounds.corners().map(|corner| {
+            bed.place_point(corner)
+                .expect("Corner should be on the bed")
+        });
+        let lap = format!(
+            "PU{},{},{},{},{},{},{},{};",
+            top_right.x,
+            top_right.y,
+            bottom_right.x,
+            bottom_right.y,
+            bottom_left.x,
+            bottom_left.y,
+            top_left.x,
+            top_left.y
+        );
+        // The frame selects the pen and parks at the origin before and after, as a job does.
+        let start = format!("IN;SC;PU;SP2;LT;PU0,18528;PU{},{};", top_left.x, top_left.y);
+        let end = "PU0,18528;SP2;EC0;EC1;OE;";
+
+        assert_eq!(
+            generate_frame_hpgl(&bounds, 1, &bed, 1),
+            Ok(format!("{start}{lap}{end}"))
+        );
+        assert_eq!(
+            generate_frame_hpgl(&bounds, 1, &bed, 0),
+            Ok(format!("{start}{lap}{end}"))
+        );
+        assert_eq!(
+            generate_frame_hpgl(&bounds, 1, &bed, 3),
+            Ok(format!("{start}{}{end}", lap.repeat(3)))
+        );
+        assert_eq!(
+            generate_frame_hpgl(&bounds, 1, &bed, 1)
+                .expect("Frame should be generated")
+                .contains("PD"),
+            false,
+            "The frame should never be cut"
+        );
+        assert_eq!(Bounds::of_paths(&BTreeMap::new(), (0.0, 0.0)), None);
+    }
+
+    #[test]
+    fn no_enabled_passes_is_an_error() {
+        let mut passes = default_passes();
+        for pass in &mut passes {
+            pass.set_enabled(false);
+        }
+
+        let err = generate_hpgl(
+            &BTreeMap::new(),
+            &passes,
+            &PrintBed::GCC_SPIRIT,
+            &HpglOptions::default(),
+        )
+        .expect_err("Disabled passes shouldn't generate");
+        assert_eq!(
+            matches!(err, HpglError::NoPassesEnabled),
+            true,
+            "No enabled passes should be an error, got {err:?}"
+        );
+    }
+
+    #[test]
+    fn too_many_passes_is_an_error() {
+        let passes = vec![default_passes()[0].clone(); MAX_TOOL_PASSES + 1];
+
+        let pass_count = |passes: &Vec<ToolPass>| match generate_hpgl(
+            &BTreeMap::new(),
+            passes,
+            &PrintBed::GCC_SPIRIT,
+            &HpglOptions::default(),
+        ) {
+            Err(HpglError::WrongPassCount { expected, got }) => Some((expected, got)),
+            _ => None,
+        };
+        assert_eq!(
+            pass_count(&passes),
+            Some((TOOL_PASS_COUNT, MAX_TOOL_PASSES + 1))
+        );
+        assert_eq!(pass_count(&vec![]), Some((TOOL_PASS_COUNT, 0)));
+    }
+
+    #[test]
+    fn unreachable_origin_is_an_error() {
+        let bed = PrintBed::new(30_000_000.0..=30_000_100.0, 0.0..=50.0, MM_PER_PLOTTER_UNIT);
+
+        let err = generate_hpgl(
+            &BTreeMap::new(),
+            &default_passes(),
+            &bed,
+            &HpglOptions::default(),
+        )
+        .expect_err("Origin too far from the device's origin shouldn't generate");
+        assert_eq!(
+            matches!(
+                err,
+                HpglError::OriginOffBed(PlacePointError::HpglOverflow {
+                    axis: BedAxis::X,
+                    ..
+                })
+            ),
+            true,
+            "Origin too far away should be an error, got {err:?}"
+        );
+    }
+
+    #[test]
+    fn jobs_park_in_the_margins() {
+        let bed = PrintBed::GCC_SPIRIT
+            .with_margins(BedMargins {
+                top: 5.0,
+                left: 5.0,
+                ..BedMargins::NONE
+            })
+            .and_then(|bed| {
+                bed.with_keep_out_zone(KeepOutZone {
+                    name: "Clamp".to_string(),
+                    x_axis: 0.0..=20.0,
+                    y_axis: 0.0..=20.0,
+                })
+            })
+            .expect("Bed should be valid");
+        let square = [(30.0, 30.0), (40.0, 30.0), (40.0, 40.0), (30.0, 40.0)]
+            .into_iter()
+            .map(|(x, y)| PointInMillimeters { x, y })
+            .collect();
+        let resolved = convert_points_to_plotter_units(
+            &BTreeMap::from([(Colour([0, 0, 0]), vec![square])]),
+            (0.0, 0.0),
+            &bed,
+        )
+        .expect("Square should be on the bed");
+
+        let hpgl = generate_hpgl(&resolved, &default_passes(), &bed, &HpglOptions::default())
+            .expect("HPGL should generate");
+        assert_eq!(&hpgl[..26], "IN;SC;PU;SP1;LT;PU0,18528;");
+        assert_eq!(&hpgl[hpgl.len() - 25..], "PU0,18528;SP1;EC0;EC1;OE;");
+    }
+
+    #[test]
+    fn write_errors_are_kept() {
+        /// A writer that is always out of space.
+        struct FullDisk;
+
+        impl Write for FullDisk {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                Err(io::Error::new(
+                    io::ErrorKind::StorageFull,
+                    "the disk is full",
+                ))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let err = generate_hpgl_to(
+            &mut FullDisk,
+            &BTreeMap::new(),
+            &default_passes(),
+            &PrintBed::GCC_SPIRIT,
+            &HpglOptions::default(),
+        )
+        .expect_err("Writing to a full disk should fail");
+        let HpglError::FailedToWrite(err) = err else {
+            panic!("Failing to write should be a write error, got {err:?}");
+        };
+        assert_eq!(
+            (err.kind(), err.to_string()),
+            (io::ErrorKind::StorageFull, "the disk is full".to_string())
+        );
+    }
+
+    #[test]
+    fn segments_make_up_the_whole_hpgl() {
+        let line = vec![
+            ResolvedSegment::Line(ResolvedPoint { x: 5, y: 5 }),
+            ResolvedSegment::Line(ResolvedPoint { x: 10, y: 0 }),
+        ];
+        let resolved = BTreeMap::from([
+            (Colour([0, 0, 0]), vec![line.clone(), line.clone()]),
+            (Colour([255, 0, 0]), vec![line.clone()]),
+        ]);
+        let passes = vec![
+            ToolPass::new("Engrave".to_string(), 255, 0, 0, 10, 100, false, 400),
+            ToolPass::new("Unused".to_string(), 0, 255, 0, 50, 100, false, 400),
+            ToolPass::new("Cut".to_string(), 0, 0, 0, 100, 10, false, 400),
+        ];
+
+        let segments = generate_hpgl_segments(&resolved, &passes, &HpglOptions::default())
+            .expect("HPGL should generate");
+        assert_eq!(
+            segments
+                .iter()
+                .map(|segment| (segment.pass_index, segment.colour))
+                .collect::<Vec<_>>(),
+            vec![(0, Colour([255, 0, 0])), (2, Colour([0, 0, 0]))]
+        );
+        assert_eq!(segments[1].travel, vec![ResolvedPoint { x: 5, y: 5 }; 2]);
+        assert_eq!(segments[1].cuts, vec![line.clone(), line]);
+
+        let hpgl = generate_hpgl(
+            &resolved,
+            &passes,
+            &PrintBed::GCC_SPIRIT,
+            &HpglOptions::default(),
+        )
+        .expect("HPGL should generate");
+        let joined: String = segments
+            .iter()
+            .map(|segment| segment.hpgl.as_str())
+            .collect();
+        assert_eq!(
+            hpgl,
+            format!("IN;SC;PU;SP1;LT;PU0,18528;{joined}PU0,18528;SP1;EC0;EC1;OE;")
+        );
+    }
+
+    #[test]
+    fn velocity_is_only_selected_when_enabled() {
+        let line = vec![
+            ResolvedSegment::Line(ResolvedPoint { x: 0, y: 0 }),
+            ResolvedSegment::Line(ResolvedPoint { x: 10, y: 0 }),
+        ];
+        let resolved = BTreeMap::from([
+            (Colour([0, 0, 0]), vec![line.clone()]),
+            (Colour([255, 0, 0]), vec![line]),
+        ]);
+        let passes = vec![
+            ToolPass::new("Engrave".to_string(), 255, 0, 0, 10, 1000, false, 400),
+            ToolPass::new("Cut".to_string(), 0, 0, 0, 100, 25, false, 400),
+        ];
+
+        let hpgl = generate_hpgl(
+            &resolved,
+            &passes,
+            &PrintBed::GCC_SPIRIT,
+            &HpglOptions::default(),
+        )
+        .expect("HPGL should generate");
+        assert_eq!(
+            hpgl,
+            "IN;SC;PU;SP1;LT;PU0,18528;SP1;PU0,0;PD0,0,10,0;SP2;PU0,0;PD0,0,10,0;PU0,18528;SP1;EC0;EC1;OE;"
+        );
+
+        let options = HpglOptions {
+            velocity_per_speed: Some(0.1),
+            ..HpglOptions::default()
+        };
+        let hpgl = generate_hpgl(&resolved, &passes, &PrintBed::GCC_SPIRIT, &options)
+            .expect("HPGL should generate");
+        assert_eq!(
+            hpgl,
+            "IN;SC;PU;SP1;LT;PU0,18528;SP1;VS100.00;PU0,0;PD0,0,10,0;SP2;VS2.50;PU0,0;PD0,0,10,0;PU0,18528;SP1;EC0;EC1;OE;"
+        );
+    }
+
+    #[test]
+    fn passes_after_the_first_are_paused_before() {
+        let line = vec![
+            ResolvedSegment::Line(ResolvedPoint { x: 0, y: 0 }),
+            ResolvedSegment::Line(ResolvedPoint { x: 10, y: 0 }),
+        ];
+        let resolved = BTreeMap::from([
+            (Colour([0, 0, 0]), vec![line.clone()]),
+            (Colour([255, 0, 0]), vec![line.clone()]),
+            (Colour([0, 0, 255]), vec![line]),
+        ]);
+        let passes = vec![
+            ToolPass::new("Engrave".to_string(), 255, 0, 0, 10, 1000, false, 400),
+            ToolPass::new("Score".to_string(), 0, 0, 255, 50, 100, false, 400),
+            ToolPass::new("Cut".to_string(), 0, 0, 0, 100, 25, false, 400),
+        ];
+
+        let options = HpglOptions {
+            pause_between_passes: true,
+            ..HpglOptions::default()
+        };
+        let hpgl = generate_hpgl(&resolved, &passes, &PrintBed::GCC_SPIRIT, &options)
+            .expect("HPGL should generate");
+        assert_eq!(
+            hpgl,
+            "IN;SC;PU;SP1;LT;PU0,18528;SP1;PU0,0;PD0,0,10,0;PU;NR;SP2;PU0,0;PD0,0,10,0;PU;NR;SP3;PU0,0;PD0,0,10,0;PU0,18528;SP1;EC0;EC1;OE;"
+        );
+    }
+
+    #[test]
+    fn duplicate_colours_are_cut_by_each_enabled_pass() {
+        let line = vec![
+            ResolvedSegment::Line(ResolvedPoint { x: 0, y: 0 }),
+            ResolvedSegment::Line(ResolvedPoint { x: 10, y: 0 }),
+        ];
+        let resolved = BTreeMap::from([(Colour([0, 0, 0]), vec![line])]);
+        let mut passes = vec![
+            ToolPass::new("Engrave".to_string(), 0, 0, 0, 10, 100, false, 400),
+            ToolPass::new("Other".to_string(), 255, 0, 0, 50, 100, false, 400),
+            ToolPass::new("Cut".to_string(), 0, 0, 0, 100, 10, false, 400),
+        ];
+
+        let hpgl = generate_hpgl(
+            &resolved,
+            &passes,
+            &PrintBed::GCC_SPIRIT,
+            &HpglOptions::default(),
+        )
+        .expect("HPGL should generate");
+        assert_eq!(
+            hpgl,
+            "IN;SC;PU;SP1;LT;PU0,18528;SP1;PU0,0;PD0,0,10,0;SP3;PU0,0;PD0,0,10,0;PU0,18528;SP1;EC0;EC1;OE;"
+        );
+
+        // Only the enabled pass cuts the colour.
+        for disabled in [0, 2] {
+            passes.iter_mut().for_each(|pass| pass.set_enabled(true));
+            passes[disabled].set_enabled(false);
+            let hpgl = generate_hpgl(
+                &resolved,
+                &passes,
+                &PrintBed::GCC_SPIRIT,
+                &HpglOptions::default(),
+            )
+            .expect("HPGL should generate");
+            assert_eq!(hpgl.matches("PD0,0,10,0;").count(), 1);
+            assert_eq!(
+                hpgl.contains(&format!("SP{};PU0,0;", disabled + 1)),
+                false,
+                "Disabled pass should not cut"
+            );
+        }
+    }
+}
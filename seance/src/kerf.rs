@@ -0,0 +1,328 @@
+//! `kerf`
+//!
+//! Compensates for the width of material that the tool removes, so that parts come out the size they were drawn.
+//!
+//! Paths are offset once they have been traced into points, rather than before they are flattened, as the offset
+//! of a Bézier curve isn't a Bézier curve. The traced points are close enough together that offsetting them is
+//! as accurate as the tracing itself.
+
+use std::{
+    collections::BTreeMap,
+    hash::{Hash, Hasher},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    paths::{PathColour, PathInMM, PointInMillimeters},
+    ToolPass,
+};
+
+/// How far a corner may move, as a multiple of the offset, before it is cut short.
+/// Without a limit the corners of sharp points would spike far out of the part.
+const MITER_LIMIT: f32 = 4.0;
+
+/// The width of material removed by the tool, in mm.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Kerf(pub f32);
+
+impl Kerf {
+    /// Checks whether there is no kerf to compensate for.
+    ///
+    /// # Returns
+    /// true if paths should be left as they are.
+    pub fn is_none(&self) -> bool {
+        self.0 <= 0.0
+    }
+}
+
+impl Hash for Kerf {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+/// Offsets the closed paths of each colour by half the kerf of the tool pass that cuts them.
+/// Parts grow so that the cut runs outside of them, and holes in parts shrink so that the cut runs inside of them.
+/// Open paths are left as they are, as they have no inside.
+///
+/// # Arguments
+/// * `paths_in_mm`: The traced paths, grouped by colour, to offset in place.
+/// * `tool_passes`: The tool passes, the first enabled pass for each colour sets its kerf.
+pub(crate) fn compensate_for_kerf(
+    paths_in_mm: &mut BTreeMap<PathColour, Vec<PathInMM>>,
+    tool_passes: &[ToolPass],
+) {
+    for (colour, paths) in paths_in_mm.iter_mut() {
+        let Some(pass) = tool_passes
+            .iter()
+            .find(|pass| *pass.enabled() && pass.colour() == colour)
+        else {
+            continue;
+        };
+        if pass.kerf_mm() > 0.0 {
+            offset_closed_paths(paths, pass.kerf_mm() / 2.0);
+        }
+    }
+}
+
+/// Offsets each closed path away from the material it cuts out.
+/// A path that is inside an odd number of the other paths is a hole, and is offset inwards,
+/// otherwise the path is the outside of a part and is offset outwards.
+///
+/// # Arguments
+/// * `paths`: The paths to offset in place.
+/// * `distance`: How far to move each closed path, in mm.
+fn offset_closed_paths(paths: &mut [PathInMM], distance: f32) {
+    let rings: Vec<Option<Vec<PointInMillimeters>>> = paths.iter().map(ring).collect();
+
+    for (index, path) in paths.iter_mut().enumerate() {
+        let Some(points) = &rings[index] else {
+            continue;
+        };
+
+        let depth = rings
+            .iter()
+            .enumerate()
+            .filter_map(|(other_index, other)| other.as_ref().filter(|_| other_index != index))
+            .filter(|other| contains(other, points[0]))
+            .count();
+        let distance = if depth % 2 == 0 { distance } else { -distance };
+
+        *path = offset_ring(points, distance);
+    }
+}
+
+/// Gets the distinct points around a closed path.
+/// Only paths that end exactly where they start are closed, which traced paths do when their shape was closed.
+/// A path whose ends are only close together, e.g. a part held in by a tab, is left open.
+///
+/// # Arguments
+/// * `path`: The path.
+///
+/// # Returns
+/// The points around the path, without repeating the first point at the end,
+/// or [`None`] if the path isn't closed.
+fn ring(path: &PathInMM) -> Option<Vec<PointInMillimeters>> {
+    let mut points: Vec<PointInMillimeters> = vec![];
+    for point in path {
+        if points.last() != Some(point) {
+            points.push(*point);
+        }
+    }
+
+    if points.len() < 2 || points.first() != points.last() {
+        return None;
+    }
+    points.pop();
+
+    (points.len() >= 3).then_some(points)
+}
+
+/// Moves each edge of a closed path along its outward normal, joining the moved edges at mitered corners.
+///
+/// # Arguments
+/// * `points`: The distinct points around the path.
+/// * `distance`: How far to move each edge in mm, outwards if positive, inwards if negative.
+///
+/// # Returns
+/// The offset path, closed back to its first point.
+fn offset_ring(points: &[PointInMillimeters], distance: f32) -> PathInMM {
+    // The winding of the path decides which side of each edge is outside.
+    let winding = if signed_area(points) > 0.0 { 1.0 } else { -1.0 };
+    let outward_normal = |from: PointInMillimeters, to: PointInMillimeters| {
+        let (dx, dy) = (to.x - from.x, to.y - from.y);
+        let length = dx.hypot(dy);
+        (winding * dy / length, -winding * dx / length)
+    };
+
+    let count = points.len();
+    let mut offset: PathInMM = (0..count)
+        .map(|index| {
+            let previous = points[(index + count - 1) % count];
+            let point = points[index];
+            let next = points[(index + 1) % count];
+
+            let (ax, ay) = outward_normal(previous, point);
+            let (bx, by) = outward_normal(point, next);
+            let (mx, my) = (ax + bx, ay + by);
+            let miter_length = mx.hypot(my);
+            if miter_length < f32::EPSILON {
+                // The path doubles back on itself, so there is no corner to miter.
+                return PointInMillimeters {
+                    x: point.x + ax * distance,
+                    y: point.y + ay * distance,
+                };
+            }
+
+            let (mx, my) = (mx / miter_length, my / miter_length);
+            let scale = (distance / (mx * ax + my * ay))
+                .clamp(-MITER_LIMIT * distance.abs(), MITER_LIMIT * distance.abs());
+            PointInMillimeters {
+                x: point.x + mx * scale,
+                y: point.y + my * scale,
+            }
+        })
+        .collect();
+    offset.push(offset[0]);
+
+    offset
+}
+
+/// Works out the signed area of a closed path with the shoelace formula.
+///
+/// # Arguments
+/// * `points`: The distinct points around the path.
+///
+/// # Returns
+/// The area of the path, whose sign gives the direction the path winds in.
+fn signed_area(points: &[PointInMillimeters]) -> f32 {
+    points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(a, b)| a.x * b.y - b.x * a.y)
+        .sum::<f32>()
+        / 2.0
+}
+
+/// Checks whether a point is inside a closed path, using the even-odd rule.
+///
+/// # Arguments
+/// * `points`: The distinct points around the path.
+/// * `point`: The point to check.
+///
+/// # Returns
+/// true if the point is inside the path.
+fn contains(points: &[PointInMillimeters], point: PointInMillimeters) -> bool {
+    let mut inside = false;
+    for (a, b) in points.iter().zip(points.iter().cycle().skip(1)) {
+        if (a.y > point.y) != (b.y > point.y)
+            && point.x < a.x + (point.y - a.y) * (b.x - a.x) / (b.y - a.y)
+        {
+            inside = !inside;
+        }
+    }
+
+    inside
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::BTreeMap, path::PathBuf};
+
+    use super::compensate_for_kerf;
+    use crate::{
+        default_passes::default_passes,
+        paths::{trace_paths, PathInMM},
+        svg::{get_paths_grouped_by_colour, parse_svg},
+        PointInMillimeters,
+    };
+
+    /// Makes a closed square path.
+    fn square(min: f32, max: f32, clockwise: bool) -> PathInMM {
+        let mut corners = vec![(min, min), (max, min), (max, max), (min, max)];
+        if clockwise {
+            corners.reverse();
+        }
+        corners.push(corners[0]);
+        corners
+            .into_iter()
+            .map(|(x, y)| PointInMillimeters { x, y })
+            .collect()
+    }
+
+    /// Gets the width and height of a path, rounded to the nearest micron.
+    fn size(path: &PathInMM) -> (f32, f32) {
+        let extent = |value: fn(&PointInMillimeters) -> f32| {
+            let values = path.iter().map(value);
+            let max = values.clone().fold(f32::MIN, f32::max);
+            let min = values.fold(f32::MAX, f32::min);
+            ((max - min) * 1000.0).round() / 1000.0
+        };
+        (extent(|point| point.x), extent(|point| point.y))
+    }
+
+    #[test]
+    fn kerf_grows_parts_and_shrinks_their_holes() {
+        let mut passes = default_passes();
+        passes[0].set_kerf_mm(0.2);
+        let colour = *passes[0].colour();
+
+        for clockwise in [false, true] {
+            let open_line = vec![
+                PointInMillimeters { x: 0.0, y: 20.0 },
+                PointInMillimeters { x: 10.0, y: 20.0 },
+            ];
+            // A square with a tab left uncut, whose ends are close together but don't meet.
+            let mut nearly_closed = square(20.0, 30.0, clockwise);
+            nearly_closed
+                .last_mut()
+                .expect("Square should have corners")
+                .x += 0.5;
+            let mut paths = BTreeMap::from([(
+                colour,
+                vec![
+                    square(0.0, 10.0, clockwise),
+                    square(3.0, 7.0, !clockwise),
+                    open_line.clone(),
+                    nearly_closed.clone(),
+                ],
+            )]);
+            compensate_for_kerf(&mut paths, &passes);
+
+            let paths = &paths[&colour];
+            // Each side of the part moves out by half the kerf, and each side of the hole moves in.
+            assert_eq!(size(&paths[0]), (10.2, 10.2));
+            assert_eq!(size(&paths[1]), (3.8, 3.8));
+            assert_eq!(paths[0].first(), paths[0].last());
+            assert_eq!(paths[2], open_line);
+            assert_eq!(paths[3], nearly_closed);
+        }
+    }
+
+    #[test]
+    fn kerf_is_set_by_the_first_enabled_pass() {
+        let mut disabled = default_passes()[0].clone();
+        disabled.set_kerf_mm(1.0);
+        disabled.set_enabled(false);
+        let mut enabled = disabled.clone();
+        enabled.set_kerf_mm(0.2);
+        enabled.set_enabled(true);
+        let colour = *enabled.colour();
+
+        let mut paths = BTreeMap::from([(colour, vec![square(0.0, 10.0, false)])]);
+        compensate_for_kerf(&mut paths, &[disabled, enabled]);
+
+        assert_eq!(size(&paths[&colour][0]), (10.2, 10.2));
+    }
+
+    #[test]
+    fn closed_shapes_are_traced_closed() {
+        // The circumference isn't a whole number of mm, so the traced points don't land back on the start.
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="20mm" height="20mm" viewBox="0 0 20 20">
+            <circle cx="10" cy="10" r="5" fill="none" stroke="black"/>
+            <path d="M 2 2 L 18 2 L 18 18 L 2 18 L 2 2.5" fill="none" stroke="black"/>
+        </svg>"#;
+        let tree =
+            parse_svg(&PathBuf::from("shapes.svg"), svg.as_bytes()).expect("SVG should parse");
+        let mut passes = default_passes();
+        passes[0].set_kerf_mm(0.2);
+        let colour = *passes[0].colour();
+        let mut paths = trace_paths(
+            &get_paths_grouped_by_colour(&tree).expect("Paths should group"),
+            &passes,
+        );
+        let (circle, nearly_closed) = (paths[&colour][0].clone(), paths[&colour][1].clone());
+        assert_eq!(circle.first(), circle.last());
+        compensate_for_kerf(&mut paths, &passes);
+
+        // The circle grows by the kerf, to the nearest 10 microns.
+        let paths = &paths[&colour];
+        let growth = |axis: fn((f32, f32)) -> f32| {
+            ((axis(size(&paths[0])) - axis(size(&circle))) * 100.0).round() / 100.0
+        };
+        assert_eq!((growth(|(x, _)| x), growth(|(_, y)| y)), (0.2, 0.2));
+        assert_eq!(paths[1], nearly_closed);
+    }
+}
@@ -1,19 +1,42 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+
+#[cfg(feature = "kerf")]
+use crate::kerf::Kerf;
+use crate::{
+    svg::{GroupKey, StrokeWidth},
+    Colour,
+};
+
+/// The most pulses per inch that can be written in the PCL pens table.
+pub const MAX_PPI: u32 = 9999;
 
 /// The settings for a single pass of the tool head over lines of a given colour.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Hash)]
 pub struct ToolPass {
     name: String,
-    /// Colour channel value of lines to machine [R, G, B].
-    colour: [u8; 3],
+    /// Colour of lines to machine.
+    colour: Colour,
     /// Tool power, max 1000. Unitless, proportion of max.
     power: u64,
     /// Tool speed, max 1000. Unitless, proportion of max.
     speed: u64,
+    /// Laser pulses per inch, max [`MAX_PPI`].
+    #[serde(default = "default_ppi", deserialize_with = "deserialize_ppi")]
+    ppi: u32,
     /// Raster engrave.
     rast: bool,
     /// Enable or disbale this tool pass
     enable: bool,
+    /// The layer or CSS class of paths that this pass cuts, when paths are grouped by name rather than colour.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
+    /// The stroke width of paths that this pass cuts, when paths are grouped by stroke width.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stroke_width: Option<StrokeWidth>,
+    /// The width of material removed by the tool, in mm, that closed paths are offset by half of.
+    #[cfg(feature = "kerf")]
+    #[serde(default, skip_serializing_if = "Kerf::is_none")]
+    kerf_mm: Kerf,
 }
 
 impl ToolPass {
@@ -27,19 +50,33 @@ impl ToolPass {
     /// * `power`: Tool power, will be clamped to 1000.
     /// * `speed`: Tool speed, will be clamped to 1000.
     /// * `rast`: Raster engrave.
-    /// * `vect`: ? Unknown.
+    /// * `ppi`: Laser pulses per inch, will be clamped to [`MAX_PPI`].
     ///
     /// # Returns
     /// A new [`ToolPass`] with values appropriately clamped.
     #[allow(clippy::too_many_arguments)]
-    pub fn new(name: String, r: u8, g: u8, b: u8, power: u64, speed: u64, rast: bool) -> Self {
+    pub fn new(
+        name: String,
+        r: u8,
+        g: u8,
+        b: u8,
+        power: u64,
+        speed: u64,
+        rast: bool,
+        ppi: u32,
+    ) -> Self {
         ToolPass {
             name,
-            colour: [r, g, b],
+            colour: Colour([r, g, b]),
             power: power.min(1000),
             speed: speed.min(1000),
+            ppi: ppi.min(MAX_PPI),
             rast,
             enable: true,
+            group: None,
+            stroke_width: None,
+            #[cfg(feature = "kerf")]
+            kerf_mm: Kerf::default(),
         }
     }
 
@@ -63,7 +100,7 @@ impl ToolPass {
     ///
     /// # Returns
     /// The colour of the tool pass.
-    pub fn colour(&self) -> &[u8; 3] {
+    pub fn colour(&self) -> &Colour {
         &self.colour
     }
 
@@ -71,10 +108,58 @@ impl ToolPass {
     ///
     /// # Arguments
     /// * `colour`: The new colour of the tool pass.
-    pub fn set_colour(&mut self, colour: [u8; 3]) {
+    pub fn set_colour(&mut self, colour: Colour) {
         self.colour = colour;
     }
 
+    /// Gets the layer or CSS class of paths that the tool pass cuts, when paths are grouped by name.
+    ///
+    /// # Returns
+    /// The name of the group, if the tool pass has one.
+    pub fn group(&self) -> Option<&str> {
+        self.group.as_deref()
+    }
+
+    /// Sets the layer or CSS class of paths that the tool pass cuts, when paths are grouped by name.
+    ///
+    /// # Arguments
+    /// * `group`: The name of the group, or `None` to not cut any named group.
+    pub fn set_group(&mut self, group: Option<String>) {
+        self.group = group;
+    }
+
+    /// Gets the stroke width of paths that the tool pass cuts, when paths are grouped by stroke width.
+    ///
+    /// # Returns
+    /// The stroke width, if the tool pass has one.
+    pub fn stroke_width(&self) -> Option<StrokeWidth> {
+        self.stroke_width
+    }
+
+    /// Sets the stroke width of paths that the tool pass cuts, when paths are grouped by stroke width.
+    ///
+    /// # Arguments
+    /// * `stroke_width`: The stroke width, or `None` to not cut paths by their stroke width.
+    pub fn set_stroke_width(&mut self, stroke_width: Option<StrokeWidth>) {
+        self.stroke_width = stroke_width;
+    }
+
+    /// Checks whether the tool pass cuts a group of paths.
+    ///
+    /// # Arguments
+    /// * `key`: The key of the group of paths.
+    ///
+    /// # Returns
+    /// `true` if the group's colour is the colour of the pass, the group's name is the group of the pass,
+    /// or the group's stroke width is the stroke width of the pass.
+    pub fn matches(&self, key: &GroupKey) -> bool {
+        match key {
+            GroupKey::Colour(colour) => self.colour == *colour,
+            GroupKey::Name(name) => self.group.as_deref() == Some(name),
+            GroupKey::StrokeWidth(width) => self.stroke_width == Some(*width),
+        }
+    }
+
     /// Gets the speed of the tool pass.
     ///
     /// # Returns
@@ -107,6 +192,56 @@ impl ToolPass {
         self.power = power.min(1000);
     }
 
+    /// Gets the laser pulses per inch of the tool pass.
+    ///
+    /// # Returns
+    /// The pulses per inch of the tool pass.
+    pub fn ppi(&self) -> &u32 {
+        &self.ppi
+    }
+
+    /// Sets the laser pulses per inch of the tool pass.
+    ///
+    /// # Arguments
+    /// * `ppi`: The new pulses per inch of the tool pass.
+    pub fn set_ppi(&mut self, ppi: u32) {
+        self.ppi = ppi.min(MAX_PPI);
+    }
+
+    /// Gets the kerf of the tool pass.
+    ///
+    /// # Returns
+    /// The width of material removed by the tool, in mm.
+    #[cfg(feature = "kerf")]
+    pub fn kerf_mm(&self) -> f32 {
+        self.kerf_mm.0
+    }
+
+    /// Sets the kerf of the tool pass.
+    ///
+    /// # Arguments
+    /// * `kerf_mm`: The new width of material removed by the tool, in mm, will be clamped to at least 0.
+    #[cfg(feature = "kerf")]
+    pub fn set_kerf_mm(&mut self, kerf_mm: f32) {
+        self.kerf_mm = Kerf(kerf_mm.max(0.0));
+    }
+
+    /// Gets whether the tool pass raster engraves.
+    ///
+    /// # Returns
+    /// Whether the tool pass raster engraves.
+    pub fn rast(&self) -> &bool {
+        &self.rast
+    }
+
+    /// Sets whether the tool pass raster engraves.
+    ///
+    /// # Arguments
+    /// * `rast`: Whether the tool pass should raster engrave.
+    pub fn set_rast(&mut self, rast: bool) {
+        self.rast = rast;
+    }
+
     /// Gets the enable state of the tool pass
     ///
     /// # Returns
@@ -123,3 +258,42 @@ impl ToolPass {
         self.enable = new_state;
     }
 }
+
+/// Gets the pulses per inch of tool passes that were saved before it could be set.
+///
+/// # Returns
+/// The default pulses per inch.
+fn default_ppi() -> u32 {
+    400
+}
+
+/// Reads the pulses per inch of a tool pass, clamping it to [`MAX_PPI`] so that a hand-edited
+/// settings file can't write more digits into the PCL pens table than it has room for.
+///
+/// # Arguments
+/// * `deserializer`: Where to read the pulses per inch from.
+///
+/// # Returns
+/// The pulses per inch, at most [`MAX_PPI`], otherwise an error if it isn't a whole number.
+fn deserialize_ppi<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    Ok(u32::deserialize(deserializer)?.min(MAX_PPI))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ToolPass, MAX_PPI};
+
+    #[test]
+    fn loaded_ppi_is_clamped() {
+        let load = |ppi: &str| {
+            serde_json::from_str::<ToolPass>(&format!(
+                r##"{{"name":"Cut","colour":"#000000","power":100,"speed":100,{ppi}"rast":false,"enable":true}}"##
+            ))
+            .expect("Tool pass should load")
+        };
+
+        assert_eq!(*load(r#""ppi":12345,"#).ppi(), MAX_PPI);
+        assert_eq!(*load(r#""ppi":500,"#).ppi(), 500);
+        assert_eq!(*load("").ppi(), 400);
+    }
+}
@@ -2,25 +2,73 @@
 //!
 //! A utility for talking to devices that speak HPGL.
 
+mod arcs;
+mod bed;
+mod colour;
+#[cfg(all(feature = "cups", not(target_os = "windows")))]
+mod cups;
 pub mod default_passes;
+#[cfg(feature = "dxf")]
+pub mod dxf;
+mod fill;
 mod hpgl;
+#[cfg(feature = "kerf")]
+mod kerf;
 mod laser_passes;
-mod paths;
+mod machine;
+mod materials;
+pub mod paths;
 mod pcl;
+mod retry;
+#[cfg(feature = "serial")]
+mod serial;
+mod settings;
 pub mod svg;
 
 use std::{
+    collections::BTreeMap,
     fs::OpenOptions,
-    io::{self, Write},
+    io::{self, BufWriter, Write},
+    net::{TcpStream, ToSocketAddrs},
     path::Path,
+    time::Duration,
 };
 
-use hpgl::generate_hpgl;
-pub use laser_passes::ToolPass;
-use paths::resolve_paths;
-use pcl::wrap_hpgl_in_pcl;
+pub use bed::{
+    BedAxis, BedMargins, BedMode, KeepOutZone, PlacePointError, PrintBed, PrintBedError,
+};
+pub use colour::{Colour, ParseColourError};
+use fill::hatch_fills;
+pub use fill::{HatchOptions, MIN_HATCH_SPACING_MM};
+use hpgl::first_enabled_pass;
+pub use hpgl::{
+    generate_frame_hpgl, generate_hpgl, generate_hpgl_segments, generate_hpgl_to,
+    HpglCoordinateMode, HpglError, HpglOptions, PassHpgl, MAX_TOOL_PASSES, TOOL_PASS_COUNT,
+};
+pub use laser_passes::{ToolPass, MAX_PPI};
+pub use machine::{DeviceProfile, Machine};
+pub use materials::{MaterialLibrary, MaterialLibraryError, MaterialPreset};
+use paths::{
+    convert_points_to_plotter_units, simplify_collinear, snap_colours_to, trace_paths,
+    ResolvedPath, COLLINEAR_TOLERANCE_MM,
+};
+pub use paths::{
+    Bounds, CutStatistics, PathColour, PathInMM, PathPlacementError, PointInMillimeters,
+};
+pub use pcl::{wrap_hpgl_in_pcl, wrap_hpgl_in_pcl_to, JobMetadata};
 use resvg::usvg;
-use svg::get_paths_grouped_by_colour;
+use retry::RetryingWriter;
+pub use retry::WriteRetry;
+#[cfg(feature = "serial")]
+use serial::{open_serial_port, serial_port_exists};
+#[cfg(feature = "serial")]
+pub use serial::{SerialFlowControl, DEFAULT_SERIAL_BAUD, SERIAL_BAUD_RATES};
+pub use settings::{SettingsFile, SettingsFileError, SETTINGS_FILE_VERSION};
+use svg::{
+    assign_groups_to_passes, design_size_mm, get_paths_grouped_by_colour, group_paths_by,
+    layer_visibility, parse_svg_with_options, system_fontdb, GroupingKey, LayerVisibility,
+    ParseWarnings,
+};
 
 type Vec2 = (f32, f32);
 
@@ -46,19 +94,94 @@ pub const BED_HEIGHT_MM: f32 = BED_Y_AXIS_MAXIMUM_MM;
 #[cfg(not(target_os = "windows"))]
 pub const DEFAULT_PRINT_DEVICE: &'static str = "/dev/usb/lp0";
 
+/// The port that network print devices usually take raw jobs on, as JetDirect does.
+pub const DEFAULT_TCP_PORT: u16 = 9100;
+
+/// How long to wait to connect to a network print device when sending a job.
+const TCP_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for a network print device to take each write before giving up on it.
+const TCP_WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long to wait to connect to a network print device when checking that it is there.
+const TCP_CHECK_TIMEOUT: Duration = Duration::from_millis(500);
+
 /// A loaded design.
+#[derive(Clone)]
 pub struct DesignFile {
     /// The name of the design.
     pub name: String,
     /// The SVG tree.
     pub tree: usvg::Tree,
+    /// The bytes of the SVG, as layer labels and CSS classes are not kept in the tree,
+    /// see [`svg::group_paths_by`]. Empty if the design wasn't loaded from an SVG.
+    pub svg: Vec<u8>,
     /// Width of the design in mm.
     pub width_mm: f32,
     /// Height of the design in mm.
     pub height_mm: f32,
+    /// The layers of the design and whether they are shown, paths in hidden layers aren't cut.
+    pub layers: Vec<LayerVisibility>,
+    /// Things noticed while parsing the design that may mean it won't be cut as expected.
+    pub warnings: ParseWarnings,
 }
 
 impl DesignFile {
+    /// Loads a design from the bytes of an SVG, without needing a path to it, e.g. for designs sent over the network.
+    /// Files that the SVG links to by a relative path are looked for in the current directory.
+    ///
+    /// # Arguments
+    /// * `name`: The name of the design.
+    /// * `bytes`: The bytes of the SVG.
+    /// * `bed`: The bed that the design will be cut on, a warning is logged if the design won't fit on it.
+    ///
+    /// # Returns
+    /// The design, or [`SendToDeviceError::ErrorParsingSvg`] if the SVG couldn't be parsed.
+    pub fn from_bytes(
+        name: String,
+        bytes: &[u8],
+        bed: &PrintBed,
+    ) -> Result<Self, SendToDeviceError> {
+        Self::from_bytes_at(name, Path::new(""), bytes, bed)
+    }
+
+    /// Loads a design from the bytes of an SVG that were read from a file.
+    ///
+    /// # Arguments
+    /// * `name`: The name of the design.
+    /// * `path`: The path that the SVG was read from, files that it links to by a relative path are looked for next to it.
+    /// * `bytes`: The bytes of the SVG.
+    /// * `bed`: The bed that the design will be cut on, a warning is logged if the design won't fit on it.
+    ///
+    /// # Returns
+    /// The design, or [`SendToDeviceError::ErrorParsingSvg`] if the SVG couldn't be parsed.
+    pub fn from_bytes_at(
+        name: String,
+        path: &Path,
+        bytes: &[u8],
+        bed: &PrintBed,
+    ) -> Result<Self, SendToDeviceError> {
+        let (tree, warnings) =
+            parse_svg_with_options(&path.to_path_buf(), bytes, system_fontdb(), true)
+                .map_err(SendToDeviceError::ErrorParsingSvg)?;
+        let (width_mm, height_mm) = design_size_mm(&tree);
+        if bed.fit_offset_range(width_mm, height_mm).is_none() {
+            log::warn!(
+                "Design '{name}' is {width_mm:.1}mm x {height_mm:.1}mm, which doesn't fit on the bed"
+            );
+        }
+
+        Ok(Self {
+            name,
+            tree,
+            svg: bytes.to_vec(),
+            width_mm,
+            height_mm,
+            layers: layer_visibility(bytes),
+            warnings,
+        })
+    }
+
     /// Gets the name of the design.
     ///
     /// # Returns
@@ -74,6 +197,14 @@ impl DesignFile {
     pub fn tree(&self) -> &usvg::Tree {
         &self.tree
     }
+
+    /// Gets the layers of the design that are hidden, and so won't be cut.
+    ///
+    /// # Returns
+    /// The hidden layers.
+    pub fn hidden_layers(&self) -> impl Iterator<Item = &LayerVisibility> {
+        self.layers.iter().filter(|layer| !layer.visible)
+    }
 }
 
 /// Errors that can occur when sending the design to the HPGL device.
@@ -81,10 +212,18 @@ impl DesignFile {
 pub enum SendToDeviceError {
     /// There was an error while parsing the SVG file.
     ErrorParsingSvg(usvg::Error),
+    /// There was an error while generating the HPGL for the design.
+    GenerateHpglError(HpglError),
     /// Failed to open the printer port.
     FailedToOpenPrinter(io::Error),
     /// Failed to write to the printer port.
     FailedToWriteToPrinter(io::Error),
+    /// Some of the paths in the design couldn't be placed on the bed.
+    PathsOffBed(Vec<PathPlacementError>),
+    /// None of the enabled tool passes cut any paths, so there is nothing to frame.
+    NothingToFrame,
+    /// A corner of the frame around the design, or the origin that the toolhead parks at, couldn't be placed on the bed.
+    FrameOffBed(PlacePointError),
 }
 
 /// The printer-like device that we're using.
@@ -95,12 +234,48 @@ pub enum PrintDevice {
     Path {
         /// The path to send the bytes to.
         path: String,
+        /// How writes to the device are retried if it is busy.
+        #[serde(default)]
+        retry: WriteRetry,
     },
     /// We're using a USB port.
     #[cfg(target_os = "windows")]
     USBPort {
         /// The USB port to use.
         port: Option<USBPort>,
+        /// How writes to the device are retried if it is busy.
+        #[serde(default)]
+        retry: WriteRetry,
+    },
+    /// We're sending the bytes over the network to a device that takes raw jobs on a TCP port.
+    Tcp {
+        /// The host name or address of the device.
+        host: String,
+        /// The port that the device takes jobs on, usually [`DEFAULT_TCP_PORT`].
+        port: u16,
+        /// How connecting and writing to the device are retried if it is busy.
+        #[serde(default)]
+        retry: WriteRetry,
+    },
+    /// We're submitting the bytes as a raw job to a printer queue that the device is set up as.
+    #[cfg(all(feature = "cups", not(target_os = "windows")))]
+    SystemPrinter {
+        /// The name of the printer queue.
+        queue_name: String,
+    },
+    /// We're sending the bytes to a plotter on a serial port.
+    #[cfg(feature = "serial")]
+    Serial {
+        /// The path or name of the port, e.g. `/dev/ttyUSB0` or `COM1`.
+        path: String,
+        /// The baud rate of the plotter, usually [`DEFAULT_SERIAL_BAUD`].
+        baud: u32,
+        /// How the plotter tells us to stop sending while its buffer is full.
+        #[serde(default)]
+        flow_control: SerialFlowControl,
+        /// How opening and writing to the port are retried if it is busy.
+        #[serde(default)]
+        retry: WriteRetry,
     },
 }
 
@@ -115,59 +290,319 @@ pub struct USBPort {
 }
 
 impl PrintDevice {
-    /// Sends a PCL string to the printer-like device.
+    /// Sends PCL to the printer-like device, retrying if the device is busy.
     ///
     /// # Arguments
-    /// * `design`: The PCL to print.
+    /// * `write_design`: Writes the PCL to print, it is streamed to the device where the device allows.
     ///
     /// # Returns
-    /// `Ok(())` if the PCL was successfully sent to the printer, otherwise a [`SendToDeviceError`].
-    fn print(&self, design: &str) -> Result<(), SendToDeviceError> {
+    /// Once the PCL has been sent, the ID that the device gave the job if it gives jobs IDs, otherwise a [`SendToDeviceError`].
+    fn print(
+        &self,
+        write_design: impl FnOnce(&mut dyn Write) -> Result<(), SendToDeviceError>,
+    ) -> Result<Option<String>, SendToDeviceError> {
         match self {
+            #[cfg(all(feature = "cups", not(target_os = "windows")))]
+            PrintDevice::SystemPrinter { queue_name } => {
+                // The job is checked by CUPS once it is submitted, so a job that fails part way mustn't be.
+                let mut design = vec![];
+                write_design(&mut design)?;
+                cups::submit_raw_job(queue_name, &design)
+            }
+            PrintDevice::Tcp { host, port, retry } => {
+                // Failing to reach the device can't be told apart from it dropping the job, so both are failed writes.
+                let stream = retry
+                    .run(|| connect_tcp(host, *port, TCP_CONNECT_TIMEOUT))
+                    .map_err(SendToDeviceError::FailedToWriteToPrinter)?;
+                stream
+                    .set_write_timeout(Some(TCP_WRITE_TIMEOUT))
+                    .map_err(SendToDeviceError::FailedToWriteToPrinter)?;
+                let mut writer = BufWriter::new(RetryingWriter::new(stream, *retry));
+                write_design(&mut writer)?;
+                writer
+                    .flush()
+                    .map_err(SendToDeviceError::FailedToWriteToPrinter)?;
+
+                Ok(None)
+            }
+            #[cfg(feature = "serial")]
+            PrintDevice::Serial {
+                path,
+                baud,
+                flow_control,
+                retry,
+            } => {
+                let port = retry
+                    .run(|| open_serial_port(path, *baud, *flow_control))
+                    .map_err(SendToDeviceError::FailedToOpenPrinter)?;
+                let mut writer = BufWriter::new(RetryingWriter::new(port, *retry));
+                write_design(&mut writer)?;
+                writer
+                    .flush()
+                    .map_err(SendToDeviceError::FailedToWriteToPrinter)?;
+
+                Ok(None)
+            }
             #[cfg(not(target_os = "windows"))]
-            PrintDevice::Path { path } => {
-                let mut file = OpenOptions::new()
-                    .write(true)
-                    .create(false)
-                    .append(true)
-                    .open(path)
+            PrintDevice::Path { path, retry } => {
+                let file = retry
+                    .run(|| {
+                        OpenOptions::new()
+                            .write(true)
+                            .create(false)
+                            .append(true)
+                            .open(path)
+                    })
                     .map_err(SendToDeviceError::FailedToOpenPrinter)?;
-                file.write(design.as_bytes())
+                let mut writer = BufWriter::new(RetryingWriter::new(file, *retry));
+                write_design(&mut writer)?;
+                writer
+                    .flush()
                     .map_err(SendToDeviceError::FailedToWriteToPrinter)?;
 
-                Ok(())
+                Ok(None)
             }
             #[cfg(target_os = "windows")]
-            PrintDevice::USBPort { port } => {
-                let api = hidapi_rusb::HidApi::new().unwrap();
-                if let Some(port) = port {
-                    if let Ok(device) = api.open(port.vendor_id, port.product_id) {
-                        device.write(design.as_bytes()).expect("Failed to print");
-                    }
-                }
+            PrintDevice::USBPort { port, retry } => {
+                // The whole design has to be handed over in one go.
+                let mut design = vec![];
+                write_design(&mut design)?;
+
+                let Some(port) = port else {
+                    return Err(SendToDeviceError::FailedToOpenPrinter(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        "no USB port has been selected",
+                    )));
+                };
+                let device = retry
+                    .run(|| {
+                        hidapi_rusb::HidApi::new()
+                            .and_then(|api| api.open(port.vendor_id, port.product_id))
+                            .map_err(io::Error::other)
+                    })
+                    .map_err(SendToDeviceError::FailedToOpenPrinter)?;
+                retry
+                    .run(|| device.write(&design).map_err(io::Error::other))
+                    .map_err(SendToDeviceError::FailedToWriteToPrinter)?;
+
+                Ok(None)
             }
         }
     }
 
     /// Checks whether the print device is valid to be used for printing.
+    /// Network devices are checked by connecting to them, which can take a moment.
     ///
     /// # Returns
     /// `true` if the print device is valid to be used for printing.
     pub fn is_valid(&self) -> bool {
         match self {
+            PrintDevice::Tcp { host, port, .. } => {
+                connect_tcp(host, *port, TCP_CHECK_TIMEOUT).is_ok()
+            }
+            #[cfg(all(feature = "cups", not(target_os = "windows")))]
+            PrintDevice::SystemPrinter { queue_name } => cups::queue_exists(queue_name),
+            #[cfg(feature = "serial")]
+            PrintDevice::Serial { path, .. } => serial_port_exists(path),
             #[cfg(not(target_os = "windows"))]
-            PrintDevice::Path { path } => Path::new(path).exists(),
+            PrintDevice::Path { path, .. } => Path::new(path).exists(),
             #[cfg(target_os = "windows")]
-            PrintDevice::USBPort { port } => {
+            PrintDevice::USBPort { port, .. } => {
                 let Some(port) = port else {
                     return false;
                 };
 
-                let api = hidapi_rusb::HidApi::new().unwrap();
-                api.open(port.vendor_id, port.product_id).is_ok()
+                match hidapi_rusb::HidApi::new() {
+                    Ok(api) => api.open(port.vendor_id, port.product_id).is_ok(),
+                    Err(err) => {
+                        log::warn!("Could not access USB devices: {err}");
+                        false
+                    }
+                }
             }
         }
     }
+
+    /// Finds the print devices that are connected, so that the user can pick one rather than typing it in.
+    /// Network devices can't be found, so are never included.
+    /// On Linux these are the USB printers in `/dev/usb`, and the system's printer queues where CUPS is used,
+    /// on Windows they are the connected HID devices. Serial ports are included where serial plotters are supported.
+    ///
+    /// # Returns
+    /// The devices that were found, with the names of their manufacturer and product where they are known.
+    #[cfg(not(target_os = "windows"))]
+    pub fn enumerate() -> Vec<PrintDeviceCandidate> {
+        let mut candidates = Self::enumerate_usb_printers();
+        #[cfg(feature = "cups")]
+        candidates.extend(
+            cups::list_queues()
+                .into_iter()
+                .map(|queue_name| PrintDeviceCandidate {
+                    description: format!("{queue_name} (system printer)"),
+                    device: PrintDevice::SystemPrinter { queue_name },
+                }),
+        );
+        #[cfg(feature = "serial")]
+        candidates.extend(serial::enumerate_serial_ports());
+
+        candidates
+    }
+
+    /// Finds the USB printers in `/dev/usb`.
+    ///
+    /// # Returns
+    /// The printers that were found, with the names of their manufacturer and product where they are known.
+    #[cfg(not(target_os = "windows"))]
+    fn enumerate_usb_printers() -> Vec<PrintDeviceCandidate> {
+        let Ok(entries) = std::fs::read_dir(USB_PRINTER_DIRECTORY) else {
+            return vec![];
+        };
+
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| name.starts_with("lp"))
+            .collect();
+        names.sort();
+
+        names
+            .into_iter()
+            .map(|name| {
+                // The USB device that the printer belongs to describes itself in sysfs.
+                let describe = |attribute: &str| {
+                    std::fs::read_to_string(format!(
+                        "/sys/class/usbmisc/{name}/device/../{attribute}"
+                    ))
+                    .ok()
+                    .map(|value| value.trim().to_string())
+                };
+                let path = format!("{USB_PRINTER_DIRECTORY}/{name}");
+                PrintDeviceCandidate {
+                    description: describe_device(
+                        &path,
+                        describe("manufacturer"),
+                        describe("product"),
+                    ),
+                    device: PrintDevice::Path {
+                        path,
+                        retry: WriteRetry::default(),
+                    },
+                }
+            })
+            .collect()
+    }
+
+    /// Finds the print devices that are connected, so that the user can pick one rather than typing it in.
+    /// Network devices can't be found, so are never included.
+    /// On Linux these are the USB printers in `/dev/usb`, on Windows they are the connected HID devices.
+    /// Serial ports are included where serial plotters are supported.
+    ///
+    /// # Returns
+    /// The devices that were found, with the names of their manufacturer and product where they are known.
+    #[cfg(target_os = "windows")]
+    pub fn enumerate() -> Vec<PrintDeviceCandidate> {
+        let api = match hidapi_rusb::HidApi::new() {
+            Ok(api) => api,
+            Err(err) => {
+                log::warn!("Could not access USB devices: {err}");
+                return vec![];
+            }
+        };
+
+        let mut candidates: Vec<PrintDeviceCandidate> = vec![];
+        for info in api.device_list() {
+            let port = USBPort {
+                vendor_id: info.vendor_id(),
+                product_id: info.product_id(),
+            };
+            // Each interface of a device is listed separately, but the device is opened by its IDs.
+            let listed = candidates.iter().any(|candidate| {
+                matches!(&candidate.device, PrintDevice::USBPort { port: Some(listed), .. } if *listed == port)
+            });
+            if listed {
+                continue;
+            }
+
+            candidates.push(PrintDeviceCandidate {
+                description: describe_device(
+                    &format!("{:04X}:{:04X}", port.vendor_id, port.product_id),
+                    info.manufacturer_string().map(str::to_string),
+                    info.product_string().map(str::to_string),
+                ),
+                device: PrintDevice::USBPort {
+                    port: Some(port),
+                    retry: WriteRetry::default(),
+                },
+            });
+        }
+        #[cfg(feature = "serial")]
+        candidates.extend(serial::enumerate_serial_ports());
+
+        candidates
+    }
+}
+
+/// The directory that Linux puts USB printers in.
+#[cfg(not(target_os = "windows"))]
+const USB_PRINTER_DIRECTORY: &str = "/dev/usb";
+
+/// A print device that was found by [`PrintDevice::enumerate`].
+#[derive(Clone)]
+pub struct PrintDeviceCandidate {
+    /// The device.
+    pub device: PrintDevice,
+    /// A description of the device to show to the user.
+    pub description: String,
+}
+
+/// Connects to a network print device, trying each address that its host name resolves to.
+///
+/// # Arguments
+/// * `host`: The host name or address of the device.
+/// * `port`: The port that the device takes jobs on.
+/// * `timeout`: How long to wait to connect to each address.
+///
+/// # Returns
+/// The connection, otherwise the error from the last address that was tried.
+fn connect_tcp(host: &str, port: u16, timeout: Duration) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("no addresses were found for {host}"),
+    );
+    for address in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_error = err,
+        }
+    }
+
+    Err(last_error)
+}
+
+/// Describes a print device for the user.
+///
+/// # Arguments
+/// * `location`: Where the device is, e.g. its path.
+/// * `manufacturer`: The name of the manufacturer of the device, if known.
+/// * `product`: The name of the device, if known.
+///
+/// # Returns
+/// The description of the device.
+fn describe_device(
+    location: &str,
+    manufacturer: Option<String>,
+    product: Option<String>,
+) -> String {
+    let name = [manufacturer, product]
+        .into_iter()
+        .flatten()
+        .filter(|name| !name.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    if name.is_empty() {
+        location.to_string()
+    } else {
+        format!("{name} ({location})")
+    }
 }
 
 impl Default for PrintDevice {
@@ -175,6 +610,7 @@ impl Default for PrintDevice {
     fn default() -> Self {
         PrintDevice::Path {
             path: DEFAULT_PRINT_DEVICE.to_string(),
+            retry: WriteRetry::default(),
         }
     }
 
@@ -186,8 +622,140 @@ impl Default for PrintDevice {
                 vendor_id: port.vendor_id,
                 product_id: port.product_id,
             });
-        PrintDevice::USBPort { port }
+        PrintDevice::USBPort {
+            port,
+            retry: WriteRetry::default(),
+        }
+    }
+}
+
+/// Where to put a design on the bed, and how to trace it and write the job for it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JobOptions {
+    /// How much to move the design by relative to its starting position, in mm, where +x is more right and +y is more down.
+    pub offset: Vec2,
+    /// If provided, filled shapes whose fill colour matches a tool pass are hatched and the hatching is cut by that pass.
+    pub hatch: Option<HatchOptions>,
+    /// If provided, paths whose colour is this close to a tool pass's colour are cut by that pass,
+    /// see [`paths::snap_colours_to`].
+    pub colour_snap_tolerance: Option<u8>,
+    /// What paths are grouped by to choose the tool pass that cuts them.
+    pub grouping: GroupingKey,
+    /// How to write the HPGL.
+    pub hpgl_options: HpglOptions,
+    /// If provided, who the job is from, so that it can be shown on the machine.
+    pub metadata: Option<JobMetadata>,
+}
+
+/// Everything that building a job for a design works out, without sending it anywhere.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobBuildResult {
+    /// The HPGL for the job, or `None` if some of the paths couldn't be placed on the bed.
+    pub hpgl: Option<String>,
+    /// How much the job will cut.
+    pub statistics: CutStatistics,
+    /// The paths that couldn't be placed on the bed, empty if they all could.
+    pub paths_off_bed: Vec<PathPlacementError>,
+    /// Things noticed while parsing the design that may mean it won't be cut as expected.
+    pub warnings: ParseWarnings,
+}
+
+/// Builds the job for a design file without touching a device, so that the job can be inspected or checked
+/// against a known good job.
+///
+/// # Arguments
+/// * `design_file`: The design to build the job for.
+/// * `tool_passes`: Passes of the cutting tool.
+/// * `machine`: The machine that the design will be cut on.
+/// * `options`: Where to put the design and how to trace it and write the job.
+///
+/// # Returns
+/// The built job, which only has HPGL if every path is on the bed, otherwise a [`SendToDeviceError`]
+/// if the job can't be built at all.
+pub fn build_job(
+    design_file: &DesignFile,
+    tool_passes: &Vec<ToolPass>,
+    machine: &Machine,
+    options: &JobOptions,
+) -> Result<JobBuildResult, SendToDeviceError> {
+    let job = place_job(design_file, tool_passes, machine, options)?;
+    let statistics = CutStatistics::of_paths(&job.paths_in_mm, tool_passes);
+
+    let (hpgl, paths_off_bed) = match job.resolved_paths {
+        Ok(resolved_paths) => {
+            let hpgl = generate_hpgl(
+                &resolved_paths,
+                tool_passes,
+                machine.bed(),
+                &job.hpgl_options,
+            )
+            .map_err(SendToDeviceError::GenerateHpglError)?;
+            (Some(hpgl), vec![])
+        }
+        Err(paths_off_bed) => (None, paths_off_bed),
+    };
+
+    Ok(JobBuildResult {
+        hpgl,
+        statistics,
+        paths_off_bed,
+        warnings: design_file.warnings.clone(),
+    })
+}
+
+/// A design that has been traced and placed on a machine's bed, ready for its HPGL to be generated.
+struct PlacedJob {
+    /// The paths to cut, in mm.
+    paths_in_mm: BTreeMap<PathColour, Vec<PathInMM>>,
+    /// The paths to cut, placed on the bed, or the paths that couldn't be placed on it.
+    resolved_paths: Result<BTreeMap<PathColour, Vec<ResolvedPath>>, Vec<PathPlacementError>>,
+    /// How to write the HPGL, with anything the machine can't do turned off.
+    hpgl_options: HpglOptions,
+}
+
+/// Traces a design and places it on a machine's bed, the part of building a job that is the same
+/// whether the job is inspected or sent.
+///
+/// # Arguments
+/// * `design_file`: The design to place.
+/// * `tool_passes`: Passes of the cutting tool.
+/// * `machine`: The machine that the design will be cut on.
+/// * `options`: Where to put the design and how to trace it and write the job.
+///
+/// # Returns
+/// The placed job, otherwise a [`SendToDeviceError`] if the design couldn't be traced or the passes could never cut it.
+fn place_job(
+    design_file: &DesignFile,
+    tool_passes: &Vec<ToolPass>,
+    machine: &Machine,
+    options: &JobOptions,
+) -> Result<PlacedJob, SendToDeviceError> {
+    let paths_in_mm = trace_design(
+        &design_file.tree,
+        &design_file.svg,
+        tool_passes,
+        options.grouping,
+        options.hatch.as_ref(),
+        options.colour_snap_tolerance,
+    )?;
+    let resolved_paths =
+        convert_points_to_plotter_units(&paths_in_mm, options.offset, machine.bed());
+
+    let mut hpgl_options = options.hpgl_options;
+    if hpgl_options.pause_between_passes && !machine.profile().supports_pause() {
+        log::warn!("The machine can't pause between passes, the passes will run without pausing");
+        hpgl_options.pause_between_passes = false;
     }
+
+    // Check the passes whether or not the design is on the bed, so that a job that could never be cut
+    // is an error wherever it is placed, and isn't half sent.
+    first_enabled_pass(tool_passes).map_err(SendToDeviceError::GenerateHpglError)?;
+
+    Ok(PlacedJob {
+        paths_in_mm,
+        resolved_paths,
+        hpgl_options,
+    })
 }
 
 /// Sends a design file to the printer-like device.
@@ -196,23 +764,450 @@ impl Default for PrintDevice {
 /// * `design_file`: The design to send to the printer-like device.
 /// * `tool_passes`: Passes of the cutting tool.
 /// * `print_device`: The device to send the design to.
-/// * `offset`: How much to move the design by relative to its starting position, in mm, where +x is more right and +y is more down.
+/// * `machine`: The machine that the design will be cut on.
+/// * `options`: Where to put the design and how to trace it and write the job.
 ///
 /// # Returns
-/// `Ok(())` if the file has been sent correctly, otherwise a [`SendToDeviceError`].
+/// Once the file has been sent, the ID that the device gave the job if it gives jobs IDs, otherwise a [`SendToDeviceError`].
 pub fn cut_file(
     design_file: &DesignFile,
     tool_passes: &Vec<ToolPass>,
     print_device: &PrintDevice,
-    offset: Vec2,
+    machine: &Machine,
+    options: &JobOptions,
+) -> Result<Option<String>, SendToDeviceError> {
+    print_device.print(|mut w| generate_pcl_to(&mut w, design_file, tool_passes, machine, options))
+}
+
+/// Generates the PCL job for a design file without sending it anywhere, so that
+/// a job can be checked before anything is cut.
+///
+/// # Arguments
+/// * `design_file`: The design to generate the job for.
+/// * `tool_passes`: Passes of the cutting tool.
+/// * `machine`: The machine that the design will be cut on.
+/// * `options`: Where to put the design and how to trace it and write the job.
+///
+/// # Returns
+/// The PCL bytes that would be sent to the printer-like device, otherwise a [`SendToDeviceError`].
+pub fn generate_pcl(
+    design_file: &DesignFile,
+    tool_passes: &Vec<ToolPass>,
+    machine: &Machine,
+    options: &JobOptions,
+) -> Result<Vec<u8>, SendToDeviceError> {
+    let mut pcl = vec![];
+    generate_pcl_to(&mut pcl, design_file, tool_passes, machine, options)?;
+    Ok(pcl)
+}
+
+/// Generates the PCL job for a design file, writing it out as it goes so that
+/// the whole job never has to be held in memory.
+/// Nothing is written if the job can't be generated.
+///
+/// # Arguments
+/// * `w`: Where to write the PCL.
+/// * `design_file`: The design to generate the job for.
+/// * `tool_passes`: Passes of the cutting tool.
+/// * `machine`: The machine that the design will be cut on.
+/// * `options`: Where to put the design and how to trace it and write the job.
+///
+/// # Returns
+/// `Ok(())` if the whole job was written, otherwise a [`SendToDeviceError`].
+pub fn generate_pcl_to(
+    w: &mut impl Write,
+    design_file: &DesignFile,
+    tool_passes: &Vec<ToolPass>,
+    machine: &Machine,
+    options: &JobOptions,
 ) -> Result<(), SendToDeviceError> {
-    let design_name = design_file.name();
+    let job = place_job(design_file, tool_passes, machine, options)?;
+    let resolved_paths = job.resolved_paths.map_err(SendToDeviceError::PathsOffBed)?;
 
-    let paths = get_paths_grouped_by_colour(&design_file.tree)?;
-    let resolved_paths = resolve_paths(&paths, &tool_passes, offset);
-    let hpgl = generate_hpgl(&resolved_paths, &tool_passes);
-    let pcl = wrap_hpgl_in_pcl(hpgl, &design_name, &tool_passes);
-    print_device.print(&pcl)?;
+    wrap_hpgl_in_pcl_to(
+        w,
+        design_file.name(),
+        tool_passes,
+        machine.profile(),
+        options.metadata.as_ref(),
+        |w| {
+            generate_hpgl_to(
+                w,
+                &resolved_paths,
+                tool_passes,
+                machine.bed(),
+                &job.hpgl_options,
+            )
+        },
+    )
+    .map_err(|err| match err {
+        HpglError::FailedToWrite(err) => SendToDeviceError::FailedToWriteToPrinter(err),
+        err => SendToDeviceError::GenerateHpglError(err),
+    })
+}
 
-    Ok(())
+/// Sends a job to a printer-like device that moves the toolhead around the edge of a design
+/// without cutting it, so that where the design will be cut can be checked first.
+///
+/// # Arguments
+/// * `design_file`: The design to frame.
+/// * `tool_passes`: Passes of the cutting tool, only the paths cut by enabled passes are framed.
+/// * `print_device`: The device to send the job to.
+/// * `machine`: The machine that the design will be cut on.
+/// * `options`: Where to put the design and how to trace it, how the job is written isn't used for a frame.
+/// * `repeats`: How many times to go around the edge of the design.
+///
+/// # Returns
+/// Once the frame has been sent, the ID that the device gave the job if it gives jobs IDs, otherwise a [`SendToDeviceError`].
+pub fn frame_file(
+    design_file: &DesignFile,
+    tool_passes: &Vec<ToolPass>,
+    print_device: &PrintDevice,
+    machine: &Machine,
+    options: &JobOptions,
+    repeats: usize,
+) -> Result<Option<String>, SendToDeviceError> {
+    let pcl = generate_frame_pcl(design_file, tool_passes, machine, options, repeats)?;
+    print_device.print(|w| {
+        w.write_all(&pcl)
+            .map_err(SendToDeviceError::FailedToWriteToPrinter)
+    })
+}
+
+/// Generates the PCL job that moves the toolhead around the edge of a design without cutting it.
+///
+/// # Arguments
+/// * `design_file`: The design to frame.
+/// * `tool_passes`: Passes of the cutting tool, only the paths cut by enabled passes are framed.
+/// * `machine`: The machine that the design will be cut on.
+/// * `options`: Where to put the design and how to trace it, how the job is written isn't used for a frame.
+/// * `repeats`: How many times to go around the edge of the design.
+///
+/// # Returns
+/// The PCL bytes that would be sent to the printer-like device, otherwise a [`SendToDeviceError`].
+pub fn generate_frame_pcl(
+    design_file: &DesignFile,
+    tool_passes: &Vec<ToolPass>,
+    machine: &Machine,
+    options: &JobOptions,
+    repeats: usize,
+) -> Result<Vec<u8>, SendToDeviceError> {
+    let mut paths_in_mm = trace_design(
+        &design_file.tree,
+        &design_file.svg,
+        tool_passes,
+        options.grouping,
+        options.hatch.as_ref(),
+        options.colour_snap_tolerance,
+    )?;
+    paths_in_mm.retain(|colour, _| {
+        tool_passes
+            .iter()
+            .any(|pass| *pass.enabled() && pass.colour() == colour)
+    });
+    let bounds =
+        Bounds::of_paths(&paths_in_mm, options.offset).ok_or(SendToDeviceError::NothingToFrame)?;
+    let first_pen =
+        first_enabled_pass(tool_passes).map_err(SendToDeviceError::GenerateHpglError)?;
+    let hpgl = generate_frame_hpgl(&bounds, first_pen, machine.bed(), repeats)
+        .map_err(SendToDeviceError::FrameOffBed)?;
+
+    Ok(wrap_hpgl_in_pcl(
+        hpgl.as_bytes(),
+        &format!("{} (frame)", design_file.name()),
+        tool_passes,
+        machine.profile(),
+        None,
+    ))
+}
+
+/// A design placed somewhere on the bed, so that several designs can be cut together in one job.
+pub struct PlacedDesign {
+    /// The SVG tree of the design.
+    pub tree: usvg::Tree,
+    /// How much to move the design by relative to its starting position, in mm, where +x is more right and +y is more down.
+    pub offset: Vec2,
+    /// How to rotate, scale or flip the design, in mm, applied before it is moved by the offset.
+    pub transform: usvg::Transform,
+}
+
+/// Traces several placed designs and combines their paths, so that they can be cut in one job.
+/// The paths of each colour are cut in the order that the designs are given, paths are cut by the pass of their stroke colour.
+///
+/// # Arguments
+/// * `designs`: The designs to combine.
+/// * `tool_passes`: Passes of the cutting tool.
+/// * `hatch`: If provided, filled shapes whose fill colour matches a tool pass are hatched and the hatching is traced too.
+/// * `colour_snap_tolerance`: If provided, paths whose colour is this close to a tool pass's colour are traced
+/// as if they were that colour, see [`paths::snap_colours_to`].
+///
+/// # Returns
+/// The traced paths of every design, in their place on the bed and grouped by colour, otherwise a [`SendToDeviceError`].
+/// The paths are already offset, so they should be placed on the bed with an offset of `(0.0, 0.0)`.
+pub fn combine_placed_designs(
+    designs: &[PlacedDesign],
+    tool_passes: &Vec<ToolPass>,
+    hatch: Option<&HatchOptions>,
+    colour_snap_tolerance: Option<u8>,
+) -> Result<BTreeMap<PathColour, Vec<PathInMM>>, SendToDeviceError> {
+    let mut combined: BTreeMap<PathColour, Vec<PathInMM>> = BTreeMap::new();
+
+    for design in designs {
+        let paths_in_mm = trace_design(
+            &design.tree,
+            &[],
+            tool_passes,
+            GroupingKey::StrokeColour,
+            hatch,
+            colour_snap_tolerance,
+        )?;
+        let usvg::Transform {
+            sx,
+            kx,
+            ky,
+            sy,
+            tx,
+            ty,
+        } = design.transform;
+        let (ox, oy) = design.offset;
+
+        for (colour, paths) in paths_in_mm {
+            combined
+                .entry(colour)
+                .or_default()
+                .extend(paths.into_iter().map(|path| {
+                    path.into_iter()
+                        .map(|PointInMillimeters { x, y }| PointInMillimeters {
+                            x: sx * x + kx * y + tx + ox,
+                            y: ky * x + sy * y + ty + oy,
+                        })
+                        .collect::<PathInMM>()
+                }));
+        }
+    }
+
+    Ok(combined)
+}
+
+/// Traces the paths of a design that the tool passes will cut, in mm, before they are placed on the bed.
+/// Points along straight lines are dropped, see [`paths::simplify_collinear`].
+///
+/// # Arguments
+/// * `tree`: The SVG tree of the design to trace.
+/// * `svg`: The bytes of the SVG, needed to group paths by layer label or CSS class.
+/// * `tool_passes`: Passes of the cutting tool.
+/// * `grouping`: What paths are grouped by to choose the tool pass that cuts them.
+/// * `hatch`: If provided, filled shapes whose fill colour matches a tool pass are hatched and the hatching is traced too.
+/// * `colour_snap_tolerance`: If provided, paths whose colour is this close to a tool pass's colour are traced
+/// as if they were that colour, see [`paths::snap_colours_to`].
+///
+/// # Returns
+/// The traced paths, grouped by colour, otherwise a [`SendToDeviceError`].
+fn trace_design(
+    tree: &usvg::Tree,
+    svg: &[u8],
+    tool_passes: &Vec<ToolPass>,
+    grouping: GroupingKey,
+    hatch: Option<&HatchOptions>,
+    colour_snap_tolerance: Option<u8>,
+) -> Result<BTreeMap<PathColour, Vec<PathInMM>>, SendToDeviceError> {
+    let mut paths = match grouping {
+        GroupingKey::StrokeColour => get_paths_grouped_by_colour(tree)?,
+        // Paths grouped by anything else are given the colour of the pass that cuts them.
+        grouping => assign_groups_to_passes(group_paths_by(tree, svg, grouping), tool_passes),
+    };
+    if let Some(tolerance) = colour_snap_tolerance {
+        let colours: Vec<PathColour> = tool_passes.iter().map(|pass| *pass.colour()).collect();
+        paths = snap_colours_to(paths, &colours, tolerance);
+    }
+    let mut paths_in_mm = trace_paths(&paths, tool_passes);
+    #[cfg(feature = "kerf")]
+    kerf::compensate_for_kerf(&mut paths_in_mm, tool_passes);
+    if let Some(hatch) = hatch {
+        for (colour, lines) in hatch_fills(tree, tool_passes, hatch) {
+            paths_in_mm.entry(colour).or_default().extend(lines);
+        }
+    }
+    for path in paths_in_mm.values_mut().flatten() {
+        simplify_collinear(path, COLLINEAR_TOLERANCE_MM);
+    }
+
+    Ok(paths_in_mm)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use resvg::usvg;
+
+    use super::{
+        build_job, combine_placed_designs, Bounds, DesignFile, GroupingKey, HpglOptions,
+        JobOptions, Machine, PlacedDesign, PrintBed, SendToDeviceError, ToolPass,
+    };
+    use crate::{
+        default_passes::default_passes,
+        svg::{layer_visibility, parse_svg},
+        Colour,
+    };
+
+    /// Parses a design of a 10mm square.
+    fn square() -> usvg::Tree {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="20mm" height="20mm" viewBox="0 0 20 20">
+            <rect x="0" y="0" width="10" height="10" fill="none" stroke="black" stroke-width="0.1"/>
+        </svg>"#;
+        parse_svg(&PathBuf::from("square.svg"), svg.as_bytes()).expect("SVG should parse")
+    }
+
+    #[test]
+    fn designs_are_loaded_from_bytes() {
+        let logo = include_bytes!("../../logo.svg");
+        let design_file = DesignFile::from_bytes("logo".to_string(), logo, &PrintBed::GCC_SPIRIT)
+            .expect("Logo should load");
+        assert_eq!(design_file.name(), "logo");
+        // The logo is 512 SVG units square, at 96 units per inch.
+        let round = |mm: f32| (mm * 100.0).round() / 100.0;
+        assert_eq!(
+            (round(design_file.width_mm), round(design_file.height_mm)),
+            (135.47, 135.47)
+        );
+        assert_eq!(design_file.layers, layer_visibility(logo));
+        assert_eq!(design_file.warnings.ignored_images, 0);
+
+        let err = DesignFile::from_bytes("broken".to_string(), b"<svg", &PrintBed::GCC_SPIRIT)
+            .err()
+            .expect("Broken SVG shouldn't load");
+        assert_eq!(
+            matches!(err, SendToDeviceError::ErrorParsingSvg(_)),
+            true,
+            "Broken SVG should be a parsing error, got {err:?}"
+        );
+    }
+
+    #[test]
+    fn jobs_are_built_without_a_device() {
+        let design_file = DesignFile {
+            name: "square".to_string(),
+            tree: square(),
+            svg: vec![],
+            width_mm: 20.0,
+            height_mm: 20.0,
+            layers: vec![],
+            warnings: Default::default(),
+        };
+        let passes = default_passes();
+        let build = |offset| {
+            build_job(
+                &design_file,
+                &passes,
+                &Machine::GCC_SPIRIT,
+                &JobOptions {
+                    offset,
+                    ..JobOptions::default()
+                },
+            )
+            .expect("Job should build")
+        };
+
+        let job = build((0.0, 0.0));
+        assert_eq!(job.statistics.paths_cut, 1);
+        assert_eq!(job.statistics.cut_length_mm.round(), 40.0);
+        assert_eq!(job.paths_off_bed, vec![]);
+        let hpgl = job.hpgl.expect("Job on the bed should have HPGL");
+        assert_eq!(hpgl.starts_with("IN;"), true, "HPGL should start the job");
+
+        // A job off the bed still reports what it would cut, along with the paths that are off the bed.
+        let job = build((-1000.0, 0.0));
+        assert_eq!(job.hpgl, None);
+        assert_eq!(job.statistics.paths_cut, 1);
+        assert_eq!(job.paths_off_bed.len(), 1);
+
+        // The Spirit can't pause, so the job is built as it would be sent, without pausing.
+        let job = build_job(
+            &design_file,
+            &passes,
+            &Machine::GCC_SPIRIT,
+            &JobOptions {
+                hpgl_options: HpglOptions {
+                    pause_between_passes: true,
+                    ..HpglOptions::default()
+                },
+                ..JobOptions::default()
+            },
+        )
+        .expect("Job should build");
+        assert_eq!(job.hpgl, build((0.0, 0.0)).hpgl);
+    }
+
+    #[test]
+    fn jobs_cut_paths_by_the_chosen_grouping() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape"
+            width="20mm" height="20mm" viewBox="0 0 20 20">
+            <g id="layer1" inkscape:groupmode="layer" inkscape:label="cut">
+                <rect x="0" y="0" width="10" height="10" fill="none" stroke="black" stroke-width="0.1"/>
+            </g>
+            <g id="layer2" inkscape:groupmode="layer" inkscape:label="engrave">
+                <rect x="0" y="0" width="5" height="5" fill="none" stroke="black" stroke-width="0.1"/>
+            </g>
+        </svg>"#;
+        let design_file =
+            DesignFile::from_bytes("layers".to_string(), svg.as_bytes(), &PrintBed::GCC_SPIRIT)
+                .expect("SVG should load");
+        let mut cut = ToolPass::new("Cut".to_string(), 255, 0, 0, 100, 20, false, 400);
+        cut.set_group(Some("cut".to_string()));
+        let passes = vec![cut];
+        let build = |grouping| {
+            build_job(
+                &design_file,
+                &passes,
+                &Machine::GCC_SPIRIT,
+                &JobOptions {
+                    grouping,
+                    ..JobOptions::default()
+                },
+            )
+            .expect("Job should build")
+        };
+
+        // Only the layer with the pass's name is cut, even though the paths are the same colour.
+        let job = build(GroupingKey::LayerName);
+        assert_eq!(job.statistics.paths_cut, 1);
+        assert_eq!(job.statistics.cut_length_mm.round(), 40.0);
+
+        // Grouped by stroke colour, the pass's colour isn't in the design.
+        let job = build(GroupingKey::StrokeColour);
+        assert_eq!(job.statistics.paths_cut, 0);
+    }
+
+    #[test]
+    fn placed_designs_are_combined_in_place() {
+        let designs = [
+            PlacedDesign {
+                tree: square(),
+                offset: (0.0, 0.0),
+                transform: usvg::Transform::default(),
+            },
+            PlacedDesign {
+                tree: square(),
+                offset: (20.0, 5.0),
+                transform: usvg::Transform::from_scale(2.0, 2.0),
+            },
+        ];
+
+        let combined = combine_placed_designs(&designs, &default_passes(), None, None)
+            .expect("Designs should combine");
+        let squares = &combined[&Colour([0, 0, 0])];
+        assert_eq!(squares.len(), 2);
+
+        // The corners of each square, rounded to the nearest micron.
+        let corners = |index: usize| {
+            let paths = [(Colour([0, 0, 0]), vec![squares[index].clone()])].into();
+            let Bounds { min, max } =
+                Bounds::of_paths(&paths, (0.0, 0.0)).expect("Square should have bounds");
+            [min.x, min.y, max.x, max.y].map(|value| (value * 1000.0).round() / 1000.0)
+        };
+        assert_eq!(corners(0), [0.0, 0.0, 10.0, 10.0]);
+        // The second square is scaled up and then moved.
+        assert_eq!(corners(1), [20.0, 5.0, 40.0, 25.0]);
+    }
 }
@@ -0,0 +1,195 @@
+//! `machine`
+//!
+//! Describes the differences between the devices that we can send jobs to.
+
+use ascii::AsciiChar;
+
+use crate::{BedMode, PrintBed, PrintBedError};
+
+/// How a device expects the PCL around a job to be written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceProfile {
+    /// The resolution of rasterisation, in DPI.
+    raster_resolution_dpi: u64,
+    /// The DPI equivalent of a single machine unit.
+    unit_of_measure_dpi: u64,
+    /// Vendor-specific PCL sent after entering PCL mode, before the job is set up.
+    init_block: &'static str,
+    /// The character marking a pen as enabled in the pen table.
+    pen_enabled: AsciiChar,
+    /// The character marking a pen as disabled in the pen table.
+    pen_disabled: AsciiChar,
+    /// The most characters of the job's filename that the device can show.
+    max_filename_length: usize,
+    /// Whether the device pauses for the operator when it is sent Not Ready (`NR`).
+    supports_pause: bool,
+}
+
+impl DeviceProfile {
+    /// The profile of the GCC Spirit.
+    pub const GCC_SPIRIT: DeviceProfile = DeviceProfile::new(
+        508,
+        508,
+        "\x1b!r1000I\x1b!r1000K\x1b!r500P",
+        AsciiChar::SOX,
+        AsciiChar::Null,
+        31,
+        false,
+    );
+
+    /// Creates a new device profile.
+    ///
+    /// # Arguments
+    /// * `raster_resolution_dpi`: The resolution of rasterisation, in DPI.
+    /// * `unit_of_measure_dpi`: The DPI equivalent of a single machine unit.
+    /// * `init_block`: Vendor-specific PCL sent after entering PCL mode, before the job is set up.
+    /// * `pen_enabled`: The character marking a pen as enabled in the pen table.
+    /// * `pen_disabled`: The character marking a pen as disabled in the pen table.
+    /// * `max_filename_length`: The most characters of the job's filename that the device can show.
+    /// * `supports_pause`: Whether the device pauses for the operator when it is sent Not Ready (`NR`).
+    ///
+    /// # Returns
+    /// The device profile.
+    pub const fn new(
+        raster_resolution_dpi: u64,
+        unit_of_measure_dpi: u64,
+        init_block: &'static str,
+        pen_enabled: AsciiChar,
+        pen_disabled: AsciiChar,
+        max_filename_length: usize,
+        supports_pause: bool,
+    ) -> Self {
+        Self {
+            raster_resolution_dpi,
+            unit_of_measure_dpi,
+            init_block,
+            pen_enabled,
+            pen_disabled,
+            max_filename_length,
+            supports_pause,
+        }
+    }
+
+    /// Gets the resolution of rasterisation.
+    ///
+    /// # Returns
+    /// The raster resolution, in DPI.
+    pub fn raster_resolution_dpi(&self) -> u64 {
+        self.raster_resolution_dpi
+    }
+
+    /// Gets the DPI equivalent of a single machine unit.
+    ///
+    /// # Returns
+    /// The unit of measure, in DPI.
+    pub fn unit_of_measure_dpi(&self) -> u64 {
+        self.unit_of_measure_dpi
+    }
+
+    /// Gets the vendor-specific PCL sent before the job is set up.
+    ///
+    /// # Returns
+    /// The PCL init block.
+    pub fn init_block(&self) -> &str {
+        self.init_block
+    }
+
+    /// Gets the character that marks a pen as enabled or disabled in the pen table.
+    ///
+    /// # Arguments
+    /// * `enabled`: Whether the pen is enabled.
+    ///
+    /// # Returns
+    /// The character for the pen.
+    pub fn pen_enable_char(&self, enabled: bool) -> AsciiChar {
+        if enabled {
+            self.pen_enabled
+        } else {
+            self.pen_disabled
+        }
+    }
+
+    /// Gets the most characters of the job's filename that the device can show.
+    ///
+    /// # Returns
+    /// The maximum filename length.
+    pub fn max_filename_length(&self) -> usize {
+        self.max_filename_length
+    }
+
+    /// Gets whether the device pauses for the operator when it is sent Not Ready (`NR`),
+    /// which [`crate::HpglOptions::pause_between_passes`] relies on.
+    /// The GCC Spirit isn't known to, so its profile doesn't.
+    ///
+    /// # Returns
+    /// `true` if the device can pause between passes.
+    pub fn supports_pause(&self) -> bool {
+        self.supports_pause
+    }
+}
+
+impl Default for DeviceProfile {
+    fn default() -> Self {
+        Self::GCC_SPIRIT
+    }
+}
+
+/// A device that jobs can be cut on, its bed and how it expects jobs to be sent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Machine {
+    /// The area the toolhead can move over.
+    bed: PrintBed,
+    /// How the device expects the PCL around a job to be written.
+    profile: DeviceProfile,
+}
+
+impl Machine {
+    /// The GCC Spirit.
+    pub const GCC_SPIRIT: Machine = Machine::new(PrintBed::GCC_SPIRIT, DeviceProfile::GCC_SPIRIT);
+
+    /// Creates a new machine.
+    ///
+    /// # Arguments
+    /// * `bed`: The area the toolhead can move over.
+    /// * `profile`: How the device expects the PCL around a job to be written.
+    ///
+    /// # Returns
+    /// The machine.
+    pub const fn new(bed: PrintBed, profile: DeviceProfile) -> Self {
+        Self { bed, profile }
+    }
+
+    /// Gets the bed of the machine.
+    ///
+    /// # Returns
+    /// The bed.
+    pub fn bed(&self) -> &PrintBed {
+        &self.bed
+    }
+
+    /// Sets how the Y axis of the machine moves the work, e.g. to use a rotary attachment for a job.
+    ///
+    /// # Arguments
+    /// * `mode`: How the Y axis moves the work.
+    ///
+    /// # Returns
+    /// The machine with its bed in that mode, or a [`PrintBedError`] if the mode can't be used.
+    pub fn with_bed_mode(mut self, mode: BedMode) -> Result<Self, PrintBedError> {
+        self.bed = self.bed.with_mode(mode)?;
+        Ok(self)
+    }
+
+    /// Gets the device profile of the machine.
+    ///
+    /// # Returns
+    /// The device profile.
+    pub fn profile(&self) -> &DeviceProfile {
+        &self.profile
+    }
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::GCC_SPIRIT
+    }
+}
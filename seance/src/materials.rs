@@ -0,0 +1,231 @@
+//! `materials`
+//!
+//! Saved tool pass settings for the materials that are commonly cut, so that they don't need to be typed in each time.
+
+use std::{fmt, fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ToolPass;
+
+/// A named set of tool passes for cutting a material, e.g. "3mm plywood".
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MaterialPreset {
+    /// The name of the preset, shown to the user.
+    pub name: String,
+    /// How thick the material is, in mm, if known.
+    #[serde(default)]
+    pub thickness_mm: Option<f32>,
+    /// The tool passes to use.
+    pub passes: Vec<ToolPass>,
+}
+
+impl MaterialPreset {
+    /// Creates a new [`MaterialPreset`].
+    ///
+    /// # Arguments
+    /// * `name`: The name of the preset, shown to the user.
+    /// * `thickness_mm`: How thick the material is, in mm, if known.
+    /// * `passes`: The tool passes to use.
+    ///
+    /// # Returns
+    /// A new [`MaterialPreset`].
+    pub fn new(name: String, thickness_mm: Option<f32>, passes: Vec<ToolPass>) -> Self {
+        Self {
+            name,
+            thickness_mm,
+            passes,
+        }
+    }
+
+    /// Applies the settings of the preset onto a list of tool passes.
+    /// Each pass takes the power, speed, PPI and raster setting of the preset's pass with the same colour,
+    /// the names, colours and order of the passes are kept so that the user's colour assignments survive.
+    ///
+    /// # Arguments
+    /// * `passes`: The tool passes to update.
+    ///
+    /// # Returns
+    /// The number of passes that had a pass of the same colour in the preset.
+    pub fn apply_to(&self, passes: &mut [ToolPass]) -> usize {
+        let mut applied = 0;
+        for pass in passes.iter_mut() {
+            let Some(preset_pass) = self
+                .passes
+                .iter()
+                .find(|preset_pass| preset_pass.colour() == pass.colour())
+            else {
+                continue;
+            };
+
+            pass.set_power(*preset_pass.power());
+            pass.set_speed(*preset_pass.speed());
+            pass.set_ppi(*preset_pass.ppi());
+            pass.set_rast(*preset_pass.rast());
+            applied += 1;
+        }
+
+        applied
+    }
+}
+
+/// Errors that can occur when loading or saving a [`MaterialLibrary`].
+#[derive(Debug)]
+pub enum MaterialLibraryError {
+    /// The library file could not be read or written.
+    Io(io::Error),
+    /// The library file is not valid.
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for MaterialLibraryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MaterialLibraryError::Io(err) => write!(f, "could not access material library: {err}"),
+            MaterialLibraryError::Json(err) => write!(f, "material library is not valid: {err}"),
+        }
+    }
+}
+
+/// A collection of material presets, which can be saved to and loaded from a file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MaterialLibrary {
+    /// The presets in the library.
+    pub presets: Vec<MaterialPreset>,
+}
+
+impl MaterialLibrary {
+    /// Gets the library of presets that Seance comes with, as a starting point for common materials.
+    /// The settings are a guide only, materials vary, so test on an offcut first.
+    ///
+    /// # Returns
+    /// The built-in library.
+    pub fn built_in() -> Self {
+        let preset = |name: &str, thickness_mm, cut: (u64, u64), engrave: (u64, u64)| {
+            MaterialPreset::new(
+                name.to_string(),
+                Some(thickness_mm),
+                vec![
+                    ToolPass::new("Cut".to_string(), 0, 0, 0, cut.0, cut.1, false, 400),
+                    ToolPass::new(
+                        "Engrave".to_string(),
+                        255,
+                        0,
+                        0,
+                        engrave.0,
+                        engrave.1,
+                        false,
+                        400,
+                    ),
+                ],
+            )
+        };
+
+        Self {
+            presets: vec![
+                preset("3mm plywood", 3.0, (100, 20), (30, 100)),
+                preset("6mm plywood", 6.0, (100, 8), (30, 100)),
+                preset("3mm MDF", 3.0, (100, 15), (25, 100)),
+                preset("3mm acrylic", 3.0, (100, 12), (20, 100)),
+                preset("Card", 0.5, (30, 60), (10, 100)),
+            ],
+        }
+    }
+
+    /// Finds a preset by its name.
+    ///
+    /// # Arguments
+    /// * `name`: The name of the preset.
+    ///
+    /// # Returns
+    /// The preset, if there is one with that name.
+    pub fn find(&self, name: &str) -> Option<&MaterialPreset> {
+        self.presets.iter().find(|preset| preset.name == name)
+    }
+
+    /// Loads a library from a JSON file.
+    ///
+    /// # Arguments
+    /// * `path`: The path of the file.
+    ///
+    /// # Returns
+    /// The library if it could be loaded, otherwise an error.
+    pub fn load(path: &Path) -> Result<Self, MaterialLibraryError> {
+        let json = fs::read_to_string(path).map_err(MaterialLibraryError::Io)?;
+        serde_json::from_str(&json).map_err(MaterialLibraryError::Json)
+    }
+
+    /// Saves the library to a JSON file, replacing the file if it exists.
+    ///
+    /// # Arguments
+    /// * `path`: The path of the file.
+    ///
+    /// # Returns
+    /// An error if the library could not be saved.
+    pub fn save(&self, path: &Path) -> Result<(), MaterialLibraryError> {
+        let json = serde_json::to_string_pretty(self).map_err(MaterialLibraryError::Json)?;
+        fs::write(path, json).map_err(MaterialLibraryError::Io)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MaterialLibrary, MaterialPreset};
+    use crate::{default_passes::default_passes, Colour, ToolPass};
+
+    #[test]
+    fn libraries_round_trip() {
+        let library = MaterialLibrary::built_in();
+        let json = serde_json::to_string(&library).expect("Library should serialize");
+        let loaded: MaterialLibrary = serde_json::from_str(&json).expect("Library should load");
+        assert_eq!(loaded, library);
+
+        // Presets saved without a thickness still load.
+        let preset: MaterialPreset =
+            serde_json::from_str(r#"{"name":"Felt","passes":[]}"#).expect("Preset should load");
+        assert_eq!(
+            preset,
+            MaterialPreset::new("Felt".to_string(), None, vec![])
+        );
+    }
+
+    #[test]
+    fn presets_are_applied_by_colour() {
+        let mut passes = default_passes();
+        passes[1].set_colour(Colour([0, 0, 0]));
+        passes[0].set_colour(Colour([255, 0, 0]));
+
+        let preset = MaterialPreset::new(
+            "Test".to_string(),
+            Some(3.0),
+            vec![
+                ToolPass::new("Cut".to_string(), 0, 0, 0, 90, 10, false, 400),
+                ToolPass::new("Engrave".to_string(), 255, 0, 0, 30, 80, true, 400),
+            ],
+        );
+        assert_eq!(preset.apply_to(&mut passes), 2);
+
+        let settings: Vec<_> = passes
+            .iter()
+            .map(|pass| {
+                (
+                    pass.name(),
+                    *pass.colour(),
+                    *pass.power(),
+                    *pass.speed(),
+                    *pass.rast(),
+                )
+            })
+            .collect();
+        let unchanged = default_passes();
+        assert_eq!(
+            settings,
+            vec![
+                ("Pass 1", Colour([255, 0, 0]), 30, 80, true),
+                ("Pass 2", Colour([0, 0, 0]), 90, 10, false),
+                ("Pass 3", *unchanged[2].colour(), 100, 20, false),
+                ("Pass 4", *unchanged[3].colour(), 100, 20, false),
+            ]
+        );
+    }
+}
@@ -3,37 +3,155 @@
 //! Provides utilities for tracing paths, turning them into a set of points that
 //! the toolhead moves through.
 
-use std::collections::HashMap;
+use std::{collections::BTreeMap, fmt::Write};
 
 use crate::Vec2;
 use lyon_algorithms::geom::euclid::UnknownUnit;
 use lyon_algorithms::path::math::Point;
-use lyon_algorithms::path::PathSlice;
+use lyon_algorithms::path::{PathEvent, PathSlice};
 use lyon_algorithms::walk::{walk_along_path, RegularPattern, WalkerEvent};
 use resvg::usvg;
 use usvg::Path;
 
-use crate::{ToolPass, BED_HEIGHT_MM};
+use crate::{
+    arcs::fit_arcs, bed::PlacePointError, svg::SVG_UNITS_PER_MM, Colour, PrintBed, ToolPass,
+};
 
 /// The number of mm that are moved per unit that the plotter is instructed to move.
 /// This is the HPGL/2 default specified in the HPGL/2 specification.
-const MM_PER_PLOTTER_UNIT: f32 = 0.025;
+pub(crate) const MM_PER_PLOTTER_UNIT: f32 = 0.025;
+
+/// How far, in mm, a point may be from a straight line and still be dropped from it by [`simplify_collinear`].
+/// This is less than a plotter unit, so the points that are dropped couldn't be told apart from the line.
+pub(crate) const COLLINEAR_TOLERANCE_MM: f32 = MM_PER_PLOTTER_UNIT / 2.0;
+
+/// The width of the lines drawn when writing paths out as an SVG, in mm.
+const SVG_STROKE_WIDTH_MM: f32 = 0.1;
 
 /// This is a point that is along a path that we wish to trace with the tool.
 /// The units are HPGL/2 units, which are rather nebulous and may vary from
 /// machine to machine in terms of their translation to mm.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct ResolvedPoint {
     /// Horizontal axis position.
-    pub x: i16,
+    pub x: i32,
     /// Vertical axis position.
-    pub y: i16,
+    pub y: i32,
+}
+
+/// A path that couldn't be placed on the bed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathPlacementError {
+    /// The colour of the path.
+    pub colour: PathColour,
+    /// Which of the paths of that colour it is.
+    pub path_index: usize,
+    /// Why the first point of the path that couldn't be placed couldn't be.
+    pub error: PlacePointError,
+}
+
+/// A single movement of the toolhead along a path.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResolvedSegment {
+    /// Move in a straight line to a point.
+    Line(ResolvedPoint),
+    /// Move along a circular arc, starting from the current position.
+    Arc {
+        /// The centre of the circle the arc is part of.
+        centre: ResolvedPoint,
+        /// How far around the circle to move, in degrees. Positive values move
+        /// anticlockwise in the device's coordinate system.
+        sweep_degrees: f32,
+        /// Where the arc finishes.
+        end: ResolvedPoint,
+    },
+}
+
+impl ResolvedSegment {
+    /// Gets where the toolhead will be once this segment has been traced.
+    ///
+    /// # Returns
+    /// The end point of the segment.
+    pub fn end(&self) -> ResolvedPoint {
+        match self {
+            ResolvedSegment::Line(point) => *point,
+            ResolvedSegment::Arc { end, .. } => *end,
+        }
+    }
 }
-/// A path that the toolhead will move through, comprised of a series of points in-order.
-pub type ResolvedPath = Vec<ResolvedPoint>;
+
+/// A path that the toolhead will move through, comprised of a series of segments in-order.
+/// The first segment is where the path starts.
+pub type ResolvedPath = Vec<ResolvedSegment>;
 
 /// The colour associated with a path.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct PathColour(pub [u8; 3]);
+pub type PathColour = Colour;
+
+/// A path that has been traced into a series of points, in mm, that the toolhead will move through.
+pub type PathInMM = Vec<PointInMillimeters>;
+
+/// The smallest rectangle that contains a set of points, in mm.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    /// The top-left corner.
+    pub min: PointInMillimeters,
+    /// The bottom-right corner.
+    pub max: PointInMillimeters,
+}
+
+impl Bounds {
+    /// Works out the bounds of traced paths, once they have been moved by an offset.
+    ///
+    /// # Arguments
+    /// * `paths_in_mm`: The traced paths, grouped by colour.
+    /// * `offset`: How much to move the paths by relative to their starting position, in mm, where +x is more right and +y is more down.
+    ///
+    /// # Returns
+    /// The bounds of the offset paths, or `None` if there aren't any points.
+    pub fn of_paths(
+        paths_in_mm: &BTreeMap<PathColour, Vec<PathInMM>>,
+        offset: Vec2,
+    ) -> Option<Self> {
+        let mut points = paths_in_mm.values().flatten().flatten();
+        let first = points.next()?;
+        let (min, max) = points.fold((*first, *first), |(min, max), point| {
+            (
+                PointInMillimeters {
+                    x: min.x.min(point.x),
+                    y: min.y.min(point.y),
+                },
+                PointInMillimeters {
+                    x: max.x.max(point.x),
+                    y: max.y.max(point.y),
+                },
+            )
+        });
+
+        let offset_by = |point: PointInMillimeters| {
+            let mut point: Point = point.into();
+            offset_point(&mut point, offset);
+            point.into()
+        };
+        Some(Self {
+            min: offset_by(min),
+            max: offset_by(max),
+        })
+    }
+
+    /// Gets the corners of the bounds, clockwise from the top-left.
+    ///
+    /// # Returns
+    /// The corners, in mm.
+    pub fn corners(&self) -> [PointInMillimeters; 4] {
+        let Self { min, max } = *self;
+        [
+            min,
+            PointInMillimeters { x: max.x, y: min.y },
+            max,
+            PointInMillimeters { x: min.x, y: max.y },
+        ]
+    }
+}
 
 /// Takes a set of SVG paths grouped by their colour and traces them, turning
 /// the paths into a set of points for the toolhead to move through.
@@ -44,117 +162,586 @@ pub struct PathColour(pub [u8; 3]);
 /// * `offset`: How much to move the design by relative to its starting position, in mm, where +x is more right and +y is more down.
 ///
 /// # Returns
-/// A set of resolved paths, grouped by path colour.
+/// A set of resolved paths, grouped by path colour, or the paths that couldn't be placed on the bed.
 pub fn resolve_paths(
-    paths_grouped_by_colour: &HashMap<PathColour, Vec<Box<Path>>>,
+    paths_grouped_by_colour: &BTreeMap<PathColour, Vec<Box<Path>>>,
     tool_passes: &Vec<ToolPass>,
     offset: Vec2,
-) -> HashMap<PathColour, Vec<ResolvedPath>> {
-    let mut resolved_paths: HashMap<PathColour, Vec<ResolvedPath>> = HashMap::new();
+    bed: &PrintBed,
+) -> Result<BTreeMap<PathColour, Vec<ResolvedPath>>, Vec<PathPlacementError>> {
+    let paths_in_mm = trace_paths(paths_grouped_by_colour, tool_passes);
+    convert_points_to_plotter_units(&paths_in_mm, offset, bed)
+}
+
+/// Keeps only the paths whose colour matches an enabled tool pass, which are the
+/// paths that will actually be cut.
+///
+/// # Arguments
+/// * `paths_grouped_by_colour`: The paths, grouped by their colour.
+/// * `tool_passes`: The toolhead passes to be done.
+///
+/// # Returns
+/// The paths that will be cut, grouped by their colour.
+pub fn filter_paths_to_tool_passes(
+    paths_grouped_by_colour: &BTreeMap<PathColour, Vec<Box<Path>>>,
+    tool_passes: &[ToolPass],
+) -> BTreeMap<PathColour, Vec<Box<Path>>> {
+    paths_grouped_by_colour
+        .iter()
+        .filter(|(colour, _)| {
+            tool_passes
+                .iter()
+                .any(|pass| *pass.enabled() && pass.colour() == *colour)
+        })
+        .map(|(colour, paths)| (*colour, paths.clone()))
+        .collect()
+}
+
+/// Moves paths whose colour is nearly, but not exactly, one of the given colours over to that colour.
+/// Design tools sometimes write e.g. `#000001` where black was meant, which wouldn't match a black tool pass.
+///
+/// # Arguments
+/// * `paths_grouped_by_colour`: The paths, grouped by their colour.
+/// * `colours`: The colours to snap to, usually those of the tool passes.
+/// * `tolerance`: The furthest that a colour can be from one of `colours` and be snapped to it,
+/// as the distance between them in RGB space.
+///
+/// # Returns
+/// The paths, grouped by their snapped colour. Paths that are not near any of `colours` keep their own colour.
+pub fn snap_colours_to<T>(
+    paths_grouped_by_colour: BTreeMap<PathColour, Vec<T>>,
+    colours: &[PathColour],
+    tolerance: u8,
+) -> BTreeMap<PathColour, Vec<T>> {
+    let mut snapped: BTreeMap<PathColour, Vec<T>> = BTreeMap::new();
+
+    for (colour, paths) in paths_grouped_by_colour {
+        let snapped_colour = if colours.contains(&colour) {
+            colour
+        } else {
+            colours
+                .iter()
+                .map(|target| (*target, colour_distance(colour, *target)))
+                .filter(|(_, distance)| *distance <= f32::from(tolerance))
+                .min_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map_or(colour, |(target, _)| {
+                    log::info!(
+                        "Snapping {} paths coloured {colour} to {target}",
+                        paths.len()
+                    );
+                    target
+                })
+        };
+        snapped.entry(snapped_colour).or_default().extend(paths);
+    }
+
+    snapped
+}
+
+/// Works out how far apart two colours are.
+///
+/// # Arguments
+/// * `a`: One of the colours.
+/// * `b`: The other colour.
+///
+/// # Returns
+/// The distance between the colours in RGB space.
+fn colour_distance(a: PathColour, b: PathColour) -> f32 {
+    a.0.iter()
+        .zip(b.0.iter())
+        .map(|(a, b)| (f32::from(*a) - f32::from(*b)).powi(2))
+        .sum::<f32>()
+        .sqrt()
+}
+
+/// Takes a set of SVG paths grouped by their colour and traces them into points in mm.
+/// Only paths with a colour that matches one of the tool passes are traced.
+///
+/// # Arguments
+/// * `paths_grouped_by_colour`: The paths to be traced, grouped by their colour.
+/// * `tool_passes`: The toolhead passes to be done.
+///
+/// # Returns
+/// A set of traced paths in mm, grouped by path colour.
+pub fn trace_paths(
+    paths_grouped_by_colour: &BTreeMap<PathColour, Vec<Box<Path>>>,
+    tool_passes: &Vec<ToolPass>,
+) -> BTreeMap<PathColour, Vec<PathInMM>> {
+    let mut traced_paths: BTreeMap<PathColour, Vec<PathInMM>> = BTreeMap::new();
 
     for pass in tool_passes {
-        let path_colour = PathColour(pass.colour().to_owned());
+        let path_colour = *pass.colour();
+        // A colour used by more than one pass is only traced once, each pass cuts the same paths.
+        if traced_paths.contains_key(&path_colour) {
+            continue;
+        }
+
         if let Some(paths) = paths_grouped_by_colour.get(&path_colour) {
             for path in paths {
-                let mut path_builder = lyon_algorithms::path::Path::builder();
-                let mut closed = false;
-                for segment in path.data().segments() {
-                    match segment {
-                        usvg::tiny_skia_path::PathSegment::MoveTo(point) => {
-                            path_builder.begin(
-                                PointInMillimeters {
-                                    x: point.x,
-                                    y: point.y,
-                                }
-                                .into(),
-                            );
-                        }
-                        usvg::tiny_skia_path::PathSegment::LineTo(point) => {
-                            path_builder.line_to(
-                                PointInMillimeters {
-                                    x: point.x,
-                                    y: point.y,
-                                }
-                                .into(),
-                            );
-                        }
-                        // The target point is the end of the curve, the control point is somewhere in the middle.
-                        usvg::tiny_skia_path::PathSegment::QuadTo(control_point, target_point) => {
-                            path_builder.quadratic_bezier_to(
-                                PointInMillimeters {
-                                    x: control_point.x,
-                                    y: control_point.y,
-                                }
-                                .into(),
-                                PointInMillimeters {
-                                    x: target_point.x,
-                                    y: target_point.y,
-                                }
-                                .into(),
-                            );
-                        }
-                        // The target point is the end of the curve, the first control point is towards the beginning
-                        // of the curve, the second control point is towards the end of the curve.
-                        usvg::tiny_skia_path::PathSegment::CubicTo(
-                            first_control_point,
-                            second_control_point,
-                            target_point,
-                        ) => {
-                            path_builder.cubic_bezier_to(
-                                PointInMillimeters {
-                                    x: first_control_point.x,
-                                    y: first_control_point.y,
-                                }
-                                .into(),
-                                PointInMillimeters {
-                                    x: second_control_point.x,
-                                    y: second_control_point.y,
-                                }
-                                .into(),
-                                PointInMillimeters {
-                                    x: target_point.x,
-                                    y: target_point.y,
-                                }
-                                .into(),
-                            );
-                        }
-                        usvg::tiny_skia_path::PathSegment::Close => {
-                            path_builder.end(true);
-                            closed = true;
-                        }
-                    }
-                }
+                let entry = traced_paths.entry(path_colour).or_default();
+                entry.push(trace_path(path));
+            }
+        }
+    }
+
+    traced_paths
+}
+
+/// Takes a set of SVG paths grouped by their colour and traces all of them into points in mm,
+/// whether or not a tool pass will cut them.
+///
+/// # Arguments
+/// * `paths_grouped_by_colour`: The paths to be traced, grouped by their colour.
+///
+/// # Returns
+/// A set of traced paths in mm, grouped by path colour.
+pub fn trace_all_paths(
+    paths_grouped_by_colour: &BTreeMap<PathColour, Vec<Box<Path>>>,
+) -> BTreeMap<PathColour, Vec<PathInMM>> {
+    paths_grouped_by_colour
+        .iter()
+        .map(|(colour, paths)| (*colour, paths.iter().map(|path| trace_path(path)).collect()))
+        .collect()
+}
+
+/// Traces a single SVG path into points in mm.
+///
+/// # Arguments
+/// * `path`: The path to trace.
+///
+/// # Returns
+/// The points along the path. A path that is a single closed shape ends exactly where it starts.
+fn trace_path(path: &Path) -> PathInMM {
+    let built_path = build_path(path);
+    let mut points = vec![];
+    points_along_path(built_path.as_slice(), &mut points);
+
+    // Points are only traced every so often, so the last one usually falls short of closing the shape.
+    let mut starts = built_path
+        .iter()
+        .filter(|event| matches!(event, PathEvent::Begin { .. }));
+    let is_closed = built_path
+        .iter()
+        .any(|event| matches!(event, PathEvent::End { close: true, .. }));
+    if let (Some(PathEvent::Begin { at }), None, true) = (starts.next(), starts.next(), is_closed) {
+        if points.last() != Some(&at) {
+            points.push(at);
+        }
+    }
+
+    points.into_iter().map(|point| point.into()).collect()
+}
+
+/// Finds the colour of the path nearest to a point, e.g. to work out which path was clicked on.
+///
+/// # Arguments
+/// * `paths_in_mm`: The traced paths, grouped by colour.
+/// * `point`: The point to look near.
+/// * `max_distance_mm`: How far away from the point a path can be.
+///
+/// # Returns
+/// The colour of the nearest path, if there is one close enough.
+pub fn nearest_path_colour(
+    paths_in_mm: &BTreeMap<PathColour, Vec<PathInMM>>,
+    point: PointInMillimeters,
+    max_distance_mm: f32,
+) -> Option<PathColour> {
+    paths_in_mm
+        .iter()
+        .flat_map(|(colour, paths)| {
+            paths
+                .iter()
+                .map(move |path| (*colour, distance_to_path(path, point)))
+        })
+        .filter(|(_, distance)| *distance <= max_distance_mm)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(colour, _)| colour)
+}
+
+/// Works out how far a point is from the nearest part of a path.
+///
+/// # Arguments
+/// * `path`: The path.
+/// * `point`: The point.
+///
+/// # Returns
+/// The distance in mm, which is infinite if the path has no points.
+fn distance_to_path(path: &PathInMM, point: PointInMillimeters) -> f32 {
+    match path.as_slice() {
+        [] => f32::INFINITY,
+        [only] => distance_to_segment(*only, *only, point),
+        _ => path
+            .windows(2)
+            .map(|segment| distance_to_segment(segment[0], segment[1], point))
+            .fold(f32::INFINITY, f32::min),
+    }
+}
+
+/// Works out how far a point is from the nearest part of a straight line.
+///
+/// # Arguments
+/// * `start`: The start of the line.
+/// * `end`: The end of the line.
+/// * `point`: The point.
+///
+/// # Returns
+/// The distance in mm.
+fn distance_to_segment(
+    start: PointInMillimeters,
+    end: PointInMillimeters,
+    point: PointInMillimeters,
+) -> f32 {
+    let (dx, dy) = (end.x - start.x, end.y - start.y);
+    let length_squared = dx * dx + dy * dy;
+    // How far along the line the nearest point is, from 0 at the start to 1 at the end.
+    let along = if length_squared == 0.0 {
+        0.0
+    } else {
+        (((point.x - start.x) * dx + (point.y - start.y) * dy) / length_squared).clamp(0.0, 1.0)
+    };
+
+    (point.x - (start.x + along * dx)).hypot(point.y - (start.y + along * dy))
+}
+
+/// Drops the points of a path that are (nearly) on a straight line between the points either side of them,
+/// so that long straight edges are a single move rather than one move for every traced point.
+/// This is the Ramer–Douglas–Peucker algorithm, so the points that are kept are points of the original path,
+/// and the path never moves more than `epsilon` from where it was.
+///
+/// # Arguments
+/// * `path`: The path to simplify, in place.
+/// * `epsilon`: How far, in mm, a point may be from the simplified path.
+pub fn simplify_collinear(path: &mut PathInMM, epsilon: f32) {
+    if path.len() < 3 {
+        return;
+    }
+
+    let last = path.len() - 1;
+    let mut keep = vec![false; path.len()];
+    keep[0] = true;
+    keep[last] = true;
+
+    // Keep the point furthest from each line, then check the lines either side of it, until every
+    // point that hasn't been kept is close enough to a line.
+    let mut lines = vec![(0, last)];
+    while let Some((start, end)) = lines.pop() {
+        let furthest = (start + 1..end)
+            .map(|index| {
+                let distance = distance_to_segment(path[start], path[end], path[index]);
+                (index, distance)
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b));
+        if let Some((index, distance)) = furthest {
+            if distance > epsilon {
+                keep[index] = true;
+                lines.push((start, index));
+                lines.push((index, end));
+            }
+        }
+    }
+
+    let mut kept = keep.into_iter();
+    path.retain(|_| kept.next().unwrap_or(true));
+}
+
+/// Works out the moves the toolhead makes between paths with the tool off,
+/// from the end of each path to the start of the next, in the order they are cut.
+/// Disabled tool passes are skipped, as nothing is cut for them.
+///
+/// # Arguments
+/// * `paths_in_mm`: The traced paths, grouped by colour.
+/// * `tool_passes`: The toolhead passes to be done.
+///
+/// # Returns
+/// The start and end of each move.
+pub fn travel_moves(
+    paths_in_mm: &BTreeMap<PathColour, Vec<PathInMM>>,
+    tool_passes: &[ToolPass],
+) -> Vec<(PointInMillimeters, PointInMillimeters)> {
+    let mut moves = vec![];
+    let mut previous_end: Option<PointInMillimeters> = None;
+
+    for pass in tool_passes.iter().filter(|pass| *pass.enabled()) {
+        let Some(paths) = paths_in_mm.get(pass.colour()) else {
+            continue;
+        };
 
-                if !closed {
-                    path_builder.end(false);
+        for path in paths {
+            let (Some(start), Some(end)) = (path.first(), path.last()) else {
+                continue;
+            };
+
+            if let Some(previous_end) = previous_end {
+                if previous_end != *start {
+                    moves.push((previous_end, *start));
                 }
+            }
+            previous_end = Some(*end);
+        }
+    }
+
+    moves
+}
+
+/// How much a job will cut, worked out from its traced paths.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CutStatistics {
+    /// The number of paths cut, counting a path once for each enabled tool pass that cuts it.
+    pub paths_cut: usize,
+    /// How far the toolhead moves with the tool on, in mm.
+    pub cut_length_mm: f32,
+    /// How far the toolhead moves with the tool off between paths, in mm, see [`travel_moves`].
+    pub travel_length_mm: f32,
+}
+
+impl CutStatistics {
+    /// Works out how much the enabled tool passes will cut.
+    ///
+    /// # Arguments
+    /// * `paths_in_mm`: The traced paths, grouped by colour.
+    /// * `tool_passes`: The toolhead passes to be done.
+    ///
+    /// # Returns
+    /// The statistics of the cut.
+    pub fn of_paths(
+        paths_in_mm: &BTreeMap<PathColour, Vec<PathInMM>>,
+        tool_passes: &[ToolPass],
+    ) -> Self {
+        let length = |from: &PointInMillimeters, to: &PointInMillimeters| {
+            (to.x - from.x).hypot(to.y - from.y)
+        };
+
+        let mut statistics = Self::default();
+        for pass in tool_passes.iter().filter(|pass| *pass.enabled()) {
+            for path in paths_in_mm.get(pass.colour()).into_iter().flatten() {
+                statistics.paths_cut += 1;
+                statistics.cut_length_mm += path
+                    .windows(2)
+                    .map(|points| length(&points[0], &points[1]))
+                    .sum::<f32>();
+            }
+        }
+        statistics.travel_length_mm = travel_moves(paths_in_mm, tool_passes)
+            .iter()
+            .map(|(from, to)| length(from, to))
+            .sum();
 
-                let mut resolved_points = vec![];
+        statistics
+    }
+}
 
-                let built_path = path_builder.build();
-                let mut points = vec![];
-                points_along_path(built_path.as_slice(), &mut points);
-                for mut point in points {
+/// Offsets traced paths and converts them into HPGL/2 machine units.
+///
+/// # Arguments
+/// * `paths_in_mm`: The traced paths, grouped by colour.
+/// * `offset`: How much to move the design by relative to its starting position, in mm, where +x is more right and +y is more down.
+///
+/// # Returns
+/// A set of resolved paths, grouped by path colour, or every path that couldn't be placed on the bed.
+pub fn convert_points_to_plotter_units(
+    paths_in_mm: &BTreeMap<PathColour, Vec<PathInMM>>,
+    offset: Vec2,
+    bed: &PrintBed,
+) -> Result<BTreeMap<PathColour, Vec<ResolvedPath>>, Vec<PathPlacementError>> {
+    let mut resolved_paths: BTreeMap<PathColour, Vec<ResolvedPath>> = BTreeMap::new();
+    let mut errors = vec![];
+
+    for (path_colour, paths) in paths_in_mm {
+        let entry = resolved_paths.entry(*path_colour).or_default();
+        for (path_index, path) in paths.iter().enumerate() {
+            let offset_points = path
+                .iter()
+                .map(|point| {
+                    let mut point: Point = (*point).into();
                     offset_point(&mut point, offset);
-                    resolved_points.push(point.into());
-                }
+                    point.into()
+                })
+                .collect();
+            match points_in_mm_to_printer_units(offset_points, bed) {
+                Ok(points) => entry.push(fit_arcs(&points, bed.mm_per_plotter_unit())),
+                Err(error) => errors.push(PathPlacementError {
+                    colour: *path_colour,
+                    path_index,
+                    error,
+                }),
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(resolved_paths)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Writes traced paths out as an SVG covering the whole bed, so that what the
+/// toolhead will move through can be checked in any SVG viewer.
+///
+/// # Arguments
+/// * `paths`: The traced paths, grouped by colour. These should already be offset to their position on the bed.
+/// * `bed`: The bed that the paths will be cut on.
+///
+/// # Returns
+/// The SVG document, with one polyline per path.
+pub fn to_svg(paths: &BTreeMap<PathColour, Vec<PathInMM>>, bed: &PrintBed) -> String {
+    write_svg(
+        paths,
+        *bed.x_axis().start(),
+        *bed.y_axis().start(),
+        bed.width_mm(),
+        bed.height_mm(),
+    )
+}
+
+/// Writes traced paths out as an SVG where one user unit is one mm.
+///
+/// # Arguments
+/// * `paths`: The traced paths, grouped by colour.
+/// * `x`: The left edge of the document, in mm.
+/// * `y`: The top edge of the document, in mm.
+/// * `width`: The width of the document, in mm.
+/// * `height`: The height of the document, in mm.
+///
+/// # Returns
+/// The SVG document, with one polyline per path.
+pub(crate) fn write_svg(
+    paths: &BTreeMap<PathColour, Vec<PathInMM>>,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+) -> String {
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}mm" height="{height}mm" viewBox="{x} {y} {width} {height}">"#
+    );
+
+    // Sort the colours so that the output is the same each time.
+    let mut colours: Vec<&PathColour> = paths.keys().collect();
+    colours.sort_by_key(|colour| colour.0);
+    for colour in colours {
+        let Colour([r, g, b]) = colour;
+        for path in &paths[colour] {
+            let points = path
+                .iter()
+                .map(|point| format!("{},{}", point.x, point.y))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let _ = writeln!(
+                svg,
+                r#"<polyline points="{points}" fill="none" stroke="rgb({r},{g},{b})" stroke-width="{SVG_STROKE_WIDTH_MM}"/>"#
+            );
+        }
+    }
 
-                let entry = resolved_paths.entry(path_colour).or_default();
-                entry.push(points_in_mm_to_printer_units(resolved_points));
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Converts an SVG path into a path in mm that can be walked along.
+/// The path is moved into place by the transforms of the groups it is in, along with the `viewBox` of the design,
+/// which gives a path in SVG units. This is then converted into mm at [`SVG_UNITS_PER_MM`].
+///
+/// # Arguments
+/// * `path`: The SVG path to convert.
+///
+/// # Returns
+/// The equivalent path, in mm.
+pub fn build_path(path: &Path) -> lyon_algorithms::path::Path {
+    let mut path_builder = lyon_algorithms::path::Path::builder();
+
+    let to_mm = path
+        .abs_transform()
+        .post_scale(1.0 / SVG_UNITS_PER_MM, 1.0 / SVG_UNITS_PER_MM);
+    let Some(data) = path.data().clone().transform(to_mm) else {
+        log::warn!("Could not convert path into mm, it has been skipped");
+        return path_builder.build();
+    };
+
+    let mut closed = false;
+    for segment in data.segments() {
+        match segment {
+            usvg::tiny_skia_path::PathSegment::MoveTo(point) => {
+                path_builder.begin(
+                    PointInMillimeters {
+                        x: point.x,
+                        y: point.y,
+                    }
+                    .into(),
+                );
+            }
+            usvg::tiny_skia_path::PathSegment::LineTo(point) => {
+                path_builder.line_to(
+                    PointInMillimeters {
+                        x: point.x,
+                        y: point.y,
+                    }
+                    .into(),
+                );
+            }
+            // The target point is the end of the curve, the control point is somewhere in the middle.
+            usvg::tiny_skia_path::PathSegment::QuadTo(control_point, target_point) => {
+                path_builder.quadratic_bezier_to(
+                    PointInMillimeters {
+                        x: control_point.x,
+                        y: control_point.y,
+                    }
+                    .into(),
+                    PointInMillimeters {
+                        x: target_point.x,
+                        y: target_point.y,
+                    }
+                    .into(),
+                );
+            }
+            // The target point is the end of the curve, the first control point is towards the beginning
+            // of the curve, the second control point is towards the end of the curve.
+            usvg::tiny_skia_path::PathSegment::CubicTo(
+                first_control_point,
+                second_control_point,
+                target_point,
+            ) => {
+                path_builder.cubic_bezier_to(
+                    PointInMillimeters {
+                        x: first_control_point.x,
+                        y: first_control_point.y,
+                    }
+                    .into(),
+                    PointInMillimeters {
+                        x: second_control_point.x,
+                        y: second_control_point.y,
+                    }
+                    .into(),
+                    PointInMillimeters {
+                        x: target_point.x,
+                        y: target_point.y,
+                    }
+                    .into(),
+                );
+            }
+            usvg::tiny_skia_path::PathSegment::Close => {
+                path_builder.end(true);
+                closed = true;
             }
         }
     }
 
-    resolved_paths
+    if !closed {
+        path_builder.end(false);
+    }
+
+    path_builder.build()
 }
 
 /// A point in terms of mm.
-#[derive(Debug, Clone, Copy)]
-struct PointInMillimeters {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointInMillimeters {
     /// Horizontal axis.
-    x: f32,
+    pub x: f32,
     /// Vertical axis.
-    y: f32,
+    pub y: f32,
 }
 
 impl From<PointInMillimeters> for lyon_algorithms::geom::euclid::Point2D<f32, UnknownUnit> {
@@ -212,27 +799,205 @@ fn offset_point(point: &mut Point, (ox, oy): Vec2) {
 /// * `points`: Points in mm to resolve.
 ///
 /// # Returns
-/// The provided points converted to HPGL/2 machine units.
-fn points_in_mm_to_printer_units(points: Vec<PointInMillimeters>) -> Vec<ResolvedPoint> {
-    let mut resolved_points = Vec::with_capacity(points.len());
+/// The provided points converted to HPGL/2 machine units, or why the first point that couldn't be placed
+/// on the bed couldn't be.
+fn points_in_mm_to_printer_units(
+    points: Vec<PointInMillimeters>,
+    bed: &PrintBed,
+) -> Result<Vec<ResolvedPoint>, PlacePointError> {
+    // The GCC Spirit has y=0 at the bottom, but generally we want 0,0 to be in the top-left,
+    // so the Y axis is mirrored by the bed.
+    points
+        .into_iter()
+        .map(|point| bed.place_point(point))
+        .collect()
+}
 
-    for point in points {
-        resolved_points.push(ResolvedPoint {
-            x: mm_to_hpgl_units(point.x, true),
-            y: mm_to_hpgl_units(point.y, false),
-        })
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use std::collections::BTreeMap;
+
+    use super::{
+        filter_paths_to_tool_passes, nearest_path_colour, simplify_collinear, snap_colours_to,
+        to_svg, travel_moves, PointInMillimeters, COLLINEAR_TOLERANCE_MM,
+    };
+    use crate::{
+        default_passes::default_passes,
+        fill::hatch_fills,
+        svg::{get_paths_grouped_by_colour, parse_svg, SVG_UNITS_PER_MM},
+        Colour, HatchOptions, PrintBed,
+    };
+
+    #[test]
+    fn traced_logo_round_trips_through_svg() {
+        let logo = include_bytes!("../../logo.svg");
+        let tree = parse_svg(&PathBuf::from("logo.svg"), logo).expect("Logo should parse");
+        // The logo is made of filled shapes, so hatch them to get some paths.
+        let traced = hatch_fills(&tree, &default_passes(), &HatchOptions::default());
+        let traced_count: usize = traced.values().map(Vec::len).sum();
+        assert_ne!(traced_count, 0);
+
+        let svg = to_svg(&traced, &PrintBed::GCC_SPIRIT);
+        assert_eq!(svg, to_svg(&traced, &PrintBed::GCC_SPIRIT));
+        assert_eq!(svg.matches("<polyline").count(), traced_count);
+
+        let exported = parse_svg(&PathBuf::from("exported.svg"), svg.as_bytes())
+            .expect("Exported SVG should parse");
+        assert_eq!(
+            (exported.size().width() / SVG_UNITS_PER_MM).round(),
+            PrintBed::GCC_SPIRIT.width_mm().round(),
+            "Exported SVG should be the width of the bed"
+        );
+        let exported_paths =
+            get_paths_grouped_by_colour(&exported).expect("Exported paths should group");
+        for (colour, paths) in &traced {
+            assert_eq!(
+                exported_paths.get(colour).map(Vec::len),
+                Some(paths.len()),
+                "Every traced path should be exported with its colour"
+            );
+        }
     }
 
-    resolved_points
-}
+    #[test]
+    fn travel_skips_disabled_passes() {
+        let point = |x, y| PointInMillimeters { x, y };
+        let paths = BTreeMap::from([
+            (
+                Colour([0, 0, 0]),
+                vec![
+                    vec![point(0.0, 0.0), point(10.0, 0.0)],
+                    vec![point(20.0, 0.0), point(30.0, 0.0)],
+                ],
+            ),
+            (
+                Colour([255, 0, 0]),
+                vec![vec![point(0.0, 10.0), point(10.0, 10.0)]],
+            ),
+            (
+                Colour([0, 0, 255]),
+                vec![vec![point(0.0, 20.0), point(10.0, 20.0)]],
+            ),
+        ]);
+        let mut passes = default_passes();
+        passes[1].set_enabled(false);
 
-/// Converts a mm value into the value in HPGL/2 units.
-///
-/// # Arguments
-/// * `mm`: The value in mm.
-/// * `mirror_x_axis`: The GCC Spirit has x=0 at the bottom. Generally we want 0,0 to be
-/// in the top-left, so we mirror the x axis in this case.
-pub fn mm_to_hpgl_units(mm: f32, is_x_axis: bool) -> i16 {
-    let position_mm = if is_x_axis { mm } else { BED_HEIGHT_MM - mm };
-    (position_mm / MM_PER_PLOTTER_UNIT).round() as i16
+        assert_eq!(
+            travel_moves(&paths, &passes),
+            vec![
+                (point(10.0, 0.0), point(20.0, 0.0)),
+                (point(30.0, 0.0), point(0.0, 20.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_enabled_passes_are_kept() {
+        let svg = r##"<svg xmlns="http://www.w3.org/2000/svg" width="20mm" height="20mm" viewBox="0 0 20 20">
+            <path d="M 0 0 L 10 0" stroke="#000000"/>
+            <path d="M 0 5 L 10 5" stroke="#ff0000"/>
+            <path d="M 0 10 L 10 10" stroke="#123456"/>
+        </svg>"##;
+        let tree =
+            parse_svg(&PathBuf::from("lines.svg"), svg.as_bytes()).expect("SVG should parse");
+        let paths = get_paths_grouped_by_colour(&tree).expect("Paths should group");
+        assert_eq!(paths.len(), 3);
+
+        let mut passes = default_passes();
+        passes[0].set_enabled(false);
+        let filtered = filter_paths_to_tool_passes(&paths, &passes);
+        assert_eq!(
+            filtered.keys().collect::<Vec<_>>(),
+            vec![&Colour([255, 0, 0])]
+        );
+    }
+
+    #[test]
+    fn nearest_path_colour_to_a_point() {
+        let red = Colour([255, 0, 0]);
+        let blue = Colour([0, 0, 255]);
+        let point = |x, y| PointInMillimeters { x, y };
+        // A long straight line only has points at its ends, the middle of it must still be found.
+        let paths = BTreeMap::from([
+            (red, vec![vec![point(0.0, 0.0), point(100.0, 0.0)]]),
+            (blue, vec![vec![point(0.0, 10.0), point(100.0, 10.0)]]),
+        ]);
+
+        assert_eq!(
+            nearest_path_colour(&paths, point(50.0, 2.0), 5.0),
+            Some(red)
+        );
+        assert_eq!(
+            nearest_path_colour(&paths, point(50.0, 7.0), 5.0),
+            Some(blue)
+        );
+        assert_eq!(nearest_path_colour(&paths, point(50.0, 30.0), 5.0), None);
+        assert_eq!(
+            nearest_path_colour(&paths, point(-3.0, -4.0), 5.0),
+            Some(red)
+        );
+    }
+
+    #[test]
+    fn near_colours_are_snapped() {
+        let black = Colour([0, 0, 0]);
+        let nearly_black = Colour([1, 1, 1]);
+        let red = Colour([200, 0, 0]);
+        let paths = BTreeMap::from([(nearly_black, vec!["nearly black"]), (red, vec!["red"])]);
+
+        assert_eq!(
+            snap_colours_to(paths.clone(), &[black], 4),
+            BTreeMap::from([(black, vec!["nearly black"]), (red, vec!["red"])])
+        );
+        assert_eq!(snap_colours_to(paths.clone(), &[black], 0), paths);
+    }
+
+    #[test]
+    fn straight_lines_are_simplified_to_their_ends() {
+        let point = |x, y| PointInMillimeters { x, y };
+        let mut line: Vec<_> = (0..100).map(|x| point(x as f32, 2.0 * x as f32)).collect();
+        simplify_collinear(&mut line, COLLINEAR_TOLERANCE_MM);
+        assert_eq!(line, vec![point(0.0, 0.0), point(99.0, 198.0)]);
+
+        // Corners are kept, even when a path ends where it starts.
+        let mut square: Vec<_> = (0..10)
+            .map(|x| point(x as f32, 0.0))
+            .chain((0..10).map(|y| point(10.0, y as f32)))
+            .chain((0..10).map(|x| point(10.0 - x as f32, 10.0)))
+            .chain((0..=10).map(|y| point(0.0, 10.0 - y as f32)))
+            .collect();
+        simplify_collinear(&mut square, COLLINEAR_TOLERANCE_MM);
+        assert_eq!(
+            square,
+            vec![
+                point(0.0, 0.0),
+                point(10.0, 0.0),
+                point(10.0, 10.0),
+                point(0.0, 10.0),
+                point(0.0, 0.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn snapped_paths_are_merged_in_colour_order() {
+        let black = Colour([0, 0, 0]);
+        let snapped_order = || {
+            let paths = BTreeMap::from([
+                (Colour([3, 0, 0]), vec!["3"]),
+                (Colour([0, 2, 0]), vec!["2"]),
+                (Colour([0, 0, 1]), vec!["1"]),
+                (black, vec!["0"]),
+            ]);
+            snap_colours_to(paths, &[black], 4)
+        };
+
+        // Every run merges the paths in the same order, so the job is cut the same way each time.
+        let expected = BTreeMap::from([(black, vec!["0", "1", "2", "3"])]);
+        for _ in 0..10 {
+            assert_eq!(snapped_order(), expected);
+        }
+    }
 }
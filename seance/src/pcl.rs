@@ -2,41 +2,110 @@
 //!
 //! Generates PCL to send to a machine.
 
-use crate::ToolPass;
+use std::{
+    io::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{DeviceProfile, ToolPass};
 
 /// The escape character, we insert this _a lot_.
 const ESC: char = '\x1b';
 
+/// Who a job is from, so that whoever is at the machine can tell whose job it is running.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobMetadata {
+    /// The name of the job.
+    pub name: String,
+    /// Who sent the job, if known.
+    pub author: Option<String>,
+    /// When the job was sent.
+    pub submitted_at: SystemTime,
+}
+
 /// Take some HPGL and wrap it in PCL.
 ///
 /// # Arguments
 /// * `hpgl`: The HPGL to be wrapped in PCL.
 /// * `filename`: This will be displayed on the screen of the machine, so should be recognisable to the user.
 /// * `laser_passes`: The passes of the toolhead to perform.
+/// * `profile`: How the device expects the PCL to be written.
+/// * `metadata`: If provided, who the job is from, sent as PJL ahead of the PCL.
+///
+/// # Returns
+/// PCL bytes that can be sent to the machine.
+pub fn wrap_hpgl_in_pcl(
+    hpgl: &[u8],
+    filename: &str,
+    laser_passes: &Vec<ToolPass>,
+    profile: &DeviceProfile,
+    metadata: Option<&JobMetadata>,
+) -> Vec<u8> {
+    let mut pcl = vec![];
+    wrap_hpgl_in_pcl_to(&mut pcl, filename, laser_passes, profile, metadata, |w| {
+        w.write_all(hpgl)
+    })
+    .expect("Writing to a Vec can't fail");
+    pcl
+}
+
+/// Writes PCL around some HPGL, without needing the HPGL up front.
+///
+/// # Arguments
+/// * `w`: Where to write the PCL.
+/// * `filename`: This will be displayed on the screen of the machine, so should be recognisable to the user.
+/// * `laser_passes`: The passes of the toolhead to perform.
+/// * `profile`: How the device expects the PCL to be written.
+/// * `metadata`: If provided, who the job is from, sent as PJL ahead of the PCL.
+/// * `write_hpgl`: Writes the HPGL, once the PCL has been set up to receive it.
 ///
 /// # Returns
-/// PCL string that can be sent to the machine.
-pub fn wrap_hpgl_in_pcl(hpgl: String, filename: &str, laser_passes: &Vec<ToolPass>) -> String {
-    vec![
+/// `Ok(())` if all of the PCL was written, otherwise the first error from writing it.
+pub fn wrap_hpgl_in_pcl_to<W: Write, E: From<io::Error>>(
+    w: &mut W,
+    filename: &str,
+    laser_passes: &Vec<ToolPass>,
+    profile: &DeviceProfile,
+    metadata: Option<&JobMetadata>,
+    write_hpgl: impl FnOnce(&mut W) -> Result<(), E>,
+) -> Result<(), E> {
+    // The pen table holds control characters, so the prologue is built up as bytes.
+    let mut prologue = [
         pjl_universal_exit_language(),
+        metadata.map(pjl_job).unwrap_or_default(),
         pcl_reset(),
-        pcl_filename(filename),
-        pcl_pen_table(laser_passes),
-        pcl_raster_resolution(508),
-        pcl_unit_of_measure(508),
+        pcl_filename(filename, profile.max_filename_length()),
+    ]
+    .concat()
+    .into_bytes();
+    prologue.extend(pcl_pen_table(laser_passes, profile));
+    let setup = [
+        pcl_raster_resolution(profile.raster_resolution_dpi()),
+        pcl_unit_of_measure(profile.unit_of_measure_dpi()),
         format!("{ESC}!r0N"),
         pcl_enter_pcl_mode(),
-        format!("{ESC}!r1000I{ESC}!r1000K{ESC}!r500P"),
-        pcl_raster_resolution(508),
-        pcl_unit_of_measure(508),
+        profile.init_block().to_string(),
+        pcl_raster_resolution(profile.raster_resolution_dpi()),
+        pcl_unit_of_measure(profile.unit_of_measure_dpi()),
         format!("{ESC}!m0S{ESC}!s1S"),
         pcl_enter_hpgl_mode(),
-        hpgl,
+    ];
+    prologue.extend(setup.concat().as_bytes());
+    w.write_all(&prologue)?;
+
+    write_hpgl(w)?;
+
+    let mut epilogue = vec![
         pcl_enter_pcl_mode(),
         pcl_reset(),
         pjl_universal_exit_language(),
-    ]
-    .join("")
+    ];
+    if let Some(metadata) = metadata {
+        epilogue.extend([pjl_end_of_job(metadata), pjl_universal_exit_language()]);
+    }
+    w.write_all(epilogue.concat().as_bytes())?;
+
+    Ok(())
 }
 
 /// Insert the Printer Job Language (PJL) Universal Exit Language (UEL) command.
@@ -53,6 +122,89 @@ fn pjl_universal_exit_language() -> String {
     format!("{ESC}%-12345X")
 }
 
+/// Starts a PJL job named after the job, with comments saying who sent it and when.
+/// These have to come straight after the UEL, before we switch to PCL.
+///
+/// # Arguments
+/// * `metadata`: Who the job is from.
+///
+/// # Returns
+/// The PJL job commands.
+fn pjl_job(metadata: &JobMetadata) -> String {
+    let mut pjl = format!("@PJL JOB NAME=\"{}\"\r\n", pjl_string(&metadata.name));
+    if let Some(author) = &metadata.author {
+        pjl += &format!("@PJL COMMENT AUTHOR={}\r\n", pjl_string(author));
+    }
+    pjl += &format!(
+        "@PJL COMMENT SUBMITTED={}\r\n",
+        utc_timestamp(metadata.submitted_at)
+    );
+    pjl
+}
+
+/// Ends the PJL job started by [`pjl_job`]. This has to be sent after the UEL at the end of the job,
+/// and be followed by another UEL.
+///
+/// # Arguments
+/// * `metadata`: Who the job is from.
+///
+/// # Returns
+/// The PJL end of job command.
+fn pjl_end_of_job(metadata: &JobMetadata) -> String {
+    format!("@PJL EOJ NAME=\"{}\"\r\n", pjl_string(&metadata.name))
+}
+
+/// Makes some text safe to put in a PJL command, which can't contain quotes or anything outside printable ASCII.
+///
+/// # Arguments
+/// * `text`: The text to make safe.
+///
+/// # Returns
+/// The text, with anything that isn't allowed replaced with `_`.
+fn pjl_string(text: &str) -> String {
+    printable_ascii(text)
+        .map(|c| if c == '"' { '_' } else { c })
+        .collect()
+}
+
+/// Formats a time as an ISO 8601 UTC timestamp, e.g. `2023-11-14T22:13:20Z`.
+///
+/// # Arguments
+/// * `time`: The time to format. Times before 1970 are treated as 1970.
+///
+/// # Returns
+/// The timestamp.
+fn utc_timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs())
+        .unwrap_or_default();
+    let seconds_of_day = seconds % 86_400;
+
+    // Converting days to a date, from http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = seconds / 86_400 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds_of_day / 3_600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60
+    )
+}
+
 /// Sending this command enters PCL and resets the printer in this mode.
 /// This must be sent before any other PCL commands. It is also good manners to
 /// send this at the end to return the tool and bed to their home positions.
@@ -64,17 +216,39 @@ fn pcl_reset() -> String {
 }
 
 /// Tells PCL to report the filename of the print job.
+/// The machine can only show printable ASCII, so anything else is replaced with `_`,
+/// and the name is cut short to what the machine can show.
 ///
 /// # Arguments
 /// * `filename`: The filename to report.
+/// * `max_length`: The most characters of the filename to report.
 ///
 /// # Returns
 /// Command to report the filename.
-fn pcl_filename(filename: &str) -> String {
+fn pcl_filename(filename: &str, max_length: usize) -> String {
+    let filename: String = printable_ascii(filename).take(max_length).collect();
+    // Every character is a single byte now, so this is both the byte and character count.
     let len = filename.len();
     format!("{ESC}!m{len}N{filename}")
 }
 
+/// Replaces each character of some text that isn't printable ASCII with `_`.
+///
+/// # Arguments
+/// * `text`: The text to replace characters in.
+///
+/// # Returns
+/// The characters of the text, all printable ASCII.
+fn printable_ascii(text: &str) -> impl Iterator<Item = char> + '_ {
+    text.chars().map(|c| {
+        if c == ' ' || c.is_ascii_graphic() {
+            c
+        } else {
+            '_'
+        }
+    })
+}
+
 /// Constructs the table of 'pens'.
 /// A pen is a pass of the tool. Think about CNC machines as being pen plotters.
 /// I mean, they basically are right?
@@ -84,13 +258,15 @@ fn pcl_filename(filename: &str) -> String {
 /// achieve different line styles, a CNC can move its tool at different speeds and different 'powers'
 /// (e.g. laser power) in order to perform different kinds of cut.
 /// Therefore a single pass of the tool of a CNC machine is a 'pen'!
+/// The table has one entry for each tool pass, which must be within [`crate::TOOL_PASS_COUNT`].
 ///
 /// # Arguments
 /// * `tool_passes`: The tool passes to perform.
+/// * `profile`: How the device expects the pens table to be written.
 ///
 /// # Returns
-/// A PCL string containing the pens table.
-fn pcl_pen_table(tool_passes: &Vec<ToolPass>) -> String {
+/// The PCL bytes of the pens table.
+fn pcl_pen_table(tool_passes: &Vec<ToolPass>, profile: &DeviceProfile) -> Vec<u8> {
     let num_pens = tool_passes.len();
     let message_bytes = num_pens * 4;
 
@@ -103,8 +279,8 @@ fn pcl_pen_table(tool_passes: &Vec<ToolPass>) -> String {
 
     // Pen PPI
     result += &format!("{ESC}!v{message_bytes}I");
-    for _ in tool_passes {
-        result += "0400";
+    for pen in tool_passes {
+        result += &format!("{:0>4}", pen.ppi());
     }
 
     // Pen Speed
@@ -122,12 +298,9 @@ fn pcl_pen_table(tool_passes: &Vec<ToolPass>) -> String {
     // Pen enable.
     // TODO: Should be based on enabled pens.
     result += &format!("{ESC}!v{num_pens}D");
+    let mut result = result.into_bytes();
     for pass in tool_passes {
-        if *pass.enabled() {
-            result.push(ascii::AsciiChar::SOX.into());
-        } else {
-            result.push(ascii::AsciiChar::Null.into());
-        }
+        result.push(profile.pen_enable_char(*pass.enabled()).as_byte());
     }
 
     result
@@ -176,3 +349,211 @@ fn pcl_enter_pcl_mode() -> String {
 fn pcl_enter_hpgl_mode() -> String {
     format!("{ESC}%1B")
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use ascii::AsciiChar;
+
+    use super::{pcl_filename, pcl_pen_table, utc_timestamp, wrap_hpgl_in_pcl, JobMetadata, ESC};
+    use crate::{default_passes::default_passes, DeviceProfile, ToolPass, MAX_TOOL_PASSES};
+
+    /// Wraps some HPGL in PCL, as a string that's easier to compare against.
+    fn wrap_hpgl_in_pcl_lossy(
+        hpgl: &str,
+        filename: &str,
+        passes: &Vec<ToolPass>,
+        profile: &DeviceProfile,
+        metadata: Option<&JobMetadata>,
+    ) -> String {
+        String::from_utf8_lossy(&wrap_hpgl_in_pcl(
+            hpgl.as_bytes(),
+            filename,
+            passes,
+            profile,
+            metadata,
+        ))
+        .into_owned()
+    }
+
+    #[test]
+    fn pen_table_for_four_passes() {
+        let mut passes = vec![
+            ToolPass::new("Pass 1".to_string(), 0, 0, 0, 100, 20, false, 400),
+            ToolPass::new("Pass 2".to_string(), 255, 0, 0, 1000, 5, false, 400),
+            ToolPass::new("Pass 3".to_string(), 0, 255, 0, 50, 300, false, 400),
+            ToolPass::new("Pass 4".to_string(), 0, 0, 255, 0, 1000, false, 400),
+        ];
+        passes[2].set_enabled(false);
+
+        assert_eq!(
+            pcl_pen_table(&passes, &DeviceProfile::GCC_SPIRIT),
+            format!(
+                "{ESC}!v4R1111\
+                {ESC}!v16I0400040004000400\
+                {ESC}!v16V0020000503001000\
+                {ESC}!v16P0100100000500000\
+                {ESC}!v4D\x02\x02\x00\x02"
+            )
+            .into_bytes()
+        );
+    }
+
+    #[test]
+    fn pen_table_has_the_ppi_of_each_pass() {
+        let mut passes = default_passes();
+        passes[1].set_ppi(1000);
+        passes[3].set_ppi(20_000);
+
+        assert_eq!(
+            pcl_pen_table(&passes, &DeviceProfile::GCC_SPIRIT),
+            format!(
+                "{ESC}!v4R1111\
+                {ESC}!v16I0400100004009999\
+                {ESC}!v16V0020002000200020\
+                {ESC}!v16P0100010001000100\
+                {ESC}!v4D\x02\x02\x02\x02"
+            )
+            .into_bytes()
+        );
+    }
+
+    #[test]
+    fn pen_table_for_sixteen_passes() {
+        let passes =
+            vec![ToolPass::new("Pass".to_string(), 0, 0, 0, 100, 20, false, 400); MAX_TOOL_PASSES];
+
+        assert_eq!(
+            pcl_pen_table(&passes, &DeviceProfile::GCC_SPIRIT),
+            format!(
+                "{ESC}!v16R{}{ESC}!v64I{}{ESC}!v64V{}{ESC}!v64P{}{ESC}!v16D{}",
+                "1".repeat(16),
+                "0400".repeat(16),
+                "0020".repeat(16),
+                "0100".repeat(16),
+                "\x02".repeat(16)
+            )
+            .into_bytes()
+        );
+    }
+
+    #[test]
+    fn filenames_are_printable_ascii() {
+        assert_eq!(
+            pcl_filename("design.svg", 31),
+            format!("{ESC}!m10Ndesign.svg")
+        );
+        // Each character that can't be shown is replaced once, however many bytes it is.
+        assert_eq!(
+            pcl_filename("caf\u{e9} \u{1f47b}\tsign.svg", 31),
+            format!("{ESC}!m15Ncaf_ __sign.svg")
+        );
+        assert_eq!(pcl_filename("", 31), format!("{ESC}!m0N"));
+    }
+
+    #[test]
+    fn long_filenames_are_cut_short() {
+        assert_eq!(
+            pcl_filename(&"a".repeat(300), 31),
+            format!("{ESC}!m31N{}", "a".repeat(31))
+        );
+        assert_eq!(
+            pcl_filename(&"\u{1f47b}".repeat(40), 31),
+            format!("{ESC}!m31N{}", "_".repeat(31))
+        );
+    }
+
+    #[test]
+    fn profiles_only_change_their_own_blocks() {
+        let mut passes = vec![
+            ToolPass::new("Pass 1".to_string(), 0, 0, 0, 100, 20, false, 400),
+            ToolPass::new("Pass 2".to_string(), 255, 0, 0, 1000, 5, false, 400),
+        ];
+        passes[1].set_enabled(false);
+        let other = DeviceProfile::new(
+            1000,
+            1016,
+            "\x1b!r800I",
+            AsciiChar::X,
+            AsciiChar::Space,
+            31,
+            false,
+        );
+
+        let expected = |dpi: u64, unit: u64, init: &str, enabled: &str| {
+            format!(
+                "{ESC}%-12345X{ESC}E{ESC}!m6Ndesign\
+                {ESC}!v2R11{ESC}!v8I04000400{ESC}!v8V00200005{ESC}!v8P01001000{ESC}!v2D{enabled}\
+                {ESC}*t{dpi}R{ESC}&u{unit}R{ESC}!r0N{ESC}%1A\
+                {init}{ESC}*t{dpi}R{ESC}&u{unit}R{ESC}!m0S{ESC}!s1S{ESC}%1B\
+                PU;{ESC}%1A{ESC}E{ESC}%-12345X"
+            )
+        };
+        assert_eq!(
+            wrap_hpgl_in_pcl_lossy("PU;", "design", &passes, &DeviceProfile::GCC_SPIRIT, None),
+            expected(
+                508,
+                508,
+                &format!("{ESC}!r1000I{ESC}!r1000K{ESC}!r500P"),
+                "\x02\x00"
+            )
+        );
+        assert_eq!(
+            wrap_hpgl_in_pcl_lossy("PU;", "design", &passes, &other, None),
+            expected(1000, 1016, &format!("{ESC}!r800I"), "X ")
+        );
+    }
+
+    #[test]
+    fn job_metadata_is_sent_as_pjl() {
+        let passes = vec![ToolPass::new(
+            "Pass 1".to_string(),
+            0,
+            0,
+            0,
+            100,
+            20,
+            false,
+            400,
+        )];
+        let metadata = JobMetadata {
+            name: "\"Ghost\" sign".to_string(),
+            author: Some("hackspace-pc".to_string()),
+            submitted_at: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        };
+
+        let pcl = wrap_hpgl_in_pcl_lossy(
+            "PU;",
+            "design",
+            &passes,
+            &DeviceProfile::GCC_SPIRIT,
+            Some(&metadata),
+        );
+        let without_metadata =
+            wrap_hpgl_in_pcl_lossy("PU;", "design", &passes, &DeviceProfile::GCC_SPIRIT, None);
+
+        let uel = format!("{ESC}%-12345X");
+        let job = "@PJL JOB NAME=\"_Ghost_ sign\"\r\n\
+            @PJL COMMENT AUTHOR=hackspace-pc\r\n\
+            @PJL COMMENT SUBMITTED=2023-11-14T22:13:20Z\r\n";
+        let end_of_job = format!("@PJL EOJ NAME=\"_Ghost_ sign\"\r\n{uel}");
+        assert_eq!(
+            pcl,
+            format!("{uel}{job}{}{end_of_job}", &without_metadata[uel.len()..])
+        );
+    }
+
+    #[test]
+    fn timestamps_are_utc() {
+        assert_eq!(utc_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            utc_timestamp(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "2000-02-29T00:00:00Z"
+        );
+        assert_eq!(
+            utc_timestamp(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            "2023-11-14T22:13:20Z"
+        );
+    }
+}
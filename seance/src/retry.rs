@@ -0,0 +1,183 @@
+//! `retry`
+//!
+//! Retries writes to devices that are sometimes briefly busy, such as USB printers.
+
+use std::{io, thread, time::Duration};
+
+/// The longest to wait between retries, however many times the wait has doubled.
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// How writes to a print device are retried when they fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct WriteRetry {
+    /// How many times to try each write before giving up, at least once.
+    pub attempts: u32,
+    /// How long to wait before the first retry, in ms. The wait doubles after each retry, up to 10s.
+    pub backoff_ms: u64,
+}
+
+impl Default for WriteRetry {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff_ms: 100,
+        }
+    }
+}
+
+impl WriteRetry {
+    /// Runs an operation, retrying it with backoff until it succeeds or has been tried [`WriteRetry::attempts`] times.
+    /// Operations that time out aren't retried, as they have already waited as long as the device is given.
+    ///
+    /// # Arguments
+    /// * `operation`: The operation to run.
+    ///
+    /// # Returns
+    /// The result of the first successful try, otherwise the error from the last try.
+    pub(crate) fn run<T>(&self, mut operation: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+        let mut backoff = Duration::from_millis(self.backoff_ms).min(MAX_BACKOFF);
+        let mut attempt = 1;
+        loop {
+            match operation() {
+                Ok(value) => return Ok(value),
+                Err(err) if attempt >= self.attempts || err.kind() == io::ErrorKind::TimedOut => {
+                    return Err(err)
+                }
+                Err(err) => {
+                    log::warn!(
+                        "Print device failed, retrying in {}ms (attempt {attempt} of {}): {err}",
+                        backoff.as_millis(),
+                        self.attempts
+                    );
+                    thread::sleep(backoff);
+                    backoff = backoff.saturating_mul(2).min(MAX_BACKOFF);
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+/// A writer that retries each write to the writer it wraps.
+/// A write that fails hasn't written anything, so it is safe to try it again.
+pub(crate) struct RetryingWriter<W> {
+    /// The writer to retry writes to.
+    inner: W,
+    /// How the writes are retried.
+    retry: WriteRetry,
+}
+
+impl<W: io::Write> RetryingWriter<W> {
+    /// Creates a new [`RetryingWriter`].
+    ///
+    /// # Arguments
+    /// * `inner`: The writer to retry writes to.
+    /// * `retry`: How the writes are retried.
+    ///
+    /// # Returns
+    /// A new [`RetryingWriter`].
+    pub(crate) fn new(inner: W, retry: WriteRetry) -> Self {
+        Self { inner, retry }
+    }
+}
+
+impl<W: io::Write> io::Write for RetryingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.retry.run(|| self.inner.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.retry.run(|| self.inner.flush())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{self, Write};
+
+    use super::{RetryingWriter, WriteRetry};
+
+    /// A device that is busy for a number of writes before it accepts them.
+    struct BusyDevice {
+        /// How many more writes will fail.
+        busy_for: u32,
+        /// How the writes fail while the device is busy.
+        busy_error: io::ErrorKind,
+        /// The bytes that have been written.
+        written: Vec<u8>,
+    }
+
+    impl Write for BusyDevice {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.busy_for > 0 {
+                self.busy_for -= 1;
+                return Err(io::Error::new(self.busy_error, "busy"));
+            }
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn writes_are_retried_until_the_device_is_ready() {
+        let retry = WriteRetry {
+            attempts: 3,
+            backoff_ms: 0,
+        };
+
+        let mut writer = RetryingWriter::new(
+            BusyDevice {
+                busy_for: 2,
+                busy_error: io::ErrorKind::ResourceBusy,
+                written: vec![],
+            },
+            retry,
+        );
+        writer
+            .write_all(b"IN;")
+            .expect("Write should succeed on the last attempt");
+        assert_eq!(writer.inner.written, b"IN;");
+
+        let mut writer = RetryingWriter::new(
+            BusyDevice {
+                busy_for: 3,
+                busy_error: io::ErrorKind::ResourceBusy,
+                written: vec![],
+            },
+            retry,
+        );
+        let err = writer
+            .write_all(b"IN;")
+            .expect_err("Every attempt should fail");
+        assert_eq!(err.kind(), io::ErrorKind::ResourceBusy);
+        assert_eq!(writer.inner.written, b"");
+    }
+
+    #[test]
+    fn timed_out_writes_are_not_retried() {
+        let mut writer = RetryingWriter::new(
+            BusyDevice {
+                busy_for: 1,
+                busy_error: io::ErrorKind::TimedOut,
+                written: vec![],
+            },
+            WriteRetry {
+                attempts: 3,
+                backoff_ms: 0,
+            },
+        );
+        let err = writer
+            .write_all(b"IN;")
+            .expect_err("Timed out write should fail");
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(writer.inner.busy_for, 0);
+
+        // The device is ready now, so writing again works.
+        writer.write_all(b"IN;").expect("Write should succeed");
+        assert_eq!(writer.inner.written, b"IN;");
+    }
+}
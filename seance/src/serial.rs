@@ -0,0 +1,315 @@
+//! `serial`
+//!
+//! Sends jobs to older plotters that are connected to a serial port.
+
+use std::{
+    io::{self, Write},
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use serialport::{SerialPort, SerialPortType};
+
+use crate::{describe_device, PrintDevice, PrintDeviceCandidate, WriteRetry};
+
+/// The baud rate that serial plotters usually default to.
+pub const DEFAULT_SERIAL_BAUD: u32 = 9600;
+
+/// The baud rates that serial plotters commonly support, for the user to pick from.
+pub const SERIAL_BAUD_RATES: [u32; 6] = [2400, 4800, 9600, 19_200, 38_400, 115_200];
+
+/// How long to wait for the plotter to accept each write before giving up.
+/// Plotters stop taking data while they draw what is in their buffer, which can take a while.
+const SERIAL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often to check whether the plotter is ready for more data.
+const CLEAR_TO_SEND_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How a serial plotter tells us to stop sending while its buffer is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SerialFlowControl {
+    /// The plotter can't stop us, so data is sent as fast as the baud rate allows.
+    None,
+    /// The plotter sends XOFF and XON characters.
+    Software,
+    /// The plotter drops its CTS line, as most RS-232 plotters do.
+    #[default]
+    Hardware,
+}
+
+impl From<SerialFlowControl> for serialport::FlowControl {
+    fn from(flow_control: SerialFlowControl) -> Self {
+        match flow_control {
+            SerialFlowControl::None => serialport::FlowControl::None,
+            SerialFlowControl::Software => serialport::FlowControl::Software,
+            SerialFlowControl::Hardware => serialport::FlowControl::Hardware,
+        }
+    }
+}
+
+/// A port that can tell us whether the device on the other end is ready for more data.
+pub(crate) trait ClearToSend: Write {
+    /// Checks whether the device is ready for more data.
+    ///
+    /// # Returns
+    /// `true` if the device is asserting its CTS line, otherwise `false`, or an error if the line couldn't be read.
+    fn clear_to_send(&mut self) -> io::Result<bool>;
+}
+
+impl ClearToSend for Box<dyn SerialPort> {
+    fn clear_to_send(&mut self) -> io::Result<bool> {
+        self.read_clear_to_send().map_err(io::Error::from)
+    }
+}
+
+/// A port that, with hardware flow control, waits for the device to be ready before each write.
+/// The serial driver also stops sending when CTS drops, but not all USB serial adapters do so,
+/// and a plotter with a tiny buffer loses data if it is sent even a few bytes too many.
+pub(crate) struct FlowControlledPort<P> {
+    /// The port to write to.
+    port: P,
+    /// How the device tells us to stop sending.
+    flow_control: SerialFlowControl,
+    /// How long to wait for the device to be ready before giving up.
+    timeout: Duration,
+}
+
+impl<P: ClearToSend> FlowControlledPort<P> {
+    /// Creates a new [`FlowControlledPort`].
+    ///
+    /// # Arguments
+    /// * `port`: The port to write to.
+    /// * `flow_control`: How the device tells us to stop sending.
+    /// * `timeout`: How long to wait for the device to be ready before giving up.
+    ///
+    /// # Returns
+    /// A new [`FlowControlledPort`].
+    pub(crate) fn new(port: P, flow_control: SerialFlowControl, timeout: Duration) -> Self {
+        Self {
+            port,
+            flow_control,
+            timeout,
+        }
+    }
+
+    /// Waits until the device is ready for more data, if it uses hardware flow control.
+    ///
+    /// # Returns
+    /// An error if the device wasn't ready in time or its CTS line couldn't be read.
+    fn wait_until_clear_to_send(&mut self) -> io::Result<()> {
+        if self.flow_control != SerialFlowControl::Hardware {
+            return Ok(());
+        }
+
+        let started = Instant::now();
+        while !self.port.clear_to_send()? {
+            if started.elapsed() >= self.timeout {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "the plotter didn't become ready for more data",
+                ));
+            }
+            thread::sleep(CLEAR_TO_SEND_POLL_INTERVAL);
+        }
+
+        Ok(())
+    }
+}
+
+impl<P: ClearToSend> Write for FlowControlledPort<P> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.wait_until_clear_to_send()?;
+        self.port.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.port.flush()
+    }
+}
+
+/// Opens a serial port and applies its settings, ready to stream a job to the plotter.
+///
+/// # Arguments
+/// * `path`: The path or name of the port, e.g. `/dev/ttyUSB0` or `COM1`.
+/// * `baud`: The baud rate of the plotter.
+/// * `flow_control`: How the plotter tells us to stop sending.
+///
+/// # Returns
+/// The opened port, otherwise an error if it couldn't be opened or configured.
+pub(crate) fn open_serial_port(
+    path: &str,
+    baud: u32,
+    flow_control: SerialFlowControl,
+) -> io::Result<FlowControlledPort<Box<dyn SerialPort>>> {
+    let port = serialport::new(path, baud)
+        .data_bits(serialport::DataBits::Eight)
+        .parity(serialport::Parity::None)
+        .stop_bits(serialport::StopBits::One)
+        .flow_control(flow_control.into())
+        .timeout(SERIAL_TIMEOUT)
+        .open()?;
+
+    Ok(FlowControlledPort::new(port, flow_control, SERIAL_TIMEOUT))
+}
+
+/// Checks whether a serial port exists.
+///
+/// # Arguments
+/// * `path`: The path or name of the port.
+///
+/// # Returns
+/// `true` if the port exists, without opening it, as opening some ports resets the device.
+pub(crate) fn serial_port_exists(path: &str) -> bool {
+    // Ports on Windows are names like `COM1` rather than paths, so are looked up instead.
+    Path::new(path).exists()
+        || serialport::available_ports()
+            .is_ok_and(|ports| ports.iter().any(|port| port.port_name == path))
+}
+
+/// Finds the serial ports on this computer, so that the user can pick the one the plotter is connected to.
+///
+/// # Returns
+/// The ports that were found, set up with the usual settings for a plotter, with the names of the
+/// manufacturer and product of USB serial adapters where they are known.
+pub(crate) fn enumerate_serial_ports() -> Vec<PrintDeviceCandidate> {
+    let ports = match serialport::available_ports() {
+        Ok(ports) => ports,
+        Err(err) => {
+            log::warn!("Could not list serial ports: {err}");
+            return vec![];
+        }
+    };
+
+    ports
+        .into_iter()
+        .map(|port| {
+            let (manufacturer, product) = match port.port_type {
+                SerialPortType::UsbPort(info) => (info.manufacturer, info.product),
+                _ => (None, None),
+            };
+            PrintDeviceCandidate {
+                description: describe_device(&port.port_name, manufacturer, product),
+                device: PrintDevice::Serial {
+                    path: port.port_name,
+                    baud: DEFAULT_SERIAL_BAUD,
+                    flow_control: SerialFlowControl::default(),
+                    retry: WriteRetry::default(),
+                },
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{self, Write},
+        time::Duration,
+    };
+
+    use super::{ClearToSend, FlowControlledPort, SerialFlowControl, DEFAULT_SERIAL_BAUD};
+    use crate::{PrintDevice, WriteRetry};
+
+    /// A port that loops back what is written to it, and whose device is busy for a number of checks.
+    struct LoopbackPort {
+        /// How many more checks the device will be busy for.
+        busy_for: u32,
+        /// The bytes that have been written, and would be read back.
+        looped_back: Vec<u8>,
+    }
+
+    impl Write for LoopbackPort {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.looped_back.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl ClearToSend for LoopbackPort {
+        fn clear_to_send(&mut self) -> io::Result<bool> {
+            if self.busy_for > 0 {
+                self.busy_for -= 1;
+                return Ok(false);
+            }
+            Ok(true)
+        }
+    }
+
+    #[test]
+    fn serial_devices_default_to_hardware_flow_control() {
+        let device: PrintDevice =
+            serde_json::from_str(r#"{"Serial":{"path":"/dev/ttyS0","baud":9600}}"#)
+                .expect("Serial device should load");
+        let PrintDevice::Serial {
+            path,
+            baud,
+            flow_control,
+            retry,
+        } = &device
+        else {
+            panic!("Device should be a serial device");
+        };
+        assert_eq!(
+            (path.as_str(), *baud, *flow_control, *retry),
+            (
+                "/dev/ttyS0",
+                DEFAULT_SERIAL_BAUD,
+                SerialFlowControl::Hardware,
+                WriteRetry::default()
+            )
+        );
+
+        let json = serde_json::to_string(&device).expect("Serial device should serialize");
+        assert_eq!(
+            json,
+            r#"{"Serial":{"path":"/dev/ttyS0","baud":9600,"flow_control":"Hardware","retry":{"attempts":3,"backoff_ms":100}}}"#
+        );
+    }
+
+    #[test]
+    fn writes_wait_until_the_plotter_is_ready() {
+        let mut port = FlowControlledPort::new(
+            LoopbackPort {
+                busy_for: 3,
+                looped_back: vec![],
+            },
+            SerialFlowControl::Hardware,
+            Duration::from_secs(1),
+        );
+        port.write_all(b"IN;")
+            .expect("Write should succeed once the plotter is ready");
+        assert_eq!(port.port.looped_back, b"IN;");
+        assert_eq!(port.port.busy_for, 0);
+
+        // A plotter that never becomes ready times out without being sent anything.
+        let mut port = FlowControlledPort::new(
+            LoopbackPort {
+                busy_for: u32::MAX,
+                looped_back: vec![],
+            },
+            SerialFlowControl::Hardware,
+            Duration::ZERO,
+        );
+        let err = port.write_all(b"IN;").expect_err("Write should time out");
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(port.port.looped_back, b"");
+
+        // Without hardware flow control the CTS line is ignored.
+        let mut port = FlowControlledPort::new(
+            LoopbackPort {
+                busy_for: u32::MAX,
+                looped_back: vec![],
+            },
+            SerialFlowControl::Software,
+            Duration::ZERO,
+        );
+        port.write_all(b"IN;").expect("Write should succeed");
+        assert_eq!(port.port.looped_back, b"IN;");
+    }
+}
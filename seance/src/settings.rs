@@ -0,0 +1,144 @@
+//! `settings`
+//!
+//! The file that tool passes are exported to and imported from.
+
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::ToolPass;
+
+/// The version of [`SettingsFile`] written by this build.
+pub const SETTINGS_FILE_VERSION: u32 = 1;
+
+/// Errors that can occur when bringing a [`SettingsFile`] up to date.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SettingsFileError {
+    /// The file was written by a newer build, holding the version of the file.
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for SettingsFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsFileError::UnsupportedVersion(version) => write!(
+                f,
+                "settings file is version {version}, but only up to version {SETTINGS_FILE_VERSION} can be opened"
+            ),
+        }
+    }
+}
+
+/// Tool passes as they are exported to a file, along with the version of the file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SettingsFile {
+    /// The version of the file, version 0 files were a bare list of tool passes.
+    pub version: u32,
+    /// The exported tool passes.
+    pub passes: Vec<ToolPass>,
+}
+
+impl SettingsFile {
+    /// Creates a new [`SettingsFile`] at the current version.
+    ///
+    /// # Arguments
+    /// * `passes`: The tool passes to export.
+    ///
+    /// # Returns
+    /// A new [`SettingsFile`].
+    pub fn new(passes: Vec<ToolPass>) -> Self {
+        Self {
+            version: SETTINGS_FILE_VERSION,
+            passes,
+        }
+    }
+
+    /// Upgrades a file that was written by an older build to the current version.
+    ///
+    /// # Returns
+    /// An error if the file was written by a newer build, which can't be understood.
+    pub fn migrate(&mut self) -> Result<(), SettingsFileError> {
+        if self.version > SETTINGS_FILE_VERSION {
+            return Err(SettingsFileError::UnsupportedVersion(self.version));
+        }
+
+        if self.version == 0 {
+            // Version 0 files were a bare list of passes, which have already been read into `passes`.
+            self.version = 1;
+        }
+
+        Ok(())
+    }
+}
+
+/// The ways that a [`SettingsFile`] can be written.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SerializedSettingsFile {
+    /// A file with its version, version 1 onwards.
+    Versioned { version: u32, passes: Vec<ToolPass> },
+    /// A bare list of tool passes, version 0.
+    Legacy(Vec<ToolPass>),
+}
+
+impl<'de> Deserialize<'de> for SettingsFile {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match SerializedSettingsFile::deserialize(deserializer)? {
+            SerializedSettingsFile::Versioned { version, passes } => Self { version, passes },
+            SerializedSettingsFile::Legacy(passes) => Self { version: 0, passes },
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SettingsFile, SettingsFileError, SETTINGS_FILE_VERSION};
+    use crate::default_passes::default_passes;
+
+    /// Settings exported before files were versioned.
+    const VERSION_0_FILE: &str = r#"[
+        {"name":"Pass 1","colour":[0,0,0],"power":100,"speed":20,"rast":false,"enable":true},
+        {"name":"Pass 2","colour":[255,0,0],"power":100,"speed":20,"rast":false,"enable":true},
+        {"name":"Pass 3","colour":[0,255,0],"power":100,"speed":20,"rast":false,"enable":true},
+        {"name":"Pass 4","colour":[0,0,255],"power":100,"speed":20,"rast":false,"enable":true}
+    ]"#;
+
+    /// Settings exported as version 1.
+    const VERSION_1_FILE: &str = r##"{"version":1,"passes":[
+        {"name":"Pass 1","colour":"#000000","power":100,"speed":20,"rast":false,"enable":true},
+        {"name":"Pass 2","colour":"#FF0000","power":100,"speed":20,"rast":false,"enable":true},
+        {"name":"Pass 3","colour":"#00FF00","power":100,"speed":20,"rast":false,"enable":true},
+        {"name":"Pass 4","colour":"#0000FF","power":100,"speed":20,"rast":false,"enable":true}
+    ]}"##;
+
+    #[test]
+    fn every_version_loads() {
+        for (file, version) in [(VERSION_0_FILE, 0), (VERSION_1_FILE, 1)] {
+            let mut settings: SettingsFile =
+                serde_json::from_str(file).expect("Settings file should load");
+            assert_eq!(settings.version, version);
+
+            settings.migrate().expect("Settings file should migrate");
+            assert_eq!(settings, SettingsFile::new(default_passes()));
+        }
+
+        let exported = serde_json::to_string(&SettingsFile::new(default_passes()))
+            .expect("Settings file should export");
+        let reloaded: SettingsFile =
+            serde_json::from_str(&exported).expect("Exported settings file should load");
+        assert_eq!(reloaded, SettingsFile::new(default_passes()));
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let newer = SETTINGS_FILE_VERSION + 1;
+        let mut settings = SettingsFile {
+            version: newer,
+            passes: default_passes(),
+        };
+        assert_eq!(
+            settings.migrate(),
+            Err(SettingsFileError::UnsupportedVersion(newer))
+        );
+    }
+}
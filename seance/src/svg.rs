@@ -1,17 +1,28 @@
 //!`svg`
 //!
 //! Provides utilities for handling SVG data.
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
 
 use resvg::usvg;
 
-use crate::{paths::PathColour, SendToDeviceError};
+use crate::{paths::PathColour, Colour, SendToDeviceError, ToolPass};
+
+/// The number of SVG units per inch, as set by the CSS standard.
+/// Lengths given in real-world units (`mm`, `cm`, `in`, `pt`, `pc`) are converted into SVG units at this rate when parsing,
+/// so sizes in SVG units can be converted back into mm no matter which unit the design was drawn in.
+pub const SVG_UNITS_PER_INCH: f32 = 96.0;
 
 /// The number of SVG units per mm. This is based on 96 SVG units per inch.
-pub const SVG_UNITS_PER_MM: f32 = 3.779527559;
+pub const SVG_UNITS_PER_MM: f32 = SVG_UNITS_PER_INCH / 25.4;
 
 /// Parses an SVG file and turns it into a tree of paths.
 ///
+/// System fonts are used to convert any text in the design into paths.
+///
 /// # Arguments
 /// * `path`: The path to the file, will be used to allow the SVG to link to files in the same
 /// directory, for example it will be used if the SVG embeds an image via a link.
@@ -20,6 +31,14 @@ pub const SVG_UNITS_PER_MM: f32 = 3.779527559;
 /// # Returns
 /// The parsed SVG if it was successfully parsed, otherwise an error.
 pub fn parse_svg(path: &PathBuf, bytes: &[u8]) -> Result<usvg::Tree, usvg::Error> {
+    parse_svg_with_options(path, bytes, system_fontdb(), true).map(|(tree, _)| tree)
+}
+
+/// Gets a font database containing the system fonts, with the generic font families set up.
+///
+/// # Returns
+/// The font database.
+pub fn system_fontdb() -> usvg::fontdb::Database {
     let mut fontdb = usvg::fontdb::Database::new();
     fontdb.load_system_fonts();
 
@@ -29,11 +48,73 @@ pub fn parse_svg(path: &PathBuf, bytes: &[u8]) -> Result<usvg::Tree, usvg::Error
     fontdb.set_fantasy_family("Impact");
     fontdb.set_monospace_family("Courier New");
 
+    fontdb
+}
+
+/// Things that were noticed while parsing an SVG that may mean that the design
+/// will not be cut as the user expects.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ParseWarnings {
+    /// The names of font families referenced by text in the design that could
+    /// not be found in the font database. Text using these fonts will have been
+    /// converted to paths using a fallback font, if one was available.
+    pub missing_font_families: Vec<String>,
+    /// The number of images in the design, which can't be cut and so are ignored.
+    pub ignored_images: usize,
+}
+
+impl ParseWarnings {
+    /// Checks whether there are any warnings.
+    ///
+    /// # Returns
+    /// `true` if nothing was noticed during parsing.
+    pub fn is_empty(&self) -> bool {
+        self.missing_font_families.is_empty() && self.ignored_images == 0
+    }
+}
+
+/// Parses an SVG file and turns it into a tree of paths, using the provided fonts.
+///
+/// # Arguments
+/// * `path`: The path to the file, will be used to allow the SVG to link to files in the same
+/// directory, for example it will be used if the SVG embeds an image via a link.
+/// * `bytes`: The bytes of the file.
+/// * `fontdb`: The fonts that can be used to convert text to paths.
+/// * `convert_text`: Whether text should be converted into paths. If `false` then any text in
+/// the design is dropped from the tree, so it will neither be previewed nor cut.
+///
+/// # Returns
+/// The parsed SVG along with any warnings if it was successfully parsed, otherwise an error.
+pub fn parse_svg_with_options(
+    path: &PathBuf,
+    bytes: &[u8],
+    fontdb: usvg::fontdb::Database,
+    convert_text: bool,
+) -> Result<(usvg::Tree, ParseWarnings), usvg::Error> {
     let resources_dir = path.parent().map(|p| p.to_path_buf());
 
+    let missing_font_families: Arc<Mutex<BTreeSet<String>>> = Default::default();
+    let font_resolver = if convert_text {
+        let missing_font_families = missing_font_families.clone();
+        let default_font_selector = usvg::FontResolver::default_font_selector();
+        usvg::FontResolver {
+            select_font: Box::new(move |font, fontdb| {
+                record_missing_font_families(font, fontdb, &missing_font_families);
+                default_font_selector(font, fontdb)
+            }),
+            select_fallback: usvg::FontResolver::default_fallback_selector(),
+        }
+    } else {
+        // Text that cannot be resolved to a font is removed from the tree.
+        usvg::FontResolver {
+            select_font: Box::new(|_, _| None),
+            select_fallback: Box::new(|_, _, _| None),
+        }
+    };
+
     let re_opt = usvg::Options {
         resources_dir,
-        dpi: 96.0,
+        dpi: SVG_UNITS_PER_INCH,
         font_family: "Times New Roman".to_string(),
         font_size: 12.0,
         languages: vec!["en-GB".to_string()],
@@ -42,11 +123,94 @@ pub fn parse_svg(path: &PathBuf, bytes: &[u8]) -> Result<usvg::Tree, usvg::Error
         image_rendering: Default::default(),
         default_size: usvg::Size::from_wh(1000.0, 1000.0).expect("Could not set default size"),
         image_href_resolver: usvg::ImageHrefResolver::default(),
-        font_resolver: usvg::FontResolver::default(),
+        font_resolver,
         fontdb: Arc::new(fontdb),
     };
 
-    usvg::Tree::from_data(bytes, &re_opt)
+    let tree = usvg::Tree::from_data(bytes, &re_opt)?;
+
+    let missing_font_families = missing_font_families
+        .lock()
+        .map(|families| families.iter().cloned().collect())
+        .unwrap_or_default();
+
+    let ignored_images = count_images(tree.root());
+    if ignored_images > 0 {
+        log::warn!("Ignoring {ignored_images} images in the design, images can't be cut");
+    }
+
+    Ok((
+        tree,
+        ParseWarnings {
+            missing_font_families,
+            ignored_images,
+        },
+    ))
+}
+
+/// Counts the images in a group and any groups inside it.
+///
+/// # Arguments
+/// * `group`: The SVG group to search through for images.
+///
+/// # Returns
+/// The number of images found.
+fn count_images(group: &usvg::Group) -> usize {
+    group
+        .children()
+        .iter()
+        .map(|child| match child {
+            usvg::Node::Group(child_group) => count_images(child_group),
+            usvg::Node::Image(_) => 1,
+            usvg::Node::Path(_) | usvg::Node::Text(_) => 0,
+        })
+        .sum()
+}
+
+/// Gets the size of a design in mm.
+/// The size comes from the `width` and `height` of the SVG, which have already been converted into SVG units
+/// from whichever unit they were given in. Sizes without a unit are taken to be in SVG units, i.e. 96 per inch.
+///
+/// # Arguments
+/// * `svg`: The parsed design.
+///
+/// # Returns
+/// The width and height of the design, in mm.
+pub fn design_size_mm(svg: &usvg::Tree) -> (f32, f32) {
+    (
+        svg.size().width() / SVG_UNITS_PER_MM,
+        svg.size().height() / SVG_UNITS_PER_MM,
+    )
+}
+
+/// Records any named font families that are requested by a font but are not present in the font database.
+///
+/// # Arguments
+/// * `font`: The font requested by some text.
+/// * `fontdb`: The fonts that are available.
+/// * `missing_font_families`: The set of missing font family names to add to.
+fn record_missing_font_families(
+    font: &usvg::Font,
+    fontdb: &usvg::fontdb::Database,
+    missing_font_families: &Mutex<BTreeSet<String>>,
+) {
+    for family in font.families() {
+        let usvg::FontFamily::Named(name) = family else {
+            continue;
+        };
+
+        let found = fontdb.faces().any(|face| {
+            face.families
+                .iter()
+                .any(|(face_family, _)| face_family == name)
+        });
+        if !found {
+            log::warn!("Font family '{name}' is not available");
+            if let Ok(mut missing) = missing_font_families.lock() {
+                missing.insert(name.clone());
+            }
+        }
+    }
 }
 
 /// Finds all of the paths in the SVG and groups them by their stroke colour values.
@@ -58,8 +222,8 @@ pub fn parse_svg(path: &PathBuf, bytes: &[u8]) -> Result<usvg::Tree, usvg::Error
 /// The paths grouped by colour if successful, otherwise an error.
 pub fn get_paths_grouped_by_colour(
     svg: &usvg::Tree,
-) -> Result<HashMap<PathColour, Vec<Box<usvg::Path>>>, SendToDeviceError> {
-    let mut grouped_paths = HashMap::new();
+) -> Result<BTreeMap<PathColour, Vec<Box<usvg::Path>>>, SendToDeviceError> {
+    let mut grouped_paths = BTreeMap::new();
     group_paths_by_colour(svg.root(), &mut grouped_paths);
 
     Ok(grouped_paths)
@@ -67,27 +231,27 @@ pub fn get_paths_grouped_by_colour(
 
 /// Does the actual grouping of paths by colour.
 /// Be warned, here be recursion.
-/// Images and text are ignored.
+/// Images are ignored, text is picked up through the outline paths it was converted into.
 ///
 /// # Arguments
 /// * `group`: The SVG group to search through for paths. May contain nested groups.
 /// * `grouped_paths`: The path grouping to extend with any new paths found.
 fn group_paths_by_colour(
     group: &usvg::Group,
-    grouped_paths: &mut HashMap<PathColour, Vec<Box<usvg::Path>>>,
+    grouped_paths: &mut BTreeMap<PathColour, Vec<Box<usvg::Path>>>,
 ) {
     'iter_children: for child in group.children() {
         match child {
             usvg::Node::Group(child_group) => group_paths_by_colour(child_group, grouped_paths),
             usvg::Node::Path(path) => {
                 if let Some(stroke) = path.stroke() {
-                    if !path.is_visible() {
+                    if !is_cut(path) {
                         continue 'iter_children;
                     }
 
                     if let usvg::Paint::Color(colour) = stroke.paint() {
                         let entry = grouped_paths
-                            .entry(PathColour([colour.red, colour.green, colour.blue]))
+                            .entry(Colour([colour.red, colour.green, colour.blue]))
                             .or_default();
                         entry.push(path.clone());
                     }
@@ -96,6 +260,760 @@ fn group_paths_by_colour(
             usvg::Node::Image(_) | usvg::Node::Text(_) => {}
         }
 
+        // Text exposes the paths it was converted into as a subroot.
         child.subroots(|subroot| group_paths_by_colour(subroot, grouped_paths));
     }
 }
+
+/// What paths are grouped by to choose the tool pass that cuts them.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize,
+)]
+pub enum GroupingKey {
+    /// The colour of the path's stroke.
+    #[default]
+    StrokeColour,
+    /// The colour of the path's fill.
+    FillColour,
+    /// The layer that the path is in, by the layer's label if it has one, otherwise by its `id`.
+    LayerName,
+    /// The first CSS class of the path, or of the nearest group around it that has a class.
+    CssClass,
+    /// The width of the path's stroke, see [`StrokeWidth`].
+    StrokeWidth,
+}
+
+/// The value shared by a group of paths, see [`GroupingKey`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GroupKey {
+    /// Paths of a colour, when grouping by stroke or fill colour.
+    Colour(Colour),
+    /// Paths with a name, when grouping by layer or CSS class.
+    Name(String),
+    /// Paths with a stroke width, when grouping by stroke width.
+    StrokeWidth(StrokeWidth),
+}
+
+/// The width of a path's stroke in mm, rounded to the nearest hundredth of a mm,
+/// so that widths too close to tell apart in a design are grouped together.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Deserialize, serde::Serialize,
+)]
+#[serde(from = "f32", into = "f32")]
+pub struct StrokeWidth(u32);
+
+impl StrokeWidth {
+    /// The number of steps that each mm of a stroke width is rounded to.
+    const STEPS_PER_MM: f32 = 100.0;
+
+    /// Creates a new [`StrokeWidth`].
+    ///
+    /// # Arguments
+    /// * `width_mm`: The width of the stroke, in mm.
+    ///
+    /// # Returns
+    /// The rounded stroke width. Widths that aren't a number or are negative are no width.
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn from_mm(width_mm: f32) -> Self {
+        let steps = (width_mm * Self::STEPS_PER_MM).round();
+        // The steps are clamped to what a `u32` holds, so the cast can't truncate or lose the sign.
+        // `u32::MAX` rounds up as an `f32`, which the cast saturates back down.
+        let steps = if steps.is_nan() {
+            0.0
+        } else {
+            steps.clamp(0.0, u32::MAX as f32)
+        };
+        Self(steps as u32)
+    }
+
+    /// Gets the stroke width in mm.
+    ///
+    /// # Returns
+    /// The rounded stroke width, in mm.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn mm(&self) -> f32 {
+        // Only widths of more than 2^24 steps, over 167m, lose precision.
+        self.0 as f32 / Self::STEPS_PER_MM
+    }
+}
+
+impl From<f32> for StrokeWidth {
+    fn from(width_mm: f32) -> Self {
+        Self::from_mm(width_mm)
+    }
+}
+
+impl From<StrokeWidth> for f32 {
+    fn from(width: StrokeWidth) -> Self {
+        width.mm()
+    }
+}
+
+/// Finds all of the paths in the SVG and groups them by the given key.
+/// Only paths with a stroke are grouped by stroke colour, only paths with a fill are grouped by fill colour.
+///
+/// # Arguments
+/// * `svg`: The SVG to iterate over.
+/// * `bytes`: The bytes of the SVG file, as layer labels and CSS classes are not kept in the tree.
+/// * `key`: What to group the paths by.
+///
+/// # Returns
+/// The paths grouped by key. When grouping by CSS class, paths without an `id` can't be found in the
+/// SVG file and are left out.
+pub fn group_paths_by(
+    svg: &usvg::Tree,
+    bytes: &[u8],
+    key: GroupingKey,
+) -> BTreeMap<GroupKey, Vec<Box<usvg::Path>>> {
+    let mut grouped_paths: BTreeMap<GroupKey, Vec<Box<usvg::Path>>> = BTreeMap::new();
+
+    match key {
+        GroupingKey::StrokeColour => {
+            let mut by_colour = BTreeMap::new();
+            group_paths_by_colour(svg.root(), &mut by_colour);
+            grouped_paths.extend(
+                by_colour
+                    .into_iter()
+                    .map(|(colour, paths)| (GroupKey::Colour(colour), paths)),
+            );
+        }
+        GroupingKey::FillColour => visit_paths(svg.root(), &mut |path| {
+            let Some(usvg::Paint::Color(colour)) = path.fill().map(usvg::Fill::paint) else {
+                return;
+            };
+            if path.is_visible() {
+                let colour = Colour([colour.red, colour.green, colour.blue]);
+                grouped_paths
+                    .entry(GroupKey::Colour(colour))
+                    .or_default()
+                    .push(path.clone());
+            }
+        }),
+        GroupingKey::LayerName => {
+            let labels = inkscape_layer_labels(bytes);
+            for (id, paths) in get_paths_grouped_by_layer(svg) {
+                let name = labels.get(&id).cloned().unwrap_or(id);
+                grouped_paths
+                    .entry(GroupKey::Name(name))
+                    .or_default()
+                    .extend(paths);
+            }
+        }
+        GroupingKey::CssClass => {
+            let classes = element_classes(bytes);
+            let mut without_id = 0;
+            visit_paths(svg.root(), &mut |path| {
+                if !is_cut(path) {
+                    return;
+                }
+                if path.id().is_empty() {
+                    without_id += 1;
+                    return;
+                }
+                if let Some(class) = classes.get(path.id()) {
+                    grouped_paths
+                        .entry(GroupKey::Name(class.clone()))
+                        .or_default()
+                        .push(path.clone());
+                }
+            });
+            if without_id > 0 {
+                log::warn!("{without_id} paths have no id, so their CSS class can't be found");
+            }
+        }
+        GroupingKey::StrokeWidth => visit_paths(svg.root(), &mut |path| {
+            let Some(stroke) = path.stroke().filter(|_| is_cut(path)) else {
+                return;
+            };
+            // Strokes are scaled along with their path, so the width is in the path's units.
+            let (scale_x, scale_y) = path.abs_transform().get_scale();
+            let width_mm = stroke.width().get() * (scale_x + scale_y) / 2.0 / SVG_UNITS_PER_MM;
+            grouped_paths
+                .entry(GroupKey::StrokeWidth(StrokeWidth::from_mm(width_mm)))
+                .or_default()
+                .push(path.clone());
+        }),
+    }
+
+    grouped_paths
+}
+
+/// Calls a function with every path in a group and any groups inside it.
+/// Be warned, here be recursion.
+///
+/// # Arguments
+/// * `group`: The SVG group to search through for paths. May contain nested groups.
+/// * `visit`: The function to call with each path.
+fn visit_paths(group: &usvg::Group, visit: &mut dyn FnMut(&Box<usvg::Path>)) {
+    for child in group.children() {
+        match child {
+            usvg::Node::Group(child_group) => visit_paths(child_group, visit),
+            usvg::Node::Path(path) => visit(path),
+            usvg::Node::Image(_) | usvg::Node::Text(_) => {}
+        }
+
+        // Text exposes the paths it was converted into as a subroot.
+        child.subroots(|subroot| visit_paths(subroot, visit));
+    }
+}
+
+/// Reads the CSS class of each element of an SVG, as these are not kept once the SVG has been parsed into a tree.
+///
+/// # Arguments
+/// * `bytes`: The bytes of the SVG file.
+///
+/// # Returns
+/// A map of element `id` to the first class of the element, or of the nearest element around it that has a class.
+/// Empty if the SVG could not be read.
+fn element_classes(bytes: &[u8]) -> HashMap<String, String> {
+    let Ok(text) = std::str::from_utf8(bytes) else {
+        return HashMap::new();
+    };
+    let Ok(document) = usvg::roxmltree::Document::parse(text) else {
+        return HashMap::new();
+    };
+
+    document
+        .descendants()
+        .filter_map(|node| {
+            let id = node.attribute("id")?;
+            let class = node
+                .ancestors()
+                .find_map(|ancestor| ancestor.attribute("class")?.split_whitespace().next())?;
+            Some((id.to_string(), class.to_string()))
+        })
+        .collect()
+}
+
+/// Assigns grouped paths to the tool passes that match their key, by giving them the colour of the tool pass.
+/// The result can be used wherever paths grouped by their stroke colour are used.
+///
+/// # Arguments
+/// * `grouped_paths`: The paths grouped by key, from [`group_paths_by`].
+/// * `tool_passes`: The tool passes to match the groups to.
+///
+/// # Returns
+/// The paths grouped by the colour of the first tool pass that matches them. Groups that match no tool pass aren't cut.
+pub fn assign_groups_to_passes(
+    grouped_paths: BTreeMap<GroupKey, Vec<Box<usvg::Path>>>,
+    tool_passes: &[ToolPass],
+) -> BTreeMap<PathColour, Vec<Box<usvg::Path>>> {
+    let mut assigned: BTreeMap<PathColour, Vec<Box<usvg::Path>>> = BTreeMap::new();
+
+    for (key, paths) in grouped_paths {
+        if let Some(pass) = tool_passes.iter().find(|pass| pass.matches(&key)) {
+            assigned.entry(*pass.colour()).or_default().extend(paths);
+        }
+    }
+
+    assigned
+}
+
+/// Checks whether a path will be cut, which needs it to have a stroke and to be visible.
+/// Visibility is checked on the path's computed visibility, so paths in a layer hidden with
+/// `visibility:hidden` aren't cut. Layers hidden with `display:none` (as Inkscape does) aren't in the tree at all.
+///
+/// # Arguments
+/// * `path`: The path to check.
+///
+/// # Returns
+/// `true` if the path should be cut.
+fn is_cut(path: &usvg::Path) -> bool {
+    path.stroke().is_some() && path.is_visible()
+}
+
+/// The name of the layer that paths which are not inside any named top-level group are put into.
+pub const DEFAULT_LAYER_NAME: &str = "";
+
+/// Finds all of the paths in the SVG and groups them by the layer that they are in.
+/// Layers are the outermost named groups of the SVG (as created by e.g. Inkscape), named by their `id`.
+/// Paths that are not in a named group are put into the [`DEFAULT_LAYER_NAME`] layer.
+///
+/// # Arguments
+/// * `svg`: The SVG to iterate over.
+///
+/// # Returns
+/// The paths grouped by layer name, in the order that the layers appear in the document.
+pub fn get_paths_grouped_by_layer(svg: &usvg::Tree) -> Vec<(String, Vec<Box<usvg::Path>>)> {
+    let mut layers = vec![];
+    group_paths_by_layer(svg.root(), DEFAULT_LAYER_NAME, &mut layers);
+    layers
+}
+
+/// Does the actual grouping of paths by layer.
+/// Be warned, here be recursion.
+/// Groups without an `id` (e.g. those created to scale the document) do not start a new layer.
+///
+/// # Arguments
+/// * `group`: The SVG group to search through for paths. May contain nested groups.
+/// * `layer_name`: The layer that `group` is in.
+/// * `layers`: The path grouping to extend with any new paths found.
+fn group_paths_by_layer(
+    group: &usvg::Group,
+    layer_name: &str,
+    layers: &mut Vec<(String, Vec<Box<usvg::Path>>)>,
+) {
+    for child in group.children() {
+        match child {
+            usvg::Node::Group(child_group) => {
+                if layer_name == DEFAULT_LAYER_NAME {
+                    group_paths_by_layer(child_group, child_group.id(), layers);
+                } else {
+                    group_paths_by_layer(child_group, layer_name, layers);
+                }
+            }
+            usvg::Node::Path(path) => {
+                if !is_cut(path) {
+                    continue;
+                }
+
+                match layers.iter_mut().find(|(name, _)| name == layer_name) {
+                    Some((_, paths)) => paths.push(path.clone()),
+                    None => layers.push((layer_name.to_string(), vec![path.clone()])),
+                }
+            }
+            usvg::Node::Image(_) | usvg::Node::Text(_) => {}
+        }
+
+        // Text exposes the paths it was converted into as a subroot.
+        child.subroots(|subroot| group_paths_by_layer(subroot, layer_name, layers));
+    }
+}
+
+/// Reads the human-readable names of Inkscape layers from an SVG, as these are not
+/// kept once the SVG has been parsed into a tree.
+///
+/// # Arguments
+/// * `bytes`: The bytes of the SVG file.
+///
+/// # Returns
+/// A map of layer `id` to the layer's `inkscape:label`. Empty if the SVG could not be read.
+pub fn inkscape_layer_labels(bytes: &[u8]) -> HashMap<String, String> {
+    /// The Inkscape XML namespace.
+    const INKSCAPE_NS: &str = "http://www.inkscape.org/namespaces/inkscape";
+
+    let mut labels = HashMap::new();
+    let Ok(text) = std::str::from_utf8(bytes) else {
+        return labels;
+    };
+    let Ok(document) = usvg::roxmltree::Document::parse(text) else {
+        return labels;
+    };
+
+    for node in document.descendants() {
+        if !node.has_tag_name("g") {
+            continue;
+        }
+
+        if let (Some(id), Some(label)) =
+            (node.attribute("id"), node.attribute((INKSCAPE_NS, "label")))
+        {
+            labels.insert(id.to_string(), label.to_string());
+        }
+    }
+
+    labels
+}
+
+/// A layer of a design, and whether it is shown.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerVisibility {
+    /// The `id` of the layer.
+    pub id: String,
+    /// The human-readable name of the layer, if it has one, e.g. from Inkscape.
+    pub label: Option<String>,
+    /// Whether the layer is shown. Paths in hidden layers aren't cut.
+    pub visible: bool,
+}
+
+impl LayerVisibility {
+    /// Gets the name to show the user for the layer.
+    ///
+    /// # Returns
+    /// The layer's label if it has one, otherwise its `id`.
+    pub fn name(&self) -> &str {
+        self.label.as_deref().unwrap_or(&self.id)
+    }
+}
+
+/// Reads the layers of an SVG and whether each is shown. Hidden layers are dropped when the SVG
+/// is parsed into a tree, so they have to be read from the SVG itself.
+/// Layers are the outermost named groups of the SVG, as in [`get_paths_grouped_by_layer`].
+///
+/// # Arguments
+/// * `bytes`: The bytes of the SVG file.
+///
+/// # Returns
+/// The layers, in the order that they appear in the document. Empty if the SVG could not be read.
+pub fn layer_visibility(bytes: &[u8]) -> Vec<LayerVisibility> {
+    /// The Inkscape XML namespace.
+    const INKSCAPE_NS: &str = "http://www.inkscape.org/namespaces/inkscape";
+
+    let Ok(text) = std::str::from_utf8(bytes) else {
+        return vec![];
+    };
+    let Ok(document) = usvg::roxmltree::Document::parse(text) else {
+        return vec![];
+    };
+
+    document
+        .root_element()
+        .children()
+        .filter(|node| node.has_tag_name("g"))
+        .filter_map(|node| {
+            Some(LayerVisibility {
+                id: node.attribute("id")?.to_string(),
+                label: node.attribute((INKSCAPE_NS, "label")).map(str::to_string),
+                visible: !is_hidden(node),
+            })
+        })
+        .collect()
+}
+
+/// Checks whether an SVG element is hidden by its `display` or `visibility`, set either as attributes or in its style.
+///
+/// # Arguments
+/// * `node`: The element to check.
+///
+/// # Returns
+/// `true` if the element is hidden.
+fn is_hidden(node: usvg::roxmltree::Node) -> bool {
+    let hides = |property: &str, value: &str| {
+        matches!(
+            (property, value),
+            ("display", "none") | ("visibility", "hidden" | "collapse")
+        )
+    };
+
+    let hidden_by_attribute = ["display", "visibility"].into_iter().any(|property| {
+        node.attribute(property)
+            .is_some_and(|value| hides(property, value.trim()))
+    });
+    let hidden_by_style = node.attribute("style").is_some_and(|style| {
+        style
+            .split(';')
+            .filter_map(|declaration| declaration.split_once(':'))
+            .any(|(property, value)| hides(property.trim(), value.trim()))
+    });
+
+    hidden_by_attribute || hidden_by_style
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use lyon_algorithms::path::PathEvent;
+    use resvg::usvg;
+
+    use super::{
+        assign_groups_to_passes, design_size_mm, get_paths_grouped_by_colour,
+        get_paths_grouped_by_layer, group_paths_by, layer_visibility, parse_svg,
+        parse_svg_with_options, GroupKey, GroupingKey, ParseWarnings, StrokeWidth,
+        DEFAULT_LAYER_NAME,
+    };
+    use crate::{
+        paths::{build_path, PathColour},
+        Colour, ToolPass,
+    };
+
+    /// An SVG containing a single line of red text.
+    const TEXT_SVG: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" width="100mm" height="20mm" viewBox="0 0 100 20">
+        <text x="5" y="15" font-family="Departure Mono" font-size="10" fill="none" stroke="#ff0000">HELLO</text>
+    </svg>"##;
+
+    /// Gets a font database containing only the font bundled with the app.
+    fn test_fontdb() -> usvg::fontdb::Database {
+        let mut fontdb = usvg::fontdb::Database::new();
+        fontdb.load_font_data(
+            include_bytes!("../../app/fonts/departure-mono/DepartureMono-Regular.otf").to_vec(),
+        );
+        fontdb.set_serif_family("Departure Mono");
+        fontdb
+    }
+
+    #[test]
+    fn text_is_converted_to_paths() {
+        let (tree, warnings) = parse_svg_with_options(
+            &PathBuf::from("text.svg"),
+            TEXT_SVG.as_bytes(),
+            test_fontdb(),
+            true,
+        )
+        .expect("SVG should parse");
+        assert_eq!(warnings, ParseWarnings::default());
+
+        let paths = get_paths_grouped_by_colour(&tree).expect("Paths should be grouped");
+        let red_paths = paths
+            .get(&Colour([255, 0, 0]))
+            .expect("Text should produce red paths");
+        assert_ne!(red_paths.len(), 0, "Text should produce paths");
+    }
+
+    #[test]
+    fn text_is_dropped_when_not_converted() {
+        let (tree, _) = parse_svg_with_options(
+            &PathBuf::from("text.svg"),
+            TEXT_SVG.as_bytes(),
+            test_fontdb(),
+            false,
+        )
+        .expect("SVG should parse");
+
+        let paths = get_paths_grouped_by_colour(&tree).expect("Paths should be grouped");
+        assert_eq!(paths.len(), 0, "Text should not produce paths");
+    }
+
+    #[test]
+    fn missing_fonts_are_reported() {
+        let svg = TEXT_SVG.replace("Departure Mono", "Not A Real Font");
+        let (_, warnings) = parse_svg_with_options(
+            &PathBuf::from("text.svg"),
+            svg.as_bytes(),
+            test_fontdb(),
+            true,
+        )
+        .expect("SVG should parse");
+
+        assert_eq!(
+            warnings.missing_font_families,
+            vec!["Not A Real Font".to_string()]
+        );
+    }
+
+    #[test]
+    fn real_world_units_are_converted_to_mm() {
+        for (width, height, expected) in [
+            ("100mm", "50mm", (100.0, 50.0)),
+            ("10cm", "5cm", (100.0, 50.0)),
+            ("4in", "2in", (101.6, 50.8)),
+            ("384", "192", (101.6, 50.8)),
+        ] {
+            // The line runs from corner to corner, so it should be as big as the design.
+            let svg = format!(
+                r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 20 10">
+                    <path d="M 0 0 L 20 10" stroke="#000000"/>
+                </svg>"##
+            );
+            let tree =
+                parse_svg(&PathBuf::from("units.svg"), svg.as_bytes()).expect("SVG should parse");
+
+            let (width_mm, height_mm) = design_size_mm(&tree);
+            assert_eq!(
+                ((width_mm * 10.0).round(), (height_mm * 10.0).round()),
+                (expected.0 * 10.0, expected.1 * 10.0),
+                "{width} x {height}"
+            );
+
+            let paths = get_paths_grouped_by_colour(&tree).expect("Paths should be grouped");
+            let Some(PathEvent::End { last: end, .. }) =
+                build_path(&paths[&Colour([0, 0, 0])][0]).iter().last()
+            else {
+                panic!("Line should end");
+            };
+            assert_eq!(
+                ((end.x * 10.0).round(), (end.y * 10.0).round()),
+                (expected.0 * 10.0, expected.1 * 10.0),
+                "{width} x {height}"
+            );
+        }
+    }
+
+    #[test]
+    fn images_are_reported() {
+        let svg = r##"<svg xmlns="http://www.w3.org/2000/svg" width="10mm" height="10mm" viewBox="0 0 10 10">
+            <g><image width="1" height="1" href="data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNk+M9QDwADhgGAWjR9awAAAABJRU5ErkJggg=="/></g>
+            <path d="M 1 1 L 9 9" stroke="#000000"/>
+        </svg>"##;
+        let (_, warnings) = parse_svg_with_options(
+            &PathBuf::from("image.svg"),
+            svg.as_bytes(),
+            test_fontdb(),
+            true,
+        )
+        .expect("SVG should parse");
+
+        assert_eq!(warnings.ignored_images, 1);
+    }
+
+    /// An SVG with two Inkscape layers and a path outside of any layer.
+    const LAYERS_SVG: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" width="100mm" height="100mm" viewBox="0 0 100 100">
+        <g id="layer1" inkscape:groupmode="layer" inkscape:label="cut">
+            <path d="M 10 10 L 90 10" stroke="#000000"/>
+            <g id="g1"><path d="M 10 20 L 90 20" stroke="#ff0000"/></g>
+        </g>
+        <g id="layer2" inkscape:groupmode="layer" inkscape:label="engrave">
+            <path d="M 10 30 L 90 30" stroke="#000000"/>
+        </g>
+        <path d="M 10 40 L 90 40" stroke="#000000"/>
+    </svg>"##;
+
+    #[test]
+    fn paths_grouped_by_layer() {
+        let tree = parse_svg(&PathBuf::from("layers.svg"), LAYERS_SVG.as_bytes())
+            .expect("SVG should parse");
+
+        let layers = get_paths_grouped_by_layer(&tree);
+        let summary: Vec<(&str, usize)> = layers
+            .iter()
+            .map(|(name, paths)| (name.as_str(), paths.len()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("layer1", 2), ("layer2", 1), (DEFAULT_LAYER_NAME, 1)]
+        );
+    }
+
+    /// An SVG with a shown layer, and layers hidden in the ways that Inkscape and other editors hide them.
+    const HIDDEN_LAYERS_SVG: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" width="100mm" height="100mm" viewBox="0 0 100 100">
+        <g id="layer1" inkscape:groupmode="layer" inkscape:label="cut">
+            <path d="M 10 10 L 90 10" stroke="#ff0000"/>
+        </g>
+        <g id="layer2" inkscape:groupmode="layer" inkscape:label="construction" style="display:none">
+            <path d="M 10 20 L 90 20" stroke="#0000ff"/>
+        </g>
+        <g id="layer3" visibility="hidden">
+            <path d="M 10 30 L 90 30" stroke="#00ff00"/>
+        </g>
+    </svg>"##;
+
+    #[test]
+    fn hidden_layers_are_not_cut() {
+        let tree = parse_svg(&PathBuf::from("hidden.svg"), HIDDEN_LAYERS_SVG.as_bytes())
+            .expect("SVG should parse");
+
+        let paths = get_paths_grouped_by_colour(&tree).expect("Paths should be grouped");
+        let colours: Vec<&PathColour> = paths.keys().collect();
+        assert_eq!(colours, vec![&Colour([255, 0, 0])]);
+
+        let layers = layer_visibility(HIDDEN_LAYERS_SVG.as_bytes());
+        let summary: Vec<(&str, bool)> = layers
+            .iter()
+            .map(|layer| (layer.name(), layer.visible))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("cut", true), ("construction", false), ("layer3", false)]
+        );
+    }
+
+    #[test]
+    fn paths_grouped_by_layer_name_are_assigned_to_passes() {
+        let tree = parse_svg(&PathBuf::from("layers.svg"), LAYERS_SVG.as_bytes())
+            .expect("SVG should parse");
+
+        let grouped = group_paths_by(&tree, LAYERS_SVG.as_bytes(), GroupingKey::LayerName);
+        let mut summary: Vec<(GroupKey, usize)> = grouped
+            .iter()
+            .map(|(key, paths)| (key.clone(), paths.len()))
+            .collect();
+        summary.sort_by_key(|(key, _)| format!("{key:?}"));
+        assert_eq!(
+            summary,
+            vec![
+                (GroupKey::Name(DEFAULT_LAYER_NAME.to_string()), 1),
+                (GroupKey::Name("cut".to_string()), 2),
+                (GroupKey::Name("engrave".to_string()), 1),
+            ]
+        );
+
+        let mut cut = ToolPass::new("Cut".to_string(), 0, 0, 255, 100, 20, false, 400);
+        cut.set_group(Some("cut".to_string()));
+        let mut engrave = ToolPass::new("Engrave".to_string(), 0, 255, 0, 30, 100, false, 400);
+        engrave.set_group(Some("engrave".to_string()));
+        let assigned = assign_groups_to_passes(grouped, &[cut, engrave]);
+
+        assert_eq!(assigned.get(&Colour([0, 0, 255])).map(Vec::len), Some(2));
+        assert_eq!(assigned.get(&Colour([0, 255, 0])).map(Vec::len), Some(1));
+        assert_eq!(
+            assigned.len(),
+            2,
+            "Paths outside of a matched layer should not be cut"
+        );
+    }
+
+    #[test]
+    fn paths_grouped_by_css_class() {
+        let svg = r##"<svg xmlns="http://www.w3.org/2000/svg" width="100mm" height="100mm" viewBox="0 0 100 100">
+            <g class="cut outline">
+                <path id="a" d="M 10 10 L 90 10" stroke="#000000"/>
+                <path id="b" class="score" d="M 10 20 L 90 20" stroke="#000000"/>
+            </g>
+            <path id="c" d="M 10 30 L 90 30" stroke="#000000"/>
+        </svg>"##;
+        let tree =
+            parse_svg(&PathBuf::from("classes.svg"), svg.as_bytes()).expect("SVG should parse");
+
+        let grouped = group_paths_by(&tree, svg.as_bytes(), GroupingKey::CssClass);
+        let ids = |class: &str| {
+            grouped
+                .get(&GroupKey::Name(class.to_string()))
+                .map(|paths| {
+                    paths
+                        .iter()
+                        .map(|path| path.id().to_string())
+                        .collect::<Vec<_>>()
+                })
+        };
+        assert_eq!(ids("cut"), Some(vec!["a".to_string()]));
+        assert_eq!(ids("score"), Some(vec!["b".to_string()]));
+        assert_eq!(
+            grouped.len(),
+            2,
+            "Paths without a class should not be grouped"
+        );
+    }
+
+    #[test]
+    fn paths_grouped_by_stroke_width() {
+        let svg = r##"<svg xmlns="http://www.w3.org/2000/svg" width="100mm" height="100mm" viewBox="0 0 100 100">
+            <path d="M 10 10 L 90 10" stroke="#000000" stroke-width="0.1"/>
+            <path d="M 10 20 L 90 20" stroke="#000000" stroke-width="0.5"/>
+            <g transform="scale(2)">
+                <path d="M 5 15 L 45 15" stroke="#000000" stroke-width="0.05"/>
+            </g>
+            <path d="M 10 40 L 90 40" fill="#000000"/>
+        </svg>"##;
+        let tree =
+            parse_svg(&PathBuf::from("widths.svg"), svg.as_bytes()).expect("SVG should parse");
+
+        let grouped = group_paths_by(&tree, svg.as_bytes(), GroupingKey::StrokeWidth);
+        let count = |width_mm: f32| {
+            grouped
+                .get(&GroupKey::StrokeWidth(StrokeWidth::from_mm(width_mm)))
+                .map(Vec::len)
+        };
+        // The stroke in the scaled group is scaled up along with its path.
+        assert_eq!(count(0.1), Some(2));
+        assert_eq!(count(0.5), Some(1));
+        assert_eq!(
+            grouped.len(),
+            2,
+            "Paths without a stroke should not be grouped"
+        );
+
+        let mut cut = ToolPass::new("Cut".to_string(), 0, 0, 255, 100, 20, false, 400);
+        cut.set_stroke_width(Some(StrokeWidth::from_mm(0.1)));
+        let mut engrave = ToolPass::new("Engrave".to_string(), 0, 255, 0, 30, 100, false, 400);
+        engrave.set_stroke_width(Some(StrokeWidth::from_mm(0.5)));
+        let assigned = assign_groups_to_passes(grouped, &[cut, engrave]);
+
+        assert_eq!(assigned.get(&Colour([0, 0, 255])).map(Vec::len), Some(2));
+        assert_eq!(assigned.get(&Colour([0, 255, 0])).map(Vec::len), Some(1));
+    }
+
+    #[test]
+    fn stroke_widths_out_of_range_are_clamped() {
+        assert_eq!(StrokeWidth::from_mm(-1.0), StrokeWidth::from_mm(0.0));
+        assert_eq!(StrokeWidth::from_mm(f32::NAN), StrokeWidth::from_mm(0.0));
+        assert_eq!(
+            StrokeWidth::from_mm(f32::INFINITY),
+            StrokeWidth::from_mm(f32::MAX)
+        );
+        assert_eq!(StrokeWidth::from_mm(0.123).mm(), 0.12);
+    }
+}
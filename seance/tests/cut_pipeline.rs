@@ -0,0 +1,178 @@
+//! Runs a design through the whole of `cut_file`, from the SVG to the bytes written to the device,
+//! to catch mistakes in how the stages are wired together.
+
+use std::{
+    io::Read,
+    net::{Ipv4Addr, TcpListener},
+    thread,
+};
+
+use seance::{
+    cut_file, default_passes::default_passes, generate_pcl, DesignFile, HatchOptions, JobOptions,
+    Machine, PrintDevice, SendToDeviceError,
+};
+
+/// The Universal Exit Language command that starts and ends the job.
+const UEL: &str = "\x1b%-12345X";
+/// The PCL reset command.
+const RESET: &str = "\x1bE";
+/// The HPGL commands that finish the job.
+const HPGL_END: &str = "EC0;EC1;OE;";
+
+/// Loads the hackspace logo as a design.
+fn logo() -> DesignFile {
+    let logo = include_bytes!("../../logo.svg");
+    DesignFile::from_bytes("logo".to_string(), logo, Machine::GCC_SPIRIT.bed())
+        .expect("Logo should load")
+}
+
+/// The options that the logo is cut with. The logo is made of filled shapes, so they are hatched to get some paths.
+fn logo_options() -> JobOptions {
+    JobOptions {
+        hatch: Some(HatchOptions::default()),
+        ..JobOptions::default()
+    }
+}
+
+/// Generates the job for a design up front, to compare with what was sent to a device.
+///
+/// # Arguments
+/// * `design_file`: The design to generate the job for.
+///
+/// # Returns
+/// The PCL bytes for the job.
+fn expected_job(design_file: &DesignFile) -> Vec<u8> {
+    generate_pcl(
+        design_file,
+        &default_passes(),
+        &Machine::GCC_SPIRIT,
+        &logo_options(),
+    )
+    .expect("Logo should generate")
+}
+
+// Print devices are USB ports on Windows, so this only runs where they can be files.
+#[cfg(not(target_os = "windows"))]
+#[test]
+fn logo_is_cut_to_a_file() {
+    use std::{fs, process};
+
+    let design_file = logo();
+    let passes = default_passes();
+
+    // Print devices are opened rather than created, so the file has to exist first.
+    let path = std::env::temp_dir().join(format!("seance-cut-pipeline-{}.pcl", process::id()));
+    fs::write(&path, b"").expect("Temporary file should be created");
+    let print_device = PrintDevice::Path {
+        path: path.to_string_lossy().into_owned(),
+        retry: Default::default(),
+    };
+
+    let result = cut_file(
+        &design_file,
+        &passes,
+        &print_device,
+        &Machine::GCC_SPIRIT,
+        &logo_options(),
+    );
+    let written = fs::read(&path).expect("Temporary file should be read");
+    let _ = fs::remove_file(&path);
+    result.expect("Logo should be cut");
+
+    let pcl = String::from_utf8_lossy(&written);
+    let start = format!("{UEL}{RESET}");
+    assert_eq!(
+        &pcl[..start.len()],
+        start,
+        "Job should start by leaving PJL and resetting"
+    );
+    assert_eq!(
+        pcl.matches(&format!("\x1b!v{}R", passes.len())).count(),
+        1,
+        "Job should set a pen for each pass once"
+    );
+
+    let hpgl_start = pcl
+        .find("\x1b%1BIN;")
+        .expect("Job should switch to HPGL and initialise it");
+    let hpgl_end = pcl
+        .rfind(&format!("\x1b%1A{RESET}{UEL}"))
+        .expect("Job should switch back to PCL, reset and return to PJL");
+    assert_eq!(
+        hpgl_end + "\x1b%1A".len() + RESET.len() + UEL.len(),
+        pcl.len(),
+        "Nothing should follow the end of the job"
+    );
+    let hpgl = &pcl[hpgl_start..hpgl_end];
+    assert_ne!(hpgl.matches("PD").count(), 0, "HPGL should cut something");
+    assert_eq!(
+        &hpgl[hpgl.len() - HPGL_END.len()..],
+        HPGL_END,
+        "HPGL should finish the job"
+    );
+
+    // Streaming the job to the device writes exactly what generating it up front does.
+    assert_eq!(written, expected_job(&design_file));
+}
+
+#[test]
+fn logo_is_cut_over_the_network() {
+    let design_file = logo();
+    let listener =
+        TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).expect("Listener should bind to a free port");
+    let print_device = PrintDevice::Tcp {
+        host: Ipv4Addr::LOCALHOST.to_string(),
+        port: listener
+            .local_addr()
+            .expect("Listener should have an address")
+            .port(),
+        retry: Default::default(),
+    };
+
+    // Checking the device connects to it, so accept that connection before the job's.
+    let received = thread::spawn(move || {
+        let _ = listener.accept().expect("Device should be checked");
+        let (mut stream, _) = listener.accept().expect("Job should be sent");
+        let mut received = vec![];
+        stream
+            .read_to_end(&mut received)
+            .expect("Job should be read");
+        received
+    });
+    assert_eq!(
+        print_device.is_valid(),
+        true,
+        "Listening device should be valid"
+    );
+
+    cut_file(
+        &design_file,
+        &default_passes(),
+        &print_device,
+        &Machine::GCC_SPIRIT,
+        &logo_options(),
+    )
+    .expect("Logo should be sent");
+    let received = received.join().expect("Device should receive the job");
+    assert_eq!(received, expected_job(&design_file));
+
+    // Nothing is listening now, so the device is no longer valid and jobs can't be sent to it.
+    assert_eq!(
+        print_device.is_valid(),
+        false,
+        "Closed device should be invalid"
+    );
+    let err = cut_file(
+        &design_file,
+        &default_passes(),
+        &print_device,
+        &Machine::GCC_SPIRIT,
+        &logo_options(),
+    )
+    .expect_err("Closed device should fail");
+    assert_eq!(
+        matches!(err, SendToDeviceError::FailedToWriteToPrinter(_)),
+        true,
+        "Failing to connect should be a failed write, got {err:?}"
+    );
+}